@@ -0,0 +1,37 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::output::write_atomic;
+use crate::png;
+
+/// PNG text keyword for alt text, the one image viewers and CMSes read.
+const KEYWORD: &str = "Description";
+
+/// Where the alt text of `image` is kept: `<image>.alt.txt`.
+pub fn sidecar(image: &Path) -> PathBuf {
+    let mut name = image.file_name().unwrap_or_default().to_os_string();
+    name.push(".alt.txt");
+    image.with_file_name(name)
+}
+
+/// The alt text stored for `image`, if any.
+pub fn load(image: &Path) -> Option<String> {
+    fs::read_to_string(sidecar(image)).ok()
+}
+
+/// Writes alt text next to `image` and, for PNGs, into the image itself.
+///
+/// Signed images are left alone, as changing them would break the
+/// signature of their Content Credentials.
+pub fn store(image: &Path, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    write_atomic(&sidecar(image), text.as_bytes())?;
+    let bytes = fs::read(image)?;
+    let Ok(chunks) = png::chunks(&bytes) else {
+        return Ok(());
+    };
+    if chunks.iter().any(|chunk| &chunk.kind == b"caBX") {
+        return Ok(());
+    }
+    write_atomic(image, &png::add_text(&bytes, KEYWORD, text)?)?;
+    Ok(())
+}
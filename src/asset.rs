@@ -0,0 +1,39 @@
+use clap::ValueEnum;
+
+use crate::png;
+
+/// Generated assets other than images, for providers with models that make
+/// them.
+#[derive(ValueEnum, Clone, Copy)]
+pub enum Kind {
+    /// Grayscale PNG, nearer is brighter.
+    Depth,
+    /// 3D model as binary glTF.
+    Mesh,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Depth => "depth",
+            Kind::Mesh => "mesh",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Kind::Depth => "png",
+            Kind::Mesh => "glb",
+        }
+    }
+
+    /// Checks that `bytes` hold this kind of asset, so a truncated or
+    /// mislabeled response is not saved.
+    pub fn check(self, bytes: &[u8]) -> Result<(), String> {
+        match self {
+            Kind::Depth => png::verify(bytes),
+            Kind::Mesh if bytes.starts_with(b"glTF") => Ok(()),
+            Kind::Mesh => Err("Not a binary glTF file".into()),
+        }
+    }
+}
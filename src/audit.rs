@@ -0,0 +1,162 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::config::AuditConfig;
+use crate::request::ImageRequest;
+use crate::size::Size;
+
+/// One generation request, as a line of the audit log.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    /// RFC 3339 with the local offset.
+    time: String,
+    user: String,
+    provider: String,
+    /// What was generated if not images, e.g. `video` or `depth`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kind: Option<String>,
+    prompt: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    quality: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    resolution: Option<String>,
+    count: u8,
+    files: Vec<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// An append-only JSON Lines file recording who generated what and when.
+pub struct AuditLog {
+    file: File,
+    user: String,
+}
+
+impl AuditLog {
+    /// Opens the log configured under `[audit]`, or `None` when auditing is
+    /// off. Failing to open it fails the run, so nothing goes unrecorded.
+    pub fn open(config: &AuditConfig) -> Result<Option<AuditLog>, Box<dyn std::error::Error>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        let path = path(config)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("Could not open the audit log {}: {e}", path.display()))?;
+        let user = config
+            .user
+            .clone()
+            .or_else(|| std::env::var("USER").ok())
+            .or_else(|| std::env::var("USERNAME").ok())
+            .unwrap_or_else(|| "unknown".into());
+        Ok(Some(AuditLog { file, user }))
+    }
+
+    /// Appends an entry for `req` and the files it produced or its error.
+    pub fn record(
+        &self,
+        provider: &str,
+        req: &ImageRequest,
+        result: Result<&[PathBuf], String>,
+    ) -> std::io::Result<()> {
+        let (files, error) = outcome(result);
+        self.append(Entry {
+            time: Local::now().to_rfc3339(),
+            user: self.user.clone(),
+            provider: provider.to_string(),
+            kind: None,
+            prompt: req.prompt.clone(),
+            quality: Some(req.quality.to_string()),
+            resolution: Some(req.resolution.to_string()),
+            count: req.count,
+            files,
+            error,
+        })
+    }
+
+    /// Appends an entry for a video or other asset, named by `kind`.
+    pub fn record_asset(
+        &self,
+        provider: &str,
+        kind: &str,
+        prompt: &str,
+        resolution: Option<Size>,
+        result: Result<&[PathBuf], String>,
+    ) -> std::io::Result<()> {
+        let (files, error) = outcome(result);
+        self.append(Entry {
+            time: Local::now().to_rfc3339(),
+            user: self.user.clone(),
+            provider: provider.to_string(),
+            kind: Some(kind.to_string()),
+            prompt: prompt.to_string(),
+            quality: None,
+            resolution: resolution.map(|size| size.to_string()),
+            count: 1,
+            files,
+            error,
+        })
+    }
+
+    fn append(&self, entry: Entry) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+
+        // Other imgmc processes may append at the same time
+        self.file.lock()?;
+        let result = (&self.file).write_all(&line);
+        self.file.unlock()?;
+        result
+    }
+}
+
+/// Writes the entries from `since` up to and including `until` to `out`.
+pub fn export(
+    config: &AuditConfig,
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+    mut out: impl Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let path = path(config)?;
+    let file = File::open(&path)
+        .map_err(|e| format!("Could not open the audit log {}: {e}", path.display()))?;
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let Ok(entry) = serde_json::from_str::<Entry>(&line) else {
+            continue;
+        };
+        let Ok(time) = DateTime::parse_from_rfc3339(&entry.time) else {
+            continue;
+        };
+        let date = time.date_naive();
+        if since.is_some_and(|since| date < since) || until.is_some_and(|until| date > until) {
+            continue;
+        }
+        writeln!(out, "{line}")?;
+    }
+    Ok(())
+}
+
+fn path(config: &AuditConfig) -> std::io::Result<PathBuf> {
+    match &config.path {
+        Some(path) => Ok(path.clone()),
+        None => xdg::BaseDirectories::with_prefix("imgmc").place_data_file("audit.jsonl"),
+    }
+}
+
+fn outcome(result: Result<&[PathBuf], String>) -> (Vec<PathBuf>, Option<String>) {
+    match result {
+        Ok(files) => (files.iter().map(|file| absolute(file)).collect(), None),
+        Err(e) => (Vec::new(), Some(e)),
+    }
+}
+
+fn absolute(file: &Path) -> PathBuf {
+    std::path::absolute(file).unwrap_or_else(|_| file.to_path_buf())
+}
@@ -0,0 +1,1162 @@
+use std::fs;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64_STD;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use ureq::unversioned::multipart::Form;
+
+use crate::cassette::Cassette;
+use crate::config::{AzureConfig, ConfigError, PolicyConfig, PromptConfig};
+use crate::debug_log::{DebugLog, Exchange, Reply};
+use crate::i18n::t;
+use crate::pii;
+use crate::provenance;
+use crate::request::{Background, ImageQuality, ImageRequest};
+use crate::run_log;
+use crate::script::Hooks;
+use crate::size::{AspectRatio, Size};
+use crate::video::VideoRequest;
+
+pub const NAME: &str = "azure";
+
+const API_VERSION: &str = "2025-04-01-preview";
+const CHAT_API_VERSION: &str = "2024-10-21";
+/// The deployments listing only exists in older data plane API versions.
+const DEPLOYMENTS_API_VERSION: &str = "2022-12-01";
+/// Sora is only served by the v1 preview API.
+const VIDEO_API_VERSION: &str = "preview";
+
+/// Request body fields the system `[policy]` governs, which extra params may
+/// not replace, or they would get around it.
+const POLICY_PARAMS: [&str; 5] = ["prompt", "n", "quality", "size", "model"];
+
+/// How often a video job is asked whether it is done.
+const VIDEO_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+const SORA_SIZES: [Size; 9] = [
+    Size::new(480, 480),
+    Size::new(854, 480),
+    Size::new(480, 854),
+    Size::new(720, 720),
+    Size::new(1280, 720),
+    Size::new(720, 1280),
+    Size::new(1080, 1080),
+    Size::new(1920, 1080),
+    Size::new(1080, 1920),
+];
+
+const DESCRIBE_INSTRUCTIONS: &str = "Describe the image as a prompt for an image \
+generation model. Cover subject, composition, style, medium, lighting and colors in one \
+detailed paragraph. Reply with the prompt only.";
+
+const ALT_TEXT_INSTRUCTIONS: &str = "Write alt text for the image for people using a \
+screen reader: what it shows and what matters about it, in one or two plain sentences of \
+at most 150 characters. Do not start with \"Image of\". Reply with the alt text only.";
+
+const READ_TEXT_INSTRUCTIONS: &str = "Transcribe all text visible in the image exactly as \
+written, including spelling mistakes, one line per line of text. Reply with the text only, \
+or nothing if there is none.";
+
+const JUDGE_INSTRUCTIONS: &str = "Rate the technical quality of the image from 0 to 100: \
+sharpness, detail, coherent composition, and no artifacts, distorted anatomy or garbled \
+areas. Reply with the number only.";
+
+const GPT_IMAGE_SIZES: [Size; 3] = [
+    Size::new(1024, 1024),
+    Size::new(1024, 1536),
+    Size::new(1536, 1024),
+];
+
+const DALL_E_3_SIZES: [Size; 3] = [
+    Size::new(1024, 1024),
+    Size::new(1792, 1024),
+    Size::new(1024, 1792),
+];
+
+const DALL_E_2_SIZES: [Size; 3] = [
+    Size::new(256, 256),
+    Size::new(512, 512),
+    Size::new(1024, 1024),
+];
+
+/// Added to the prompt for tileable textures, as the API has no parameter for it.
+const TILEABLE_HINT: &str = "Seamless tileable texture: the left edge continues into the right \
+edge and the top edge into the bottom edge, with no border or vignette.";
+
+/// What a model accepts, checked before a request is sent so unsupported
+/// options fail with a clear message instead of an HTTP 400.
+struct Capabilities {
+    sizes: &'static [Size],
+    /// Most images a single request may ask for.
+    max_images: u8,
+    max_prompt_chars: usize,
+    /// Whether a reference image can be edited.
+    edits: bool,
+    /// Whether transparent and opaque backgrounds can be requested.
+    background: bool,
+    moderation: bool,
+}
+
+/// The image model behind a deployment, which decides the accepted parameters.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Model {
+    #[serde(rename = "gpt-image-1")]
+    GptImage1,
+    #[serde(rename = "dall-e-3")]
+    DallE3,
+    #[serde(rename = "dall-e-2")]
+    DallE2,
+}
+
+impl Model {
+    /// Recognizes the image models among the names Azure reports for deployments.
+    fn from_name(name: &str) -> Option<Model> {
+        if name.starts_with("gpt-image") {
+            Some(Model::GptImage1)
+        } else if name.starts_with("dall-e-3") {
+            Some(Model::DallE3)
+        } else if name.starts_with("dall-e-2") {
+            Some(Model::DallE2)
+        } else {
+            None
+        }
+    }
+
+    /// Guesses the model from a deployment name, assuming gpt-image-1.
+    fn from_deployment(deployment: &str) -> Model {
+        let name = deployment.to_lowercase().replace(['-', '_', '.'], "");
+        if name.contains("dalle3") {
+            Model::DallE3
+        } else if name.contains("dalle2") {
+            Model::DallE2
+        } else {
+            Model::GptImage1
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Model::GptImage1 => "gpt-image-1",
+            Model::DallE3 => "dall-e-3",
+            Model::DallE2 => "dall-e-2",
+        }
+    }
+
+    fn capabilities(self) -> Capabilities {
+        match self {
+            Model::GptImage1 => Capabilities {
+                sizes: &GPT_IMAGE_SIZES,
+                max_images: 10,
+                max_prompt_chars: 32_000,
+                edits: true,
+                background: true,
+                moderation: true,
+            },
+            Model::DallE3 => Capabilities {
+                sizes: &DALL_E_3_SIZES,
+                max_images: 1,
+                max_prompt_chars: 4_000,
+                edits: false,
+                background: false,
+                moderation: false,
+            },
+            Model::DallE2 => Capabilities {
+                sizes: &DALL_E_2_SIZES,
+                max_images: 10,
+                max_prompt_chars: 1_000,
+                edits: true,
+                background: false,
+                moderation: false,
+            },
+        }
+    }
+
+    /// Published price in USD for one image, `None` when it depends on
+    /// choices left to the service, like `auto` quality.
+    fn list_price(self, size: Size, quality: &ImageQuality) -> Option<f64> {
+        let square = size.width == size.height;
+        match (self, quality) {
+            (Model::GptImage1, ImageQuality::Auto) => None,
+            (Model::GptImage1, ImageQuality::Low) => Some(if square { 0.011 } else { 0.016 }),
+            (Model::GptImage1, ImageQuality::Medium) => Some(if square { 0.042 } else { 0.063 }),
+            (Model::GptImage1, ImageQuality::High) => Some(if square { 0.167 } else { 0.25 }),
+            (Model::DallE3, ImageQuality::High) => Some(if square { 0.08 } else { 0.12 }),
+            (Model::DallE3, _) => Some(if square { 0.04 } else { 0.08 }),
+            (Model::DallE2, _) => Some(match size.width {
+                256 => 0.016,
+                512 => 0.018,
+                _ => 0.02,
+            }),
+        }
+    }
+
+    /// Translates a quality into the model's vocabulary, `None` meaning the
+    /// parameter is not sent at all.
+    fn quality(self, quality: &ImageQuality) -> Option<&'static str> {
+        match (self, quality) {
+            (Model::GptImage1, quality) => Some(match quality {
+                ImageQuality::High => "high",
+                ImageQuality::Medium => "medium",
+                ImageQuality::Low => "low",
+                ImageQuality::Auto => "auto",
+            }),
+            (Model::DallE3, ImageQuality::High) => Some("hd"),
+            (Model::DallE3, _) => Some("standard"),
+            (Model::DallE2, _) => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ImageData {
+    #[serde(rename = "b64_json")]
+    pub b64_json: String,
+    /// Azure's moderation verdict on the prompt, per category.
+    #[serde(default)]
+    pub prompt_filter_results: serde_json::Value,
+    /// Azure's moderation verdict on this image, per category.
+    #[serde(default)]
+    pub content_filter_results: serde_json::Value,
+}
+
+impl ImageData {
+    pub fn from_png(bytes: &[u8]) -> ImageData {
+        ImageData {
+            b64_json: BASE64_STD.encode(bytes),
+            prompt_filter_results: serde_json::Value::Null,
+            content_filter_results: serde_json::Value::Null,
+        }
+    }
+
+    /// Categories the content filter flagged without blocking the request,
+    /// e.g. `image: violence (low)`, which can explain altered results.
+    pub fn filter_annotations(&self) -> Vec<String> {
+        let flagged = |r: &serde_json::Value| {
+            r["filtered"].as_bool() == Some(true)
+                || r["detected"].as_bool() == Some(true)
+                || r["severity"]
+                    .as_str()
+                    .is_some_and(|severity| severity != "safe")
+        };
+        let prompt = filter_categories(&self.prompt_filter_results, flagged)
+            .into_iter()
+            .map(|category| format!("prompt: {category}"));
+        let image = filter_categories(&self.content_filter_results, flagged)
+            .into_iter()
+            .map(|category| format!("image: {category}"));
+        prompt.chain(image).collect()
+    }
+
+    /// Provenance data the image carries, such as the Content Credentials
+    /// Azure embeds to mark images as AI-generated.
+    pub fn provenance(&self) -> Vec<String> {
+        BASE64_STD
+            .decode(&self.b64_json)
+            .map(|bytes| provenance::inspect(&bytes))
+            .unwrap_or_default()
+    }
+}
+
+/// Parses `--header "Name: value"`.
+pub fn parse_header(s: &str) -> Result<(String, String), String> {
+    match s.split_once(':') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(format!("Expected Name: value, got {s}")),
+    }
+}
+
+/// Parses `--param name=value`. Values that are valid JSON, such as numbers,
+/// `true` or `{"a": 1}`, are sent as such, anything else as a string.
+pub fn parse_param(s: &str) -> Result<(String, serde_json::Value), String> {
+    let (name, value) = crate::request::parse_var(s)?;
+    if POLICY_PARAMS.contains(&name.as_str()) {
+        return Err(reserved_param(&name));
+    }
+    let value = serde_json::from_str(&value).unwrap_or(serde_json::Value::String(value));
+    Ok((name, value))
+}
+
+fn reserved_param(name: &str) -> String {
+    format!("{name} cannot be set as an extra param, use imgmc's own option for it")
+}
+
+/// Categories in a `content_filter_results` object that `flagged` picks out,
+/// formatted like `violence (high)`.
+fn filter_categories(
+    results: &serde_json::Value,
+    flagged: impl Fn(&serde_json::Value) -> bool,
+) -> Vec<String> {
+    results
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(_, result)| flagged(result))
+        .map(|(category, result)| match result["severity"].as_str() {
+            Some(severity) => format!("{category} ({severity})"),
+            None => category.clone(),
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+pub struct GenerationResponse {
+    pub data: Vec<ImageData>,
+}
+
+#[derive(Deserialize)]
+pub struct Deployment {
+    pub id: String,
+    pub model: String,
+}
+
+#[derive(Deserialize)]
+struct DeploymentList {
+    data: Vec<Deployment>,
+}
+
+#[derive(Deserialize)]
+struct VideoJob {
+    id: String,
+    /// `queued`, `preprocessing`, `running`, `processing`, `succeeded`,
+    /// `failed` or `cancelled`.
+    status: String,
+    #[serde(default)]
+    generations: Vec<VideoGeneration>,
+    failure_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct VideoGeneration {
+    id: String,
+}
+
+/// A request that has been sent, for the debug log, the cassette and
+/// `--verbose`.
+struct Call<'a> {
+    method: &'a str,
+    url: &'a str,
+    content_type: Option<&'a str>,
+    /// The JSON body, or its fields for multipart requests.
+    body: &'a serde_json::Value,
+    /// Bytes sent, roughly for multipart requests.
+    sent: u64,
+    started: Instant,
+    first_byte: Duration,
+    /// Whether the exchange goes into the cassette, which only replays image
+    /// requests.
+    record: bool,
+}
+
+impl<'a> Call<'a> {
+    /// A request just answered by the server, started at `started`.
+    fn new(method: &'a str, url: &'a str, body: &'a serde_json::Value, started: Instant) -> Self {
+        Call {
+            method,
+            url,
+            content_type: None,
+            body,
+            sent: 0,
+            started,
+            first_byte: started.elapsed(),
+            record: false,
+        }
+    }
+}
+
+pub struct Client {
+    pub debug_log: Option<DebugLog>,
+    /// Records image requests with their responses, or answers them from an
+    /// earlier recording without sending anything.
+    pub cassette: Option<Arc<Cassette>>,
+    /// Shared by all requests so batches reuse pooled keep-alive connections.
+    /// It asks for gzip or brotli responses, which shrink base64 image data
+    /// considerably. ureq only speaks HTTP/1.1.
+    agent: ureq::Agent,
+    /// Prefix and suffix added to every prompt on its way to the API.
+    pub affixes: PromptConfig,
+    /// Print transfer sizes and timings for every request.
+    pub verbose: bool,
+    /// User script that may change requests before they are checked.
+    pub script: Option<Hooks>,
+    pub policy: PolicyConfig,
+    /// Mask personal data in every prompt, see [`pii::scrub`].
+    pub scrub_pii: bool,
+    /// Sent with every request, e.g. tags a gateway needs for billing.
+    pub headers: Vec<(String, String)>,
+    /// Fields added to every image request body, replacing those imgmc sets.
+    pub extra_params: serde_json::Map<String, serde_json::Value>,
+    model: Model,
+    api_key: String,
+    gen_url: String,
+    edits_url: String,
+    deployments_url: String,
+    /// Chat completions endpoint of the vision deployment, if one is configured.
+    describe_url: Option<String>,
+    /// Video generations endpoint and the Sora deployment, if one is configured.
+    video: Option<(String, String)>,
+}
+
+impl Client {
+    pub fn new(config: &AzureConfig) -> Client {
+        let gen_url = format!(
+            "{}/openai/deployments/{}/images/generations?api-version={}",
+            config.api_base, config.deployment, API_VERSION
+        );
+
+        let edits_url = format!(
+            "{}/openai/deployments/{}/images/edits?api-version={}",
+            config.api_base, config.deployment, API_VERSION
+        );
+
+        let deployments_url = format!(
+            "{}/openai/deployments?api-version={}",
+            config.api_base, DEPLOYMENTS_API_VERSION
+        );
+
+        let describe_url = config.vision_deployment.as_ref().map(|deployment| {
+            format!(
+                "{}/openai/deployments/{deployment}/chat/completions?api-version={}",
+                config.api_base, CHAT_API_VERSION
+            )
+        });
+
+        let video = config.video_deployment.as_ref().map(|deployment| {
+            let url = format!("{}/openai/v1/video/generations", config.api_base);
+            (url, deployment.clone())
+        });
+
+        // Error statuses are turned into an ApiError from the response body instead
+        let agent = ureq::Agent::new_with_config(
+            ureq::Agent::config_builder()
+                .http_status_as_error(false)
+                .build(),
+        );
+
+        Client {
+            debug_log: None,
+            cassette: None,
+            agent,
+            affixes: PromptConfig::default(),
+            verbose: false,
+            script: None,
+            policy: PolicyConfig::default(),
+            scrub_pii: false,
+            headers: Vec::new(),
+            extra_params: config.extra_params.clone(),
+            model: config
+                .model
+                .unwrap_or_else(|| Model::from_deployment(&config.deployment)),
+            api_key: config.api_key.clone(),
+            gen_url,
+            edits_url,
+            deployments_url,
+            describe_url,
+            video,
+        }
+    }
+
+    /// Adds the API key and any configured headers to a request.
+    fn headers<B>(&self, mut builder: ureq::RequestBuilder<B>) -> ureq::RequestBuilder<B> {
+        for (name, value) in self.header_values() {
+            builder = builder.header(name, value);
+        }
+        builder
+    }
+
+    /// The headers [`Client::headers`] adds, for the debug log.
+    fn header_values(&self) -> Vec<(&str, &str)> {
+        self.headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .chain([("api-key", self.api_key.as_str())])
+            .collect()
+    }
+
+    /// Lists the resource's deployments that serve an image model.
+    pub fn image_deployments(&self) -> Result<Vec<Deployment>, Box<dyn std::error::Error>> {
+        let started = Instant::now();
+        let result = self.headers(self.agent.get(&self.deployments_url)).call();
+        let call = Call::new(
+            "GET",
+            &self.deployments_url,
+            &serde_json::Value::Null,
+            started,
+        );
+        let list: DeploymentList = self.read_json(&call, self.checked(&call, result)?)?;
+        Ok(list
+            .data
+            .into_iter()
+            .filter(|d| Model::from_name(&d.model).is_some())
+            .collect())
+    }
+
+    /// Asks the vision deployment for a prompt that would recreate `image`.
+    pub fn describe(&self, image: &Path) -> Result<String, Box<dyn std::error::Error>> {
+        self.vision(
+            &fs::read(image)?,
+            mime_type(image),
+            DESCRIBE_INSTRUCTIONS,
+            1000,
+        )
+    }
+
+    /// Asks the vision deployment for short alt text for `image`.
+    pub fn alt_text(&self, image: &Path) -> Result<String, Box<dyn std::error::Error>> {
+        self.vision(
+            &fs::read(image)?,
+            mime_type(image),
+            ALT_TEXT_INSTRUCTIONS,
+            200,
+        )
+    }
+
+    /// Asks the vision deployment for the text written in a PNG.
+    pub fn read_text(&self, png: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
+        self.vision(png, "image/png", READ_TEXT_INSTRUCTIONS, 500)
+    }
+
+    /// Asks the vision deployment to rate a PNG from 0 to 100.
+    pub fn judge(&self, png: &[u8]) -> Result<u8, Box<dyn std::error::Error>> {
+        let reply = self.vision(png, "image/png", JUDGE_INSTRUCTIONS, 10)?;
+        reply
+            .trim_end_matches('.')
+            .parse::<u8>()
+            .ok()
+            .filter(|&score| score <= 100)
+            .ok_or_else(|| format!("The vision deployment gave no score but: {reply}").into())
+    }
+
+    /// Sends an image to the vision deployment with `instructions` and returns
+    /// its reply.
+    fn vision(
+        &self,
+        image: &[u8],
+        mime: &str,
+        instructions: &str,
+        max_tokens: u32,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let url = self.describe_url.as_deref().ok_or_else(|| {
+            ConfigError("Set vision_deployment under [azure] to describe images".into())
+        })?;
+        let data = BASE64_STD.encode(image);
+
+        let body = serde_json::json!({
+            "messages": [
+                { "role": "system", "content": instructions },
+                {
+                    "role": "user",
+                    "content": [{
+                        "type": "image_url",
+                        "image_url": { "url": format!("data:{mime};base64,{data}") }
+                    }]
+                }
+            ],
+            "max_tokens": max_tokens
+        });
+
+        let started = Instant::now();
+        let result = self.headers(self.agent.post(url)).send_json(&body);
+        let call = Call {
+            content_type: Some("application/json"),
+            sent: body.to_string().len() as u64,
+            ..Call::new("POST", url, &body, started)
+        };
+        let value: serde_json::Value = self.read_json(&call, self.checked(&call, result)?)?;
+        value["choices"][0]["message"]["content"]
+            .as_str()
+            .map(|reply| reply.trim().to_string())
+            .ok_or_else(|| "The vision deployment returned no description".into())
+    }
+
+    /// Starts a Sora job and waits for it, telling `progress` about every
+    /// status it reports on the way. Returns the video as MP4.
+    #[tracing::instrument(
+        skip_all,
+        err,
+        fields(seconds = req.seconds, resolution = %req.resolution)
+    )]
+    pub fn generate_video(
+        &self,
+        req: &VideoRequest,
+        progress: &dyn Fn(&str),
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let (base, deployment) = self.video.as_ref().ok_or_else(|| {
+            ConfigError("Set video_deployment under [azure] to generate videos".into())
+        })?;
+        self.policy.check_prompt(&req.prompt)?;
+        let target = req.resolution;
+        if !SORA_SIZES.contains(&target) {
+            let supported: Vec<String> = SORA_SIZES.iter().map(Size::to_string).collect();
+            let msg = format!(
+                "{target} is not supported (supported: {})",
+                supported.join(", ")
+            );
+            return Err(msg.into());
+        }
+
+        let body = serde_json::json!({
+            "model": deployment,
+            "prompt": self.affixes.wrap(&req.prompt),
+            "width": req.resolution.width,
+            "height": req.resolution.height,
+            "n_seconds": req.seconds,
+            "n_variants": 1,
+        });
+        let url = format!("{base}/jobs?api-version={VIDEO_API_VERSION}");
+        let started = Instant::now();
+        let result = self.headers(self.agent.post(&url)).send_json(&body);
+        let call = Call {
+            content_type: Some("application/json"),
+            sent: body.to_string().len() as u64,
+            ..Call::new("POST", &url, &body, started)
+        };
+        let mut job: VideoJob = self.read_json(&call, self.checked(&call, result)?)?;
+
+        let started = Instant::now();
+        loop {
+            match job.status.as_str() {
+                "succeeded" => break,
+                "failed" | "cancelled" => {
+                    let reason = job.failure_reason.as_deref().unwrap_or("no reason given");
+                    return Err(format!("The video job {}: {reason}", job.status).into());
+                }
+                status => {
+                    let seconds = started.elapsed().as_secs();
+                    progress(&t!("video-status", status = status, seconds = seconds));
+                }
+            }
+            std::thread::sleep(VIDEO_POLL_INTERVAL);
+            let url = format!("{base}/jobs/{}?api-version={VIDEO_API_VERSION}", job.id);
+            let started = Instant::now();
+            let result = self.headers(self.agent.get(&url)).call();
+            let call = Call::new("GET", &url, &serde_json::Value::Null, started);
+            job = self.read_json(&call, self.checked(&call, result)?)?;
+        }
+
+        let generation = job
+            .generations
+            .first()
+            .ok_or("The video job finished without a video")?;
+        let url = format!(
+            "{base}/{}/content/video?api-version={VIDEO_API_VERSION}",
+            generation.id
+        );
+        let started = Instant::now();
+        let result = self.headers(self.agent.get(&url)).call();
+        let call = Call::new("GET", &url, &serde_json::Value::Null, started);
+        let mut resp = self.checked(&call, result)?;
+        let video = resp
+            .body_mut()
+            .with_config()
+            .limit(u64::MAX)
+            .read_to_vec()?;
+        let summary = format!("<{} bytes of video>", video.len());
+        let status = resp.status().as_u16();
+        let request_id = request_id(&resp);
+        let received = video.len() as u64;
+        self.finish(
+            &call,
+            Some(status),
+            request_id.as_deref(),
+            Reply::Text(&summary),
+            received,
+        )?;
+        Ok(video)
+    }
+
+    /// Logs a request that failed or came back with an error status, and
+    /// returns the error. Successful responses are handed back to be read.
+    fn checked(
+        &self,
+        call: &Call<'_>,
+        result: Result<ureq::http::Response<ureq::Body>, ureq::Error>,
+    ) -> Result<ureq::http::Response<ureq::Body>, Box<dyn std::error::Error>> {
+        let mut resp = match result {
+            Ok(resp) => resp,
+            Err(e) => {
+                self.finish(call, None, None, Reply::Text(&e.to_string()), 0)?;
+                return Err(e.into());
+            }
+        };
+        let status = resp.status();
+        if status.is_success() {
+            return Ok(resp);
+        }
+        let request_id = request_id(&resp);
+        let text = resp.body_mut().read_to_string()?;
+        let received = text.len() as u64;
+        self.finish(
+            call,
+            Some(status.as_u16()),
+            request_id.as_deref(),
+            Reply::Text(&text),
+            received,
+        )?;
+        Err(ApiError::from_body(status.as_u16(), &text, request_id).into())
+    }
+
+    /// Reads a successful JSON response as it arrives, so the raw body is
+    /// never held next to the strings taken from it, such as base64 images.
+    fn read_json<T: DeserializeOwned>(
+        &self,
+        call: &Call<'_>,
+        mut resp: ureq::http::Response<ureq::Body>,
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        let status = resp.status().as_u16();
+        let request_id = request_id(&resp);
+        // Several base64 images easily exceed ureq's default limit of 10 MB
+        let mut reader = Counted::new(resp.body_mut().with_config().limit(u64::MAX).reader());
+        let parsed = serde_json::from_reader(BufReader::new(&mut reader));
+        let request_id = request_id.as_deref();
+        let value: serde_json::Value = match parsed {
+            Ok(value) => value,
+            Err(e) => {
+                let reply = Reply::Text(&e.to_string());
+                self.finish(call, Some(status), request_id, reply, reader.bytes)?;
+                return Err(e.into());
+            }
+        };
+        self.finish(
+            call,
+            Some(status),
+            request_id,
+            Reply::Json(&value),
+            reader.bytes,
+        )?;
+        // Moves the strings out of `value` rather than copying them
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Writes a finished exchange to the debug log and, for image requests,
+    /// the cassette, and reports it with `--verbose`.
+    fn finish(
+        &self,
+        call: &Call<'_>,
+        status: Option<u16>,
+        request_id: Option<&str>,
+        reply: Reply<'_>,
+        received: u64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(log) = &self.debug_log {
+            let mut headers = self.header_values();
+            headers.extend(call.content_type.map(|value| ("Content-Type", value)));
+            log.record(Exchange {
+                method: call.method,
+                url: call.url,
+                headers: &headers,
+                request: call.body,
+                status,
+                request_id,
+                started: call.started,
+                response: reply,
+            });
+        }
+        if call.record
+            && let Some(cassette) = &self.cassette
+            && let Some(status) = status
+        {
+            let text = match reply {
+                Reply::Text(text) => text.to_string(),
+                Reply::Json(value) => value.to_string(),
+            };
+            cassette.add(call.url, call.body, status, &text)?;
+        }
+        if status.is_some() {
+            self.report(call, request_id, received);
+        }
+        Ok(())
+    }
+
+    /// Prints how much went over the wire and how long it took with `--verbose`,
+    /// which tells a slow model apart from a slow network, along with the
+    /// request ID to quote to Azure support.
+    fn report(&self, call: &Call<'_>, request_id: Option<&str>, received: u64) {
+        if !self.verbose {
+            return;
+        }
+        let endpoint = call.url.split('?').next().unwrap_or(call.url);
+        let request_id = request_id
+            .map(|id| format!(" (request {id})"))
+            .unwrap_or_default();
+        eprintln!(
+            "{} {endpoint}{request_id}: sent {}, received {}, \
+             first byte after {:.2}s, total {:.2}s",
+            call.method,
+            human_bytes(call.sent),
+            human_bytes(received),
+            call.first_byte.as_secs_f64(),
+            call.started.elapsed().as_secs_f64()
+        );
+    }
+
+    /// Checks `req` against what the model supports before anything is sent.
+    ///
+    /// Prompts over the length limit are cut down instead of rejected when
+    /// `req.truncate_prompt` is set. The `before_request` script hook runs
+    /// first, so what it changes is checked as well, including against the
+    /// system policy. Prompts are scrubbed here with `scrub_pii`, so those
+    /// from every subcommand are.
+    pub fn validate(&self, req: &mut ImageRequest) -> Result<(), String> {
+        if let Some(script) = &self.script {
+            script.before_request(req)?;
+        }
+        if self.scrub_pii {
+            pii::scrub_request(req);
+        }
+        self.policy.check(req)?;
+        let model = self.model.name();
+        let capabilities = self.model.capabilities();
+        if req.reference.is_some() && !capabilities.edits {
+            return Err(format!(
+                "{model} cannot edit images, --reference is not supported"
+            ));
+        }
+        if !matches!(req.background, Background::Auto) && !capabilities.background {
+            return Err(format!(
+                "{model} does not support --background {}",
+                req.background
+            ));
+        }
+        if req.moderation.is_some() && !capabilities.moderation {
+            return Err(format!("{model} does not support --moderation"));
+        }
+        // None of the image APIs takes a seed
+        if req.seed.is_some() {
+            return Err(format!("{model} does not support seeds"));
+        }
+        self.api_size(req)?;
+        self.validate_prompt(req)
+    }
+
+    fn validate_prompt(&self, req: &mut ImageRequest) -> Result<(), String> {
+        let max = self.model.capabilities().max_prompt_chars;
+        let len = self.api_prompt(req).chars().count();
+        if len > max {
+            if !req.truncate_prompt {
+                return Err(t!("prompt-too-long", len = len, max = max));
+            }
+            // Only the user's part is cut, the configured affixes stay intact
+            let affixes = len - req.prompt.chars().count();
+            let keep = max
+                .checked_sub(affixes)
+                .filter(|&keep| keep > 0)
+                .ok_or("The configured prompt prefix and suffix alone exceed the limit")?;
+            req.prompt = req.prompt.chars().take(keep).collect();
+            run_log::warn(t!("prompt-truncated", len = len, max = max));
+        } else if len > max / 10 * 9 {
+            run_log::warn(t!("prompt-near-limit", len = len, max = max));
+        }
+        Ok(())
+    }
+
+    /// The prompt as sent to the API, with the configured affixes and any hints.
+    fn api_prompt(&self, req: &ImageRequest) -> String {
+        let mut prompt = self.affixes.wrap(&req.prompt);
+        if req.tileable {
+            prompt = format!("{prompt}\n{TILEABLE_HINT}");
+        }
+        if let Some(palette) = &req.palette {
+            prompt = format!("{prompt}\n{}", palette.hint());
+        }
+        prompt
+    }
+
+    /// Rough cost of `req` in USD at list prices, which Azure may not match.
+    pub fn estimated_cost(&self, req: &ImageRequest) -> Option<f64> {
+        let size = self.api_size(req).ok()?;
+        let price = self.model.list_price(size, &req.quality)?;
+        Some(price * f64::from(req.count))
+    }
+
+    /// Picks the size to request from the API for `req`.
+    ///
+    /// Unsupported sizes are only accepted with a fit mode, in which case the
+    /// supported size closest in aspect ratio is generated and adjusted locally.
+    pub fn api_size(&self, req: &ImageRequest) -> Result<Size, String> {
+        let target = req.resolution;
+        let sizes = self.model.capabilities().sizes;
+        if sizes.contains(&target) {
+            return Ok(target);
+        }
+        if req.fit.is_none() {
+            let supported: Vec<String> = sizes.iter().map(Size::to_string).collect();
+            return Err(format!(
+                "{target} is not supported (supported: {}); pass --fit crop or --fit pad",
+                supported.join(", ")
+            ));
+        }
+
+        Ok(self.closest_size(AspectRatio {
+            width: target.width,
+            height: target.height,
+        }))
+    }
+
+    /// Returns the supported size whose aspect ratio is closest to `ratio`.
+    pub fn closest_size(&self, ratio: AspectRatio) -> Size {
+        let log_ratio = |width: u32, height: u32| (f64::from(width) / f64::from(height)).ln();
+        let want = log_ratio(ratio.width, ratio.height);
+        let sizes = self.model.capabilities().sizes;
+        sizes
+            .iter()
+            .copied()
+            .min_by(|a, b| {
+                let da = (log_ratio(a.width, a.height) - want).abs();
+                let db = (log_ratio(b.width, b.height) - want).abs();
+                // Prefer the larger size when two are equally close
+                da.total_cmp(&db)
+                    .then((b.width * b.height).cmp(&(a.width * a.height)))
+            })
+            .unwrap_or(sizes[0])
+    }
+
+    /// Generates `req.count` images, split over as many requests as the model needs.
+    #[tracing::instrument(
+        skip_all,
+        err,
+        fields(count = req.count, quality = %req.quality, resolution = %req.resolution)
+    )]
+    pub fn generate(
+        &self,
+        req: &ImageRequest,
+    ) -> Result<GenerationResponse, Box<dyn std::error::Error>> {
+        let max = self.model.capabilities().max_images;
+        let mut data = Vec::with_capacity(req.count.into());
+        let mut remaining = req.count;
+        while remaining > 0 {
+            let n = remaining.min(max);
+            data.extend(self.request(req, n)?.data);
+            remaining -= n;
+        }
+        for (i, item) in data.iter().enumerate() {
+            let annotations = item.filter_annotations();
+            if !annotations.is_empty() {
+                let categories = annotations.join(", ");
+                run_log::warn(t!(
+                    "content-filter-flagged",
+                    index = i + 1,
+                    categories = categories
+                ));
+            }
+        }
+        Ok(GenerationResponse { data })
+    }
+
+    #[tracing::instrument(
+        name = "api_call",
+        skip_all,
+        err,
+        fields(images = n, edit = req.reference.is_some())
+    )]
+    fn request(
+        &self,
+        req: &ImageRequest,
+        n: u8,
+    ) -> Result<GenerationResponse, Box<dyn std::error::Error>> {
+        let size = self.api_size(req)?.to_string();
+        let quality = self.model.quality(&req.quality);
+        let background = req.background.to_string();
+        // Only gpt-image-1 always answers with base64 and knows about backgrounds
+        let gpt_image = self.model == Model::GptImage1;
+
+        let mut body = serde_json::json!({
+            "prompt": self.api_prompt(req),
+            "n": n,
+            "size": size
+        });
+        if let Some(quality) = quality {
+            body["quality"] = quality.into();
+        }
+        if gpt_image {
+            body["background"] = background.into();
+            body["output_format"] = "png".into();
+        } else {
+            body["response_format"] = "b64_json".into();
+        }
+        // Only the generations endpoint takes a moderation level
+        if gpt_image
+            && req.reference.is_none()
+            && let Some(moderation) = &req.moderation
+        {
+            body["moderation"] = moderation.to_string().into();
+        }
+        for (name, value) in &self.extra_params {
+            if POLICY_PARAMS.contains(&name.as_str()) {
+                return Err(ConfigError(reserved_param(name)).into());
+            }
+            body[name] = value.clone();
+        }
+
+        if let Some(cassette) = &self.cassette
+            && cassette.is_replay()
+        {
+            let url = match &req.reference {
+                Some(ref_path) => {
+                    body["image"] = ref_path.display().to_string().into();
+                    &self.edits_url
+                }
+                None => &self.gen_url,
+            };
+            let (status, text) = cassette.find(url, &body)?;
+            if !(200..300).contains(&status) {
+                return Err(ApiError::from_body(status, &text, None).into());
+            }
+            return Ok(serde_json::from_str(&text)?);
+        }
+
+        let started = Instant::now();
+        let (url, content_type, sent, result) = if let Some(ref_path) = req.reference.as_ref() {
+            // Use the edits endpoint with multipart/form-data
+            let fields: Vec<(String, String)> = body
+                .as_object()
+                .into_iter()
+                .flatten()
+                .map(|(name, value)| {
+                    let value = value
+                        .as_str()
+                        .map_or_else(|| value.to_string(), str::to_string);
+                    (name.clone(), value)
+                })
+                .collect();
+            let mut form = Form::new();
+            for (name, value) in &fields {
+                form = form.text(name, value);
+            }
+            let form = form.file("image", ref_path)?;
+            body["image"] = ref_path.display().to_string().into();
+            let sent = fields.iter().map(|(n, v)| n.len() + v.len()).sum::<usize>() as u64
+                + fs::metadata(ref_path).map_or(0, |m| m.len());
+
+            let result = self.headers(self.agent.post(&self.edits_url)).send(form);
+            (&self.edits_url, "multipart/form-data", sent, result)
+        } else {
+            // Use the generations endpoint with JSON
+            let sent = body.to_string().len() as u64;
+            let result = self
+                .headers(self.agent.post(&self.gen_url))
+                .header("Content-Type", "application/json")
+                .send_json(&body);
+            (&self.gen_url, "application/json", sent, result)
+        };
+        let call = Call {
+            content_type: Some(content_type),
+            sent,
+            record: true,
+            ..Call::new("POST", url, &body, started)
+        };
+        self.read_json(&call, self.checked(&call, result)?)
+    }
+}
+
+/// Counts the bytes read through it, for `--verbose`.
+struct Counted<R> {
+    inner: R,
+    bytes: u64,
+}
+
+impl<R> Counted<R> {
+    fn new(inner: R) -> Counted<R> {
+        Counted { inner, bytes: 0 }
+    }
+}
+
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bytes += n as u64;
+        Ok(n)
+    }
+}
+
+/// The MIME type the vision API expects for `image`, going by its extension.
+fn mime_type(image: &Path) -> &'static str {
+    match image.extension().and_then(|e| e.to_str()) {
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("webp") => "image/webp",
+        Some("gif") => "image/gif",
+        _ => "image/png",
+    }
+}
+
+fn human_bytes(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{bytes} B"),
+        1024..1_048_576 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+/// The ID Azure assigned to a request, from whichever header carries it.
+fn request_id(resp: &ureq::http::Response<ureq::Body>) -> Option<String> {
+    ["apim-request-id", "x-request-id", "x-ms-request-id"]
+        .into_iter()
+        .find_map(|name| resp.headers().get(name)?.to_str().ok())
+        .map(str::to_string)
+}
+
+/// An error response from the API, with the details from its JSON payload.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: u16,
+    pub code: Option<String>,
+    pub message: String,
+    /// Content filter categories that triggered, e.g. `violence (high)`.
+    pub filtered: Vec<String>,
+    /// Azure's ID for the request, which support asks for.
+    pub request_id: Option<String>,
+}
+
+impl ApiError {
+    fn from_body(status: u16, body: &str, request_id: Option<String>) -> ApiError {
+        let value: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+        let error = &value["error"];
+
+        let filtered = filter_categories(&error["inner_error"]["content_filter_results"], |r| {
+            r["filtered"].as_bool() == Some(true)
+        });
+
+        ApiError {
+            status,
+            code: error["code"].as_str().map(str::to_string),
+            message: error["message"]
+                .as_str()
+                .map_or_else(|| body.trim().to_string(), str::to_string),
+            filtered,
+            request_id,
+        }
+    }
+
+    /// Whether the prompt or image was refused by content moderation.
+    pub fn is_content_policy(&self) -> bool {
+        matches!(
+            self.code.as_deref(),
+            Some("content_policy_violation" | "moderation_blocked" | "content_filter")
+        )
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "API returned {}", self.status)?;
+        if let Some(code) = &self.code {
+            write!(f, " ({code})")?;
+        }
+        write!(f, ": {}", self.message)?;
+        if !self.filtered.is_empty() {
+            write!(f, " [filtered: {}]", self.filtered.join(", "))?;
+        }
+        if let Some(request_id) = &self.request_id {
+            write!(f, " (request ID: {request_id})")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ApiError {}
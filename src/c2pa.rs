@@ -0,0 +1,82 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use sha2::{Digest, Sha256};
+
+use crate::config::C2paConfig;
+
+/// IPTC's term for media created by a generative model.
+const TRAINED_ALGORITHMIC_MEDIA: &str =
+    "http://cv.iptc.org/newscodes/digitalsourcetype/trainedAlgorithmicMedia";
+
+/// Embeds Content Credentials into the image at `file` with `c2patool`,
+/// stating that it was generated by imgmc with `provider` from a prompt with
+/// the given hash. The prompt itself stays private.
+pub fn embed(
+    file: &Path,
+    prompt: &str,
+    provider: &str,
+    config: &C2paConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let agent = format!("imgmc {}", env!("CARGO_PKG_VERSION"));
+    let prompt_hash: String = Sha256::digest(prompt.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+
+    let mut manifest = serde_json::json!({
+        "claim_generator": format!("imgmc/{}", env!("CARGO_PKG_VERSION")),
+        "title": name,
+        "assertions": [
+            {
+                "label": "c2pa.actions",
+                "data": {
+                    "actions": [{
+                        "action": "c2pa.created",
+                        "digitalSourceType": TRAINED_ALGORITHMIC_MEDIA,
+                        "softwareAgent": agent,
+                    }]
+                }
+            },
+            {
+                "label": "org.imgmc.generation",
+                "data": { "provider": provider, "prompt_sha256": prompt_hash }
+            }
+        ]
+    });
+    // Without these c2patool signs with its test certificate
+    if let (Some(cert), Some(key)) = (&config.sign_cert, &config.private_key) {
+        manifest["sign_cert"] = cert.display().to_string().into();
+        manifest["private_key"] = key.display().to_string().into();
+        manifest["alg"] = config.alg.as_deref().unwrap_or("es256").into();
+    }
+
+    let tmp_manifest = file.with_file_name(format!(".{name}.{}.c2pa.json", std::process::id()));
+    // c2patool goes by the extension, so keep the image's own
+    let ext = file.extension().unwrap_or_default().to_string_lossy();
+    let tmp_image = file.with_file_name(format!(".{name}.{}.c2pa.{ext}", std::process::id()));
+    fs::write(&tmp_manifest, serde_json::to_vec(&manifest)?)?;
+
+    let result = Command::new("c2patool")
+        .arg(file)
+        .arg("--manifest")
+        .arg(&tmp_manifest)
+        .arg("--output")
+        .arg(&tmp_image)
+        .arg("--force")
+        .stdout(Stdio::null())
+        .status();
+    let _ = fs::remove_file(&tmp_manifest);
+
+    let outcome: Result<(), Box<dyn std::error::Error>> = match result {
+        Ok(status) if status.success() => fs::rename(&tmp_image, file).map_err(Into::into),
+        Ok(status) => Err(format!("c2patool failed with {status}").into()),
+        Err(e) => Err(format!("Could not run c2patool: {e}").into()),
+    };
+    if outcome.is_err() {
+        let _ = fs::remove_file(&tmp_image);
+    }
+    outcome
+}
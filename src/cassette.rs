@@ -0,0 +1,101 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::output;
+
+/// One request and the response it got.
+#[derive(Serialize, Deserialize)]
+struct Interaction {
+    /// Last path segment of the URL, e.g. `generations`. The host and
+    /// deployment are left out so a cassette replays with any config.
+    endpoint: String,
+    request: Value,
+    status: u16,
+    response: String,
+}
+
+/// HTTP exchanges recorded to or replayed from a JSON file, for reproducible
+/// tests and offline demos. Headers are never recorded, so cassettes hold no
+/// keys.
+pub struct Cassette {
+    path: PathBuf,
+    replay: bool,
+    interactions: Mutex<Vec<Interaction>>,
+}
+
+fn endpoint(url: &str) -> &str {
+    let path = url.split('?').next().unwrap_or(url);
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+impl Cassette {
+    /// Starts an empty cassette that is written to `path` as requests are made.
+    pub fn record(path: &Path) -> Cassette {
+        Cassette {
+            path: path.to_path_buf(),
+            replay: false,
+            interactions: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn replay(path: &Path) -> Result<Cassette, Box<dyn std::error::Error>> {
+        let bytes = fs::read(path)
+            .map_err(|e| format!("Could not read cassette {}: {e}", path.display()))?;
+        Ok(Cassette {
+            path: path.to_path_buf(),
+            replay: true,
+            interactions: Mutex::new(serde_json::from_slice(&bytes)?),
+        })
+    }
+
+    pub fn is_replay(&self) -> bool {
+        self.replay
+    }
+
+    fn interactions(&self) -> MutexGuard<'_, Vec<Interaction>> {
+        self.interactions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Adds an exchange and rewrites the file, so a run that fails halfway
+    /// still leaves what was recorded until then.
+    pub fn add(
+        &self,
+        url: &str,
+        request: &Value,
+        status: u16,
+        response: &str,
+    ) -> std::io::Result<()> {
+        let mut interactions = self.interactions();
+        interactions.push(Interaction {
+            endpoint: endpoint(url).to_string(),
+            request: request.clone(),
+            status,
+            response: response.to_string(),
+        });
+        output::write_atomic(&self.path, &serde_json::to_vec_pretty(&*interactions)?)
+    }
+
+    /// Status and body recorded for the same request, each used only once so
+    /// repeated requests get their responses in the recorded order.
+    pub fn find(&self, url: &str, request: &Value) -> Result<(u16, String), String> {
+        let mut interactions = self.interactions();
+        let endpoint = endpoint(url);
+        let i = interactions
+            .iter()
+            .position(|i| i.endpoint == endpoint && i.request == *request)
+            .ok_or_else(|| {
+                format!(
+                    "No recorded response for this {endpoint} request in {}",
+                    self.path.display()
+                )
+            })?;
+        let interaction = interactions.remove(i);
+        Ok((interaction.status, interaction.response))
+    }
+}
@@ -0,0 +1,224 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::i18n::t;
+use crate::output;
+
+/// Extensions of the files imgmc saves: images, animations, videos and
+/// 3D models.
+const EXTENSIONS: [&str; 7] = ["png", "avif", "heic", "gif", "mp4", "webm", "glb"];
+
+/// Directories holding copies of the images next to them.
+const SIDECAR_DIRS: [&str; 2] = ["thumbs", "labeled"];
+
+/// A saved file with its alt text, thumbnail and labeled copy.
+struct Generation {
+    files: Vec<PathBuf>,
+    modified: SystemTime,
+    bytes: u64,
+}
+
+/// Removes generations in `dir` and below that are older than `older_than`,
+/// then the oldest ones until the rest takes up no more than `max_bytes`.
+/// With `dry_run` they are only listed.
+pub fn run(
+    dir: &Path,
+    older_than: Option<Duration>,
+    max_bytes: Option<u64>,
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if older_than.is_none() && max_bytes.is_none() {
+        return Err(t!("clean-no-limit").into());
+    }
+    let mut generations = Vec::new();
+    collect(dir, &mut generations)?;
+    generations.sort_by_key(|generation| generation.modified);
+
+    let now = SystemTime::now();
+    let mut total: u64 = generations.iter().map(|generation| generation.bytes).sum();
+    let mut removed = Vec::new();
+    for generation in generations {
+        let age = now.duration_since(generation.modified).unwrap_or_default();
+        let expired = older_than.is_some_and(|max| age > max);
+        let too_big = max_bytes.is_some_and(|max| total > max);
+        // Oldest first, so once neither applies the rest is kept too
+        if !expired && !too_big {
+            break;
+        }
+        total -= generation.bytes;
+        removed.push(generation);
+    }
+
+    let bytes: u64 = removed.iter().map(|generation| generation.bytes).sum();
+    for file in removed.iter().flat_map(|generation| &generation.files) {
+        if dry_run {
+            println!("{}", file.display());
+        } else {
+            fs::remove_file(file)?;
+        }
+    }
+    if !dry_run {
+        prune(dir)?;
+    }
+    let size = format!("{:.1} MB", bytes as f64 / 1_000_000.0);
+    let count = removed.len();
+    if dry_run {
+        eprintln!("{}", t!("clean-dry-run", count = count, size = size));
+    } else {
+        eprintln!("{}", t!("cleaned", count = count, size = size));
+    }
+    Ok(())
+}
+
+fn collect(dir: &Path, generations: &mut Vec<Generation>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if !SIDECAR_DIRS.iter().any(|name| entry.file_name() == *name) {
+                collect(&path, generations)?;
+            }
+            continue;
+        }
+        // `latest.<ext>` is a link to one of the others
+        let saved = path
+            .extension()
+            .is_some_and(|ext| EXTENSIONS.iter().any(|known| ext == *known));
+        if !file_type.is_file() || !saved || output::is_latest(&path) {
+            continue;
+        }
+
+        let metadata = entry.metadata()?;
+        let mut generation = Generation {
+            files: vec![path.clone()],
+            modified: metadata.modified()?,
+            bytes: metadata.len(),
+        };
+        let mut alt = path.clone().into_os_string();
+        alt.push(".alt.txt");
+        let copies = SIDECAR_DIRS.iter().map(|name| {
+            dir.join(name)
+                .join(Path::new(&entry.file_name()).with_extension("png"))
+        });
+        for sidecar in copies.chain([PathBuf::from(alt)]) {
+            if let Ok(metadata) = fs::metadata(&sidecar) {
+                generation.bytes += metadata.len();
+                generation.files.push(sidecar);
+            }
+        }
+        generations.push(generation);
+    }
+    Ok(())
+}
+
+/// Removes directories left empty and a `latest.<ext>` whose image is gone.
+fn prune(dir: &Path) -> std::io::Result<()> {
+    for latest in output::latest_links(dir)? {
+        if latest.is_symlink() && !latest.exists() {
+            fs::remove_file(&latest)?;
+        }
+    }
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            let path = entry.path();
+            prune(&path)?;
+            // Fails for directories that still hold something, which is fine
+            let _ = fs::remove_dir(&path);
+        }
+    }
+    Ok(())
+}
+
+/// Parses an age such as `30d`, in seconds, minutes, hours, days or weeks.
+pub fn parse_age(s: &str) -> Result<Duration, String> {
+    let invalid = || t!("invalid-age", value = s);
+    let split = s.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+    let (number, unit) = s.split_at(split);
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    number
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(invalid)
+}
+
+/// Parses a size such as `20G` or `500MB`, in powers of 1000.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let invalid = || t!("invalid-size", value = s);
+    let digits = s.trim_end_matches(|c: char| !c.is_ascii_digit());
+    let number: u64 = digits.parse().map_err(|_| invalid())?;
+    let factor: u64 = match s[digits.len()..].to_uppercase().trim_end_matches('B') {
+        "" => 1,
+        "K" => 1_000,
+        "M" => 1_000_000,
+        "G" => 1_000_000_000,
+        "T" => 1_000_000_000_000,
+        _ => return Err(invalid()),
+    };
+    number.checked_mul(factor).ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn age_units() {
+        assert_eq!(parse_age("45s"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_age("2h"), Ok(Duration::from_secs(2 * 60 * 60)));
+        assert_eq!(parse_age("30d"), Ok(Duration::from_secs(30 * 24 * 60 * 60)));
+        assert_eq!(parse_age("0w"), Ok(Duration::ZERO));
+    }
+
+    #[test]
+    fn age_rejects_malformed_and_overflow() {
+        for s in [
+            "",
+            "30",
+            "d",
+            "30 d",
+            "30D",
+            "30dd",
+            "-1d",
+            "1.5h",
+            "18446744073709551615w",
+        ] {
+            assert!(parse_age(s).is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn size_units() {
+        assert_eq!(parse_size("500"), Ok(500));
+        assert_eq!(parse_size("500MB"), Ok(500_000_000));
+        assert_eq!(parse_size("20G"), Ok(20_000_000_000));
+        assert_eq!(parse_size("3kb"), Ok(3_000));
+        assert_eq!(parse_size("1T"), Ok(1_000_000_000_000));
+    }
+
+    #[test]
+    fn size_rejects_malformed_and_overflow() {
+        for s in [
+            "",
+            "G",
+            "20X",
+            "2G0",
+            "-5M",
+            "1.5G",
+            "20000000T",
+            "99999999999999999999",
+        ] {
+            assert!(parse_size(s).is_err(), "{s}");
+        }
+    }
+}
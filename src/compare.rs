@@ -0,0 +1,90 @@
+use std::fs;
+use std::time::Instant;
+
+use slug::slugify;
+
+use crate::azure::Client;
+use crate::i18n::t;
+use crate::output::{self, Saver};
+use crate::redact;
+use crate::request::ImageRequest;
+use crate::{sheet, spinner};
+
+/// Generates `req` with every deployment, then writes a labeled sheet with
+/// the first image from each next to a JSON report of timings and costs.
+pub fn run(
+    clients: &[(String, Client)],
+    req: &ImageRequest,
+    mut saver: Saver,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let base = saver.base_name(&req.prompt);
+    let mut columns = Vec::with_capacity(clients.len());
+    let mut results = Vec::with_capacity(clients.len());
+
+    for (deployment, client) in clients {
+        saver.name = Some(format!("{base}_{}", slugify(deployment)));
+        let started = Instant::now();
+        let result = generate(client, &saver, req.clone());
+        let seconds = started.elapsed().as_secs_f64();
+
+        let mut entry = serde_json::json!({
+            "deployment": deployment,
+            "seconds": (seconds * 10.0).round() / 10.0,
+            "estimated_cost_usd": client.estimated_cost(req),
+        });
+        match result {
+            Ok((first, files)) => {
+                for filename in &files {
+                    println!("{}", t!("image-saved", path = filename.display()));
+                }
+                let files: Vec<String> = files.iter().map(|f| f.display().to_string()).collect();
+                entry["files"] = files.into();
+                columns.push((format!("{deployment} {seconds:.1}s"), first));
+            }
+            Err(e) => {
+                let e = redact::redact(&e.to_string());
+                eprintln!("{deployment} failed: {e}");
+                entry["error"] = e.into();
+            }
+        }
+        results.push(entry);
+    }
+
+    if columns.is_empty() {
+        return Err("Every deployment failed, nothing to compare".into());
+    }
+
+    let sheet = sheet::render(&columns)?;
+    let sheet = output::write_new(
+        &saver.out_dir()?,
+        &format!("{base}_compare"),
+        1,
+        "png",
+        &sheet,
+    )?;
+    let report = serde_json::json!({ "prompt": req.prompt, "results": results });
+    let report_path = sheet.with_extension("json");
+    fs::write(&report_path, serde_json::to_string_pretty(&report)?)?;
+
+    println!("{}", t!("comparison-saved", path = sheet.display()));
+    println!("{}", t!("report-saved", path = report_path.display()));
+    Ok(())
+}
+
+/// Returns the first decoded image for the sheet along with the saved files.
+fn generate(
+    client: &Client,
+    saver: &Saver,
+    mut req: ImageRequest,
+) -> Result<(Vec<u8>, Vec<std::path::PathBuf>), Box<dyn std::error::Error>> {
+    client.validate(&mut req)?;
+    saver.preflight(&req)?;
+    let sp = spinner::Spinner::start(t!("calling-api"));
+    let data = saver.fetch(client, &req, &sp);
+    drop(sp);
+    let data = data?;
+
+    let first = data.first().ok_or("The response contained no images")?;
+    let first = output::decode(&req, first)?;
+    Ok((first, saver.save(&req, &data)?))
+}
@@ -0,0 +1,485 @@
+use std::fs;
+use std::path::Path;
+
+use figment::{
+    Figment,
+    providers::{Format as _, Toml},
+};
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+
+use crate::azure::Model;
+use crate::encode::Format;
+use crate::i18n::t;
+use crate::redact;
+use crate::request::{ImageQuality, ImageRequest};
+use crate::size::Size;
+use crate::webhook::Webhook;
+
+#[derive(Deserialize, Clone)]
+pub struct AzureConfig {
+    pub api_base: String,
+    pub api_key: String,
+    pub deployment: String,
+    /// Model behind the deployment, guessed from its name when left out.
+    pub model: Option<Model>,
+    /// Chat deployment with vision support, used by `imgmc describe`.
+    pub vision_deployment: Option<String>,
+    /// Sora deployment, used by `imgmc video`.
+    pub video_deployment: Option<String>,
+    /// Fields merged into every image request body, for parameters imgmc does
+    /// not know about yet.
+    #[serde(default)]
+    pub extra_params: serde_json::Map<String, serde_json::Value>,
+}
+
+impl AzureConfig {
+    /// Stands in when replaying a cassette without an `[azure]` section, as
+    /// nothing is sent anywhere then.
+    pub fn offline() -> AzureConfig {
+        AzureConfig {
+            api_base: "http://localhost".into(),
+            api_key: String::new(),
+            deployment: "gpt-image-1".into(),
+            model: None,
+            vision_deployment: None,
+            video_deployment: None,
+            extra_params: serde_json::Map::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+pub struct HttpConfig {
+    /// Sent with every API request.
+    #[serde(default)]
+    pub headers: std::collections::BTreeMap<String, String>,
+}
+
+/// Limits an administrator sets in the system config. A `[policy]` table in
+/// the user config is ignored, so users cannot lift them.
+#[derive(Deserialize, Default, Clone)]
+pub struct PolicyConfig {
+    pub allowed_providers: Option<Vec<String>>,
+    pub max_quality: Option<ImageQuality>,
+    pub max_count: Option<u8>,
+    /// Words and phrases prompts may not contain, ignoring case.
+    #[serde(default)]
+    pub blocked_terms: Vec<String>,
+    /// Regular expressions prompts may not match, ignoring case.
+    #[serde(default)]
+    pub blocked_patterns: Vec<String>,
+    /// Both of the above, built by [`PolicyConfig::compile`].
+    #[serde(skip)]
+    blocklist: Vec<(String, Regex)>,
+}
+
+impl PolicyConfig {
+    /// Builds the blocklist, failing on patterns that are not valid regular
+    /// expressions.
+    fn compile(&mut self) -> Result<(), ConfigError> {
+        let terms = self
+            .blocked_terms
+            .iter()
+            // Whole words only, so "war" does not block "software"
+            .map(|term| (term, format!(r"(?:^|\W){}(?:\W|$)", regex::escape(term))));
+        let patterns = self
+            .blocked_patterns
+            .iter()
+            .map(|pattern| (pattern, pattern.clone()));
+        self.blocklist = terms
+            .chain(patterns)
+            .map(|(shown, pattern)| {
+                RegexBuilder::new(&pattern)
+                    .case_insensitive(true)
+                    .build()
+                    .map(|regex| (shown.clone(), regex))
+                    .map_err(|e| ConfigError(format!("Invalid blocked pattern {shown}: {e}")))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    pub fn allow_provider(&self, name: &str) -> Result<(), ConfigError> {
+        match &self.allowed_providers {
+            Some(allowed) if !allowed.iter().any(|p| p == name) => Err(ConfigError(format!(
+                "The provider {name} is not allowed here, only {}",
+                allowed.join(", ")
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks a prompt against the blocklist alone, for requests that are
+    /// not image requests, such as videos and assets.
+    pub fn check_prompt(&self, prompt: &str) -> Result<(), String> {
+        match self
+            .blocklist
+            .iter()
+            .find(|(_, regex)| regex.is_match(prompt))
+        {
+            Some((shown, _)) => Err(t!("prompt-blocked", entry = shown)),
+            None => Ok(()),
+        }
+    }
+
+    pub fn check(&self, req: &ImageRequest) -> Result<(), String> {
+        self.check_prompt(&req.prompt)?;
+
+        // Auto may pick high quality, so it counts as such
+        let rank = |quality: &ImageQuality| match quality {
+            ImageQuality::Low => 0,
+            ImageQuality::Medium => 1,
+            ImageQuality::High | ImageQuality::Auto => 2,
+        };
+        if let Some(max) = &self.max_quality
+            && rank(&req.quality) > rank(max)
+        {
+            return Err(format!("Quality above {max} is not allowed here"));
+        }
+        if let Some(max) = self.max_count
+            && req.count > max
+        {
+            return Err(format!(
+                "More than {max} images per request are not allowed here"
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// An append-only record of every generation, for compliance.
+#[derive(Deserialize, Default)]
+pub struct AuditConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Defaults to `audit.jsonl` in imgmc's data directory.
+    pub path: Option<std::path::PathBuf>,
+    /// Recorded as the user instead of `$USER`.
+    pub user: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct HooksConfig {
+    pub post_save: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct UploadConfig {
+    pub destination: Option<String>,
+    /// Private key used for sftp:// destinations.
+    pub ssh_key: Option<std::path::PathBuf>,
+    #[serde(default)]
+    pub no_local: bool,
+}
+
+#[derive(Deserialize, Default)]
+pub struct OutputConfig {
+    /// Nest saved images under `YYYY/MM/DD/`.
+    #[serde(default)]
+    pub date_dirs: bool,
+    /// Format to save images in, e.g. `avif`.
+    pub format: Option<Format>,
+    /// Quality from 1 to 100 for lossy formats.
+    pub quality: Option<u8>,
+}
+
+/// Certificate for signing Content Credentials; without one c2patool uses
+/// its own test certificate, which verifiers do not trust.
+#[derive(Deserialize, Default, Clone)]
+pub struct C2paConfig {
+    pub sign_cert: Option<std::path::PathBuf>,
+    pub private_key: Option<std::path::PathBuf>,
+    /// Signing algorithm matching the key, e.g. `es256` or `ps256`.
+    pub alg: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct SigningConfig {
+    /// GPG key ID or fingerprint to sign with, instead of gpg's default key.
+    pub key: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct WallpaperConfig {
+    /// Used instead of the detected screen resolution.
+    pub resolution: Option<Size>,
+    /// Where wallpapers are saved, instead of imgmc's data directory.
+    pub dir: Option<std::path::PathBuf>,
+}
+
+/// Fragments combined with every prompt, e.g. `suffix = "no text, no watermark"`.
+#[derive(Deserialize, Default, Clone)]
+pub struct PromptConfig {
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
+    /// Mask email addresses, phone numbers and names before sending.
+    #[serde(default)]
+    pub scrub_pii: bool,
+    /// Style fragments `--jitter` picks from, instead of the built-in ones.
+    #[serde(default)]
+    pub jitter: Vec<String>,
+}
+
+impl PromptConfig {
+    /// The prompt as sent to the API, with prefix and suffix on their own lines.
+    pub fn wrap(&self, prompt: &str) -> String {
+        [self.prefix.as_deref(), Some(prompt), self.suffix.as_deref()]
+            .into_iter()
+            .flatten()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[derive(Deserialize, Default)]
+pub struct MockConfig {
+    /// How long every generation takes.
+    #[serde(default)]
+    pub latency_ms: u64,
+}
+
+#[derive(Deserialize, Default)]
+pub struct Config {
+    /// Language for messages, e.g. `de`, overriding `LANG`.
+    pub locale: Option<String>,
+    pub azure: Option<AzureConfig>,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    #[serde(default)]
+    pub c2pa: C2paConfig,
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub mock: MockConfig,
+    #[serde(default)]
+    pub output: OutputConfig,
+    /// Only ever read from the system config.
+    #[serde(skip)]
+    pub policy: PolicyConfig,
+    #[serde(default)]
+    pub prompt: PromptConfig,
+    #[serde(default)]
+    pub signing: SigningConfig,
+    #[serde(default)]
+    pub upload: UploadConfig,
+    #[serde(default)]
+    pub wallpaper: WallpaperConfig,
+    #[serde(default)]
+    pub webhooks: std::collections::HashMap<String, Webhook>,
+}
+
+/// A missing or unusable configuration file or section.
+#[derive(Debug)]
+pub struct ConfigError(pub String);
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Config provisioned by an administrator for everyone on the machine.
+const SYSTEM_CONFIG: &str = "/etc/imgmc/config.toml";
+
+/// Layout of the config files this build reads, set as `version` at the top
+/// of a file. Files without one predate versioning and have this layout. A
+/// future layout change bumps this and migrates older files in [`layer`].
+const CONFIG_VERSION: u32 = 1;
+
+#[derive(Deserialize)]
+struct Versioned {
+    version: Option<u32>,
+}
+
+/// Reads one config file, refusing one written for a layout this build does
+/// not know. A missing file is empty.
+fn layer(path: &Path) -> Result<Figment, ConfigError> {
+    let file = Figment::from(Toml::file(path));
+    match file.extract::<Versioned>().map_err(explain)?.version {
+        None | Some(CONFIG_VERSION) => Ok(file),
+        Some(version) => Err(ConfigError(t!(
+            "config-version-unsupported",
+            path = path.display(),
+            version = version,
+            supported = CONFIG_VERSION
+        ))),
+    }
+}
+
+/// Rewords figment's errors to name the file and the key as written in it,
+/// one line per problem, followed by how the sections involved should look.
+fn explain(errors: figment::Error) -> ConfigError {
+    let mut sections = Vec::new();
+    let mut lines: Vec<String> = errors
+        .into_iter()
+        .map(|e| {
+            let file = source(e.metadata.as_ref());
+            let mut key = e.path.clone();
+            // Point at the missing key itself rather than its section
+            if let figment::error::Kind::MissingField(field) = &e.kind {
+                key.push(field.to_string());
+            }
+            if key.len() > 1 && !sections.contains(&key[0]) {
+                sections.push(key[0].clone());
+            }
+            if key.is_empty() {
+                t!("config-invalid", file = file, error = e.kind)
+            } else {
+                t!(
+                    "config-invalid-key",
+                    file = file,
+                    key = key.join("."),
+                    error = e.kind
+                )
+            }
+        })
+        .collect();
+    for section in sections {
+        if let Some(example) = example(&section) {
+            lines.push(format!("{}\n{example}", t!("config-example")));
+        }
+    }
+    lines.push(t!("config-init-hint"));
+    ConfigError(lines.join("\n"))
+}
+
+/// The file a value came from, for messages.
+fn source(metadata: Option<&figment::Metadata>) -> String {
+    metadata
+        .and_then(|metadata| metadata.source.as_ref())
+        .map_or_else(|| "config".to_string(), |source| source.to_string())
+}
+
+/// Starting point written by `imgmc config init`, documenting every section.
+const TEMPLATE: &str = include_str!("config.example.toml");
+
+/// The part of [`TEMPLATE`] showing `section`, uncommented if the whole
+/// section is commented out there.
+fn example(section: &str) -> Option<String> {
+    let block = TEMPLATE.split("\n\n").find(|block| {
+        let header = block.trim_start_matches("# ");
+        header.starts_with(&format!("[{section}]")) || header.starts_with(&format!("[{section}."))
+    })?;
+    if !block.starts_with("# [") {
+        return Some(block.trim_end().to_string());
+    }
+    let lines: Vec<&str> = block
+        .lines()
+        .map(|line| line.strip_prefix("# ").unwrap_or(line))
+        .collect();
+    Some(lines.join("\n"))
+}
+
+/// Writes a commented config to the XDG config directory, returning where.
+/// An existing config is only replaced with `force`.
+pub fn init(force: bool) -> Result<std::path::PathBuf, Box<dyn std::error::Error>> {
+    let path = xdg::BaseDirectories::with_prefix("imgmc").place_config_file("config.toml")?;
+    if path.exists() && !force {
+        return Err(t!("config-exists", path = path.display()).into());
+    }
+    fs::write(&path, TEMPLATE)?;
+    Ok(path)
+}
+
+/// Keys in `figment` that `T` has no field for, written as in TOML. They are
+/// ignored unless `--strict-config` is given.
+fn unknown_keys<T: DeserializeOwned>(figment: &Figment) -> Vec<String> {
+    let Ok(value) = figment.extract::<serde_json::Value>() else {
+        return Vec::new();
+    };
+    let mut unknown = Vec::new();
+    let _ = serde_ignored::deserialize::<_, _, T>(value, |path| {
+        // Options and newtypes show up as `?` in the path
+        let key: Vec<String> = path
+            .to_string()
+            .split('.')
+            .filter(|part| *part != "?")
+            .map(str::to_string)
+            .collect();
+        unknown.push(key.join("."));
+    });
+    unknown
+}
+
+/// Reads `config.toml` from the XDG config directory, on top of the system
+/// config if there is one. Tables are merged key by key, so a user can add an
+/// `api_key` to an `[azure]` endpoint set up system-wide. With `strict`, keys
+/// imgmc does not know are an error rather than ignored.
+pub fn load(strict: bool) -> Result<Config, Box<dyn std::error::Error>> {
+    let xdg_dirs = xdg::BaseDirectories::with_prefix("imgmc");
+    let xdg_file = xdg_dirs
+        .get_config_file("config.toml")
+        .ok_or_else(|| ConfigError("Could not get config file".into()))?;
+
+    let system = Path::new(SYSTEM_CONFIG);
+    if !xdg_file.exists() && !system.exists() {
+        return Err(ConfigError(t!("config-not-found", path = xdg_file.display())).into());
+    }
+
+    let system = layer(system)?;
+    let figment = Figment::new()
+        .merge(system.clone())
+        .merge(layer(&xdg_file)?);
+    // Checked first, as a misspelled key often leaves a required one missing
+    if strict {
+        // `version` is read by `layer`, `[policy]` only from the system config
+        let shared = unknown_keys::<Config>(&figment)
+            .into_iter()
+            .filter(|key| key != "version" && key != "policy" && !key.starts_with("policy."))
+            .map(|key| (figment.find_metadata(&key), key));
+        let policy = unknown_keys::<PolicyConfig>(&system.focus("policy"))
+            .into_iter()
+            .map(|key| format!("policy.{key}"))
+            .map(|key| (system.find_metadata(&key), key));
+        let lines: Vec<String> = shared
+            .chain(policy)
+            .map(|(metadata, key)| t!("config-unknown-key", file = source(metadata), key = key))
+            .collect();
+        if !lines.is_empty() {
+            return Err(ConfigError(lines.join("\n")).into());
+        }
+    }
+    let mut config: Config = figment.extract().map_err(explain)?;
+    config.policy = system.extract::<SystemConfig>().map_err(explain)?.policy;
+    config.policy.compile()?;
+    config.register_secrets();
+    Ok(config)
+}
+
+/// The parts of the system config users cannot override.
+#[derive(Deserialize)]
+struct SystemConfig {
+    #[serde(default)]
+    policy: PolicyConfig,
+}
+
+impl Config {
+    /// Keeps credentials from the config out of errors and logs.
+    fn register_secrets(&self) {
+        if let Some(azure) = &self.azure {
+            redact::register(&azure.api_key);
+        }
+        for (name, value) in &self.http.headers {
+            if redact::is_sensitive_header(name) {
+                redact::register(value);
+            }
+        }
+        // Webhook URLs carry the token that allows posting
+        for webhook in self.webhooks.values() {
+            redact::register(&webhook.url);
+        }
+    }
+
+    pub fn azure(&self) -> Result<&AzureConfig, ConfigError> {
+        self.azure
+            .as_ref()
+            .ok_or_else(|| ConfigError(t!("azure-config-missing")))
+    }
+}
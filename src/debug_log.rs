@@ -0,0 +1,125 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde_json::Value;
+
+use crate::redact;
+
+/// Strings longer than this, such as base64 image data, are replaced by their length.
+const MAX_STRING_LEN: usize = 1024;
+/// Non-JSON bodies are cut off after this many bytes.
+const MAX_TEXT_LEN: usize = 4096;
+
+/// One HTTP request and its outcome.
+pub struct Exchange<'a> {
+    pub method: &'a str,
+    pub url: &'a str,
+    /// Headers sent with the request; credentials are never logged.
+    pub headers: &'a [(&'a str, &'a str)],
+    pub request: &'a Value,
+    /// `None` when no response was received at all.
+    pub status: Option<u16>,
+    /// Azure's ID for the request, to quote when contacting support.
+    pub request_id: Option<&'a str>,
+    pub started: Instant,
+    pub response: Reply<'a>,
+}
+
+/// What came back for a request.
+#[derive(Clone, Copy)]
+pub enum Reply<'a> {
+    /// The response body as received, or the transport error.
+    Text(&'a str),
+    /// A JSON response body, parsed while it was read.
+    Json(&'a Value),
+}
+
+/// Appends a redacted JSON line per HTTP exchange, for troubleshooting
+/// provider issues and attaching to bug reports.
+pub struct DebugLog {
+    file: Mutex<File>,
+}
+
+impl DebugLog {
+    pub fn open(path: &Path) -> std::io::Result<DebugLog> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(DebugLog {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn record(&self, exchange: Exchange<'_>) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let latency_ms = u64::try_from(exchange.started.elapsed().as_millis()).unwrap_or(u64::MAX);
+
+        let headers: serde_json::Map<String, Value> = exchange
+            .headers
+            .iter()
+            .map(|&(name, value)| {
+                let value = if redact::is_sensitive_header(name) {
+                    redact::REDACTED
+                } else {
+                    value
+                };
+                (name.to_string(), value.into())
+            })
+            .collect();
+
+        let entry = serde_json::json!({
+            "timestamp": timestamp,
+            "method": exchange.method,
+            "url": exchange.url,
+            "request_headers": headers,
+            "request": elide(exchange.request),
+            "status": exchange.status,
+            "request_id": exchange.request_id,
+            "latency_ms": latency_ms,
+            "response": summarize(exchange.response),
+        });
+
+        // Logging must never get in the way of a generation
+        if let Ok(mut file) = self.file.lock() {
+            let _ = writeln!(file, "{}", redact::redact(&entry.to_string()));
+        }
+    }
+}
+
+fn summarize(reply: Reply<'_>) -> Value {
+    match reply {
+        Reply::Json(value) => elide(value),
+        Reply::Text(body) => match serde_json::from_str(body) {
+            Ok(value) => elide(&value),
+            Err(_) => Value::String(truncate(body, MAX_TEXT_LEN)),
+        },
+    }
+}
+
+/// A copy of `value` without its long strings, which are never copied.
+fn elide(value: &Value) -> Value {
+    match value {
+        Value::String(s) if s.len() > MAX_STRING_LEN => {
+            Value::String(format!("<{} bytes elided>", s.len()))
+        }
+        Value::Array(items) => Value::Array(items.iter().map(elide).collect()),
+        Value::Object(map) => {
+            Value::Object(map.iter().map(|(k, v)| (k.clone(), elide(v))).collect())
+        }
+        other => other.clone(),
+    }
+}
+
+fn truncate(s: &str, max: usize) -> String {
+    if s.len() <= max {
+        return s.to_string();
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}… ({} bytes total)", &s[..end], s.len())
+}
@@ -0,0 +1,54 @@
+use std::{env, fs};
+
+use crate::hooks;
+
+const TEMPLATE: &str = "
+# Write the image prompt above. Lines starting with '#' are ignored
+# and an empty prompt aborts generation.
+";
+
+#[cfg(unix)]
+const DEFAULT_EDITOR: &str = "vi";
+#[cfg(not(unix))]
+const DEFAULT_EDITOR: &str = "notepad";
+
+/// Opens `$VISUAL` or `$EDITOR` on a prompt template, like `git commit` does,
+/// and returns what was written with comment lines removed.
+pub fn compose(initial: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let path = env::temp_dir().join(format!("imgmc-prompt-{}.txt", std::process::id()));
+    fs::write(&path, format!("{initial}{TEMPLATE}"))?;
+
+    let result = edit(&path);
+    let _ = fs::remove_file(&path);
+
+    let prompt = strip_comments(&result?);
+    if prompt.is_empty() {
+        return Err("Aborting generation due to an empty prompt".into());
+    }
+    Ok(prompt)
+}
+
+fn edit(path: &std::path::Path) -> Result<String, Box<dyn std::error::Error>> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_string());
+
+    // Through the shell, so editors configured with arguments like `code --wait` work
+    let command = format!("{editor} {}", hooks::quote(&path.display().to_string()));
+    let status = hooks::shell(&command).status()?;
+    if !status.success() {
+        return Err(format!("Editor `{editor}` exited with {status}").into());
+    }
+    Ok(fs::read_to_string(path)?)
+}
+
+fn strip_comments(text: &str) -> String {
+    text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
@@ -0,0 +1,48 @@
+use clap::ValueEnum;
+
+/// Markup flavours for the embed snippets printed after saving.
+#[derive(ValueEnum, Clone, Copy)]
+pub enum Format {
+    Markdown,
+    Html,
+    Org,
+    /// Obsidian's `![[file]]` embeds, resolved by file name.
+    Wikilink,
+}
+
+impl Format {
+    /// Builds a snippet embedding `target` with `alt` as its alternative text.
+    pub fn snippet(self, alt: &str, target: &str) -> String {
+        match self {
+            Format::Markdown => {
+                let alt = alt.replace('[', "\\[").replace(']', "\\]");
+                format!("![{alt}]({target})")
+            }
+            Format::Html => format!(
+                "<img src=\"{}\" alt=\"{}\">",
+                escape_html(target),
+                escape_html(alt)
+            ),
+            Format::Wikilink if !target.contains("://") => {
+                let name = target.rsplit(['/', '\\']).next().unwrap_or(target);
+                format!("![[{name}]]")
+            }
+            Format::Wikilink => format!("![]({target})"),
+            Format::Org => {
+                let alt = alt.replace('\n', " ");
+                if target.contains("://") {
+                    format!("#+CAPTION: {alt}\n[[{target}]]")
+                } else {
+                    format!("#+CAPTION: {alt}\n[[file:{target}]]")
+                }
+            }
+        }
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
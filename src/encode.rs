@@ -0,0 +1,83 @@
+use std::fs;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use clap::ValueEnum;
+use image::codecs::avif::AvifEncoder;
+use serde::Deserialize;
+
+/// Quality used when none is given, a good trade-off for photos on the web.
+pub const DEFAULT_QUALITY: u8 = 80;
+
+/// rav1e speed from 1 (smallest files) to 10 (fastest). At 6 a 1024x1024
+/// image takes about a second.
+const AVIF_SPEED: u8 = 6;
+
+/// Formats images can be saved in. Images are processed as PNG and only
+/// re-encoded right before they are written.
+#[derive(ValueEnum, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Png,
+    Avif,
+    /// Needs `heif-enc` from libheif.
+    Heic,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Avif => "avif",
+            Format::Heic => "heic",
+        }
+    }
+
+    /// Re-encodes a PNG, with `quality` from 1 to 100. PNG is returned as is,
+    /// being lossless.
+    pub fn encode(self, png: &[u8], quality: u8) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        match self {
+            Format::Png => Ok(png.to_vec()),
+            Format::Avif => avif(png, quality),
+            Format::Heic => heic(png, quality),
+        }
+    }
+}
+
+fn avif(png: &[u8], quality: u8) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let img = image::load_from_memory(png)?;
+    let mut out = Vec::new();
+    img.write_with_encoder(AvifEncoder::new_with_speed_quality(
+        &mut out, AVIF_SPEED, quality,
+    ))?;
+    Ok(out)
+}
+
+/// Encodes with `heif-enc`, as there is no HEVC encoder in Rust.
+fn heic(png: &[u8], quality: u8) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    // Images are encoded on several threads at once
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    let base = std::env::temp_dir().join(format!("imgmc-heic-{}-{n}", std::process::id()));
+    let input = base.with_extension("png");
+    let output = base.with_extension("heic");
+    fs::write(&input, png)?;
+
+    let result = Command::new("heif-enc")
+        .arg("--quality")
+        .arg(quality.to_string())
+        .arg("--output")
+        .arg(&output)
+        .arg(&input)
+        .stdout(Stdio::null())
+        .status();
+    let _ = fs::remove_file(&input);
+    let outcome: Result<Vec<u8>, Box<dyn std::error::Error>> = match result {
+        Ok(status) if status.success() => fs::read(&output).map_err(Into::into),
+        Ok(status) => Err(format!("heif-enc failed with {status}").into()),
+        Err(e) => Err(format!("Could not run heif-enc: {e}").into()),
+    };
+    let _ = fs::remove_file(&output);
+    outcome
+}
@@ -0,0 +1,55 @@
+use std::error::Error;
+use std::process::ExitCode;
+
+use crate::azure::ApiError;
+use crate::config::ConfigError;
+use crate::output::CorruptImage;
+
+pub const CONFIG: u8 = 2;
+pub const AUTH: u8 = 3;
+pub const RATE_LIMIT: u8 = 4;
+pub const CONTENT_POLICY: u8 = 5;
+pub const NETWORK: u8 = 6;
+pub const IO: u8 = 7;
+pub const CORRUPT_IMAGE: u8 = 8;
+
+/// Maps an error to the documented exit code for its failure class, falling
+/// back to 1 for anything unclassified.
+pub fn code(err: &(dyn Error + 'static)) -> ExitCode {
+    ExitCode::from(number(err))
+}
+
+/// Name of the failure class of an error, e.g. for metrics labels.
+pub fn class(err: &(dyn Error + 'static)) -> &'static str {
+    match number(err) {
+        CONFIG => "config",
+        AUTH => "auth",
+        RATE_LIMIT => "rate_limit",
+        CONTENT_POLICY => "content_policy",
+        NETWORK => "network",
+        IO => "io",
+        CORRUPT_IMAGE => "corrupt_image",
+        _ => "other",
+    }
+}
+
+fn number(err: &(dyn Error + 'static)) -> u8 {
+    if err.is::<ConfigError>() || err.is::<figment::Error>() {
+        CONFIG
+    } else if let Some(api_error) = err.downcast_ref::<ApiError>() {
+        match api_error.status {
+            _ if api_error.is_content_policy() => CONTENT_POLICY,
+            401 | 403 => AUTH,
+            429 => RATE_LIMIT,
+            _ => 1,
+        }
+    } else if err.is::<CorruptImage>() {
+        CORRUPT_IMAGE
+    } else if err.is::<ureq::Error>() {
+        NETWORK
+    } else if err.is::<std::io::Error>() {
+        IO
+    } else {
+        1
+    }
+}
@@ -0,0 +1,108 @@
+use std::path::Path;
+use std::process::Command;
+
+/// A user command run after every saved image, e.g. `optipng {file}`.
+///
+/// `{file}`, `{prompt}` and `{provider}` are replaced with references to
+/// environment variables holding the values, so the shell never parses the
+/// values themselves. Prompts can come from HTTP requests and Markdown files,
+/// and no quoting is safe for every shell.
+pub struct PostSave {
+    command: String,
+}
+
+impl PostSave {
+    pub fn new(command: String) -> PostSave {
+        PostSave { command }
+    }
+
+    pub fn run(
+        &self,
+        file: &Path,
+        prompt: &str,
+        provider: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let command = expand(&self.command, |name| match name {
+            "file" => Some(var_ref("IMGMC_FILE")),
+            "prompt" => Some(var_ref("IMGMC_PROMPT")),
+            "provider" => Some(var_ref("IMGMC_PROVIDER")),
+            _ => None,
+        });
+
+        let status = shell(&command)
+            .env("IMGMC_FILE", file)
+            .env("IMGMC_PROMPT", prompt)
+            .env("IMGMC_PROVIDER", provider)
+            .status()?;
+        if !status.success() {
+            return Err(format!("`{command}` exited with {status}").into());
+        }
+        Ok(())
+    }
+}
+
+/// Replaces `{name}` placeholders in a single pass, leaving unknown ones as-is.
+pub fn expand(template: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after
+            .find('}')
+            .and_then(|end| lookup(&after[..end]).map(|value| (end, value)))
+        {
+            Some((end, value)) => {
+                out.push_str(&value);
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// A quoted reference to the environment variable `name`.
+#[cfg(unix)]
+fn var_ref(name: &str) -> String {
+    format!("\"${name}\"")
+}
+
+/// Delayed expansion, which [`shell`] turns on, happens after cmd.exe has
+/// parsed the line, so `&`, `|` or `%` in the value are not interpreted.
+#[cfg(not(unix))]
+fn var_ref(name: &str) -> String {
+    format!("\"!{name}!\"")
+}
+
+#[cfg(unix)]
+pub fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Only for values imgmc chose itself, such as temporary paths: cmd.exe has
+/// no quoting that keeps it from interpreting every character.
+#[cfg(not(unix))]
+pub fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+#[cfg(unix)]
+pub fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+#[cfg(not(unix))]
+pub fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/V:ON").arg("/C").arg(command);
+    cmd
+}
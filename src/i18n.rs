@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+const ENGLISH: &str = include_str!("locales/en.ftl");
+
+/// Translations by language code. English doubles as the fallback for
+/// messages a translation leaves out.
+const TRANSLATIONS: [(&str, &str); 1] = [("de", include_str!("locales/de.ftl"))];
+
+struct Catalog {
+    messages: HashMap<&'static str, &'static str>,
+    fallback: HashMap<&'static str, &'static str>,
+}
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+/// Picks the language for this run: `locale` from the config when set, else
+/// `LC_ALL`, `LC_MESSAGES` or `LANG`. Messages shown before this is called use
+/// the environment alone.
+pub fn init(locale: Option<&str>) {
+    let _ = CATALOG.set(Catalog::new(locale));
+}
+
+/// Formats the message `id`, substituting `{ $name }` placeholders.
+///
+/// Use through the [`t!`] macro.
+pub fn message(id: &str, args: &[(&str, &dyn Display)]) -> String {
+    let catalog = CATALOG.get_or_init(|| Catalog::new(None));
+    let mut text = catalog
+        .messages
+        .get(id)
+        .or_else(|| catalog.fallback.get(id))
+        .map_or_else(|| id.to_string(), |text| text.to_string());
+    for (name, value) in args {
+        text = text.replace(&format!("{{ ${name} }}"), &value.to_string());
+    }
+    text
+}
+
+impl Catalog {
+    fn new(locale: Option<&str>) -> Catalog {
+        let locale = locale.map(str::to_string).or_else(|| {
+            ["LC_ALL", "LC_MESSAGES", "LANG"]
+                .into_iter()
+                .find_map(|var| env::var(var).ok().filter(|value| !value.is_empty()))
+        });
+        // `de_DE.UTF-8` and `de-AT` both select `de`
+        let language = locale
+            .as_deref()
+            .and_then(|locale| locale.split(['_', '-', '.', '@']).next())
+            .unwrap_or_default()
+            .to_lowercase();
+
+        let translation = TRANSLATIONS
+            .iter()
+            .find(|(code, _)| *code == language)
+            .map_or(ENGLISH, |(_, source)| source);
+        Catalog {
+            messages: parse(translation),
+            fallback: parse(ENGLISH),
+        }
+    }
+}
+
+/// Reads `id = text` lines, skipping blank lines and `#` comments; a small
+/// subset of the Fluent syntax.
+fn parse(source: &'static str) -> HashMap<&'static str, &'static str> {
+    source
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(id, text)| (id.trim(), text.trim()))
+        .collect()
+}
+
+/// Looks up a translated message: `t!("image-saved", path = file.display())`.
+macro_rules! t {
+    ($id:literal $(, $name:ident = $value:expr)* $(,)?) => {
+        $crate::i18n::message(
+            $id,
+            &[$((stringify!($name), &$value as &dyn std::fmt::Display)),*],
+        )
+    };
+}
+
+pub(crate) use t;
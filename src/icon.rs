@@ -0,0 +1,137 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use image::imageops::FilterType;
+
+use crate::azure::Client;
+use crate::i18n::t;
+use crate::output::{self, Saver};
+use crate::process;
+use crate::request::ImageRequest;
+use crate::spinner;
+
+/// Sizes written as separate PNG files.
+const PNG_SIZES: [u32; 7] = [16, 32, 48, 64, 128, 256, 512];
+/// Sizes bundled into the `.ico`, which Windows picks from.
+const ICO_SIZES: [u32; 4] = [16, 32, 48, 256];
+/// `.icns` entry types holding PNG data, with their sizes.
+const ICNS_TYPES: [(&[u8; 4], u32); 7] = [
+    (b"icp4", 16),
+    (b"icp5", 32),
+    (b"icp6", 64),
+    (b"ic07", 128),
+    (b"ic08", 256),
+    (b"ic09", 512),
+    (b"ic10", 1024),
+];
+
+/// Generates a square image for `req` and derives an icon set from it in
+/// `<name>_icons/` next to the saved image.
+pub fn run(
+    client: &Client,
+    req: &ImageRequest,
+    saver: &Saver,
+) -> Result<(), Box<dyn std::error::Error>> {
+    saver.preflight(req)?;
+    let sp = spinner::Spinner::start(t!("calling-api"));
+    let data = saver.fetch(client, req, &sp);
+    drop(sp);
+    let data = data?;
+
+    let first = data.first().ok_or("The response contained no images")?;
+    let image = output::decode(req, first)?;
+    for filename in saver.save(req, &data)? {
+        println!("{}", t!("image-saved", path = filename.display()));
+    }
+
+    let dir = saver
+        .out_dir()?
+        .join(format!("{}_icons", saver.base_name(&req.prompt)));
+    write_set(&dir, &image)?;
+    println!("{}", t!("icon-set-saved", path = dir.display()));
+    Ok(())
+}
+
+/// Writes `icon-<n>x<n>.png` for every size plus `favicon.ico` and `icon.icns`.
+fn write_set(dir: &Path, image: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    fs::create_dir_all(dir)?;
+    let img = image::load_from_memory(image)?;
+    let side = img.width().min(img.height());
+    let img = img.resize_to_fill(side, side, FilterType::Lanczos3);
+
+    let sizes: BTreeSet<u32> = PNG_SIZES
+        .iter()
+        .chain(&ICO_SIZES)
+        .chain(ICNS_TYPES.iter().map(|(_, size)| size))
+        .copied()
+        .collect();
+    let rendered: BTreeMap<u32, Vec<u8>> = sizes
+        .into_iter()
+        .map(|size| {
+            let resized = img.resize_exact(size, size, FilterType::Lanczos3);
+            Ok((size, process::encode_png(&resized)?))
+        })
+        .collect::<Result<_, Box<dyn std::error::Error>>>()?;
+
+    for size in PNG_SIZES {
+        output::write_atomic(
+            &dir.join(format!("icon-{size}x{size}.png")),
+            &rendered[&size],
+        )?;
+    }
+    let ico: Vec<(u32, &[u8])> = ICO_SIZES
+        .iter()
+        .map(|size| (*size, rendered[size].as_slice()))
+        .collect();
+    output::write_atomic(&dir.join("favicon.ico"), &ico_file(&ico))?;
+    let icns: Vec<(&[u8; 4], &[u8])> = ICNS_TYPES
+        .iter()
+        .map(|(kind, size)| (*kind, rendered[size].as_slice()))
+        .collect();
+    output::write_atomic(&dir.join("icon.icns"), &icns_file(&icns))?;
+    Ok(())
+}
+
+/// An ICO file with PNG-compressed entries, understood since Windows Vista.
+fn ico_file(images: &[(u32, &[u8])]) -> Vec<u8> {
+    let count = u16::try_from(images.len()).unwrap_or(u16::MAX);
+    let mut out = Vec::new();
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&count.to_le_bytes());
+
+    let mut offset = 6 + 16 * images.len();
+    for (size, png) in images {
+        // 256 does not fit in a byte and is stored as 0
+        let side = u8::try_from(*size).unwrap_or(0);
+        out.extend_from_slice(&[side, side, 0, 0]);
+        out.extend_from_slice(&1u16.to_le_bytes());
+        out.extend_from_slice(&32u16.to_le_bytes());
+        out.extend_from_slice(&u32::try_from(png.len()).unwrap_or(u32::MAX).to_le_bytes());
+        out.extend_from_slice(&u32::try_from(offset).unwrap_or(u32::MAX).to_le_bytes());
+        offset += png.len();
+    }
+    for (_, png) in images {
+        out.extend_from_slice(png);
+    }
+    out
+}
+
+/// An Apple icon file, every entry holding PNG data.
+fn icns_file(images: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+    let total: usize = 8 + images.iter().map(|(_, png)| 8 + png.len()).sum::<usize>();
+    let mut out = Vec::with_capacity(total);
+    out.extend_from_slice(b"icns");
+    out.extend_from_slice(&u32::try_from(total).unwrap_or(u32::MAX).to_be_bytes());
+    for (kind, png) in images {
+        out.extend_from_slice(*kind);
+        out.extend_from_slice(
+            &u32::try_from(8 + png.len())
+                .unwrap_or(u32::MAX)
+                .to_be_bytes(),
+        );
+        out.extend_from_slice(png);
+    }
+    out
+}
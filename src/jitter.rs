@@ -0,0 +1,68 @@
+use std::hash::{BuildHasher, RandomState};
+
+use crate::azure::ImageData;
+use crate::i18n::t;
+use crate::provider::Provider;
+use crate::request::ImageRequest;
+use crate::spinner::Spinner;
+
+/// Used when the config sets no `jitter` pool under `[prompt]`.
+const DEFAULT_POOL: [&str; 12] = [
+    "soft morning light",
+    "dramatic lighting",
+    "golden hour",
+    "overcast light",
+    "muted colors",
+    "vivid colors",
+    "pastel palette",
+    "high contrast",
+    "shallow depth of field",
+    "wide angle",
+    "close-up",
+    "film grain",
+];
+
+/// Style fragments added to the prompt, a different one for every image of
+/// a request, so a run gives varied candidates instead of near copies.
+pub struct Jitter {
+    pool: Vec<String>,
+}
+
+impl Jitter {
+    pub fn new(pool: &[String]) -> Jitter {
+        let pool = if pool.is_empty() {
+            DEFAULT_POOL
+                .iter()
+                .map(|fragment| fragment.to_string())
+                .collect()
+        } else {
+            pool.to_vec()
+        };
+        Jitter { pool }
+    }
+
+    /// Generates the images of `req` one at a time, each with a fragment
+    /// from the pool appended to the prompt. Fragments only repeat once the
+    /// pool is used up.
+    pub fn generate(
+        &self,
+        client: &dyn Provider,
+        req: &ImageRequest,
+        progress: &Spinner,
+    ) -> Result<Vec<ImageData>, Box<dyn std::error::Error>> {
+        // Ordering by a randomly keyed hash shuffles without a rand crate
+        let state = RandomState::new();
+        let mut pool: Vec<&str> = self.pool.iter().map(String::as_str).collect();
+        pool.sort_by_key(|fragment| state.hash_one(fragment));
+
+        let mut data = Vec::with_capacity(req.count.into());
+        for (i, fragment) in (0..req.count).zip(pool.into_iter().cycle()) {
+            let mut single = req.clone();
+            single.count = 1;
+            single.prompt = format!("{}, {fragment}", req.prompt);
+            progress.set_message(t!("jitter-generating", index = i + 1, fragment = fragment));
+            data.extend(client.generate(&single)?.data);
+        }
+        Ok(data)
+    }
+}
@@ -0,0 +1,81 @@
+use std::path::Path;
+
+use figment::{
+    Figment,
+    providers::{Format, Toml},
+};
+use serde_json::{Map, Value};
+
+use crate::config::ConfigError;
+
+/// Reads a job file into imgmc arguments, see [`args`].
+pub fn from_file(path: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    if !path.exists() {
+        let msg = format!("Job file not found at: {}", path.display());
+        return Err(ConfigError(msg).into());
+    }
+    let job: Map<String, Value> = Figment::new().merge(Toml::file(path)).extract()?;
+    Ok(args(job, path.parent().unwrap_or(Path::new("")))?)
+}
+
+/// Turns a job into imgmc arguments, so it can set anything the command line
+/// can: `key = value` becomes `--key value`, `true` a bare `--key`, arrays
+/// repeat the flag and `prompt` is the prompt. Tables such as `[output]`
+/// only group keys. Relative paths are looked up in `dir`.
+pub fn args(job: Map<String, Value>, dir: &Path) -> Result<Vec<String>, ConfigError> {
+    let mut args = Vec::new();
+    let mut prompt = None;
+    for (key, value) in flatten(job) {
+        if key == "prompt" {
+            prompt = Some(text(&key, value)?);
+            continue;
+        }
+        let flag = format!("--{}", key.replace('_', "-"));
+        match value {
+            Value::Bool(true) => args.push(flag),
+            Value::Bool(false) => {}
+            Value::Array(values) => {
+                for value in values {
+                    args.extend([flag.clone(), relative_to(dir, text(&key, value)?)]);
+                }
+            }
+            value => args.extend([flag, relative_to(dir, text(&key, value)?)]),
+        }
+    }
+    // After `--`, so a prompt starting with a dash is not taken for a flag
+    if let Some(prompt) = prompt {
+        args.extend(["--".into(), prompt]);
+    }
+    Ok(args)
+}
+
+/// The keys of `job` with those of its tables moved to the top level.
+fn flatten(job: Map<String, Value>) -> Vec<(String, Value)> {
+    job.into_iter()
+        .flat_map(|(key, value)| match value {
+            Value::Object(table) => flatten(table),
+            value => vec![(key, value)],
+        })
+        .collect()
+}
+
+fn text(key: &str, value: Value) -> Result<String, ConfigError> {
+    match value {
+        Value::String(text) => Ok(text),
+        Value::Number(number) => Ok(number.to_string()),
+        Value::Bool(flag) => Ok(flag.to_string()),
+        _ => Err(ConfigError(format!(
+            "Unsupported value for {key} in the job"
+        ))),
+    }
+}
+
+/// Resolves values naming files next to the job file, such as references,
+/// so a job runs the same from any directory.
+fn relative_to(dir: &Path, value: String) -> String {
+    let path = dir.join(&value);
+    if Path::new(&value).is_relative() && path.exists() {
+        return std::path::absolute(&path).map_or(value, |path| path.display().to_string());
+    }
+    value
+}
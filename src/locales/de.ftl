@@ -0,0 +1,73 @@
+# Deutsche Meldungen. Platzhalter werden als { $name } geschrieben.
+
+error = Fehler: { $error }
+no-prompt = Kein Prompt angegeben; als Argument übergeben oder --edit-prompt verwenden
+config-not-found = Konfigurationsdatei nicht gefunden: { $path }; `imgmc config init` legt eine an
+azure-config-missing = Azure-Konfiguration fehlt
+config-invalid = Ungültige Konfiguration in { $file }: { $error }
+config-invalid-key = Ungültige Konfiguration in { $file } bei `{ $key }`: { $error }
+config-unknown-key = Unbekannter Schlüssel in { $file }: `{ $key }`
+config-version-unsupported = { $path } hat Konfigurationsversion { $version }, dieses imgmc liest nur bis Version { $supported }; imgmc aktualisieren
+config-example = Erwartet wird etwa:
+config-init-hint = `imgmc config init --force` erzeugt eine kommentierte Beispielkonfiguration zum Neuanfangen
+config-exists = { $path } existiert bereits; mit --force ersetzen
+config-created = { $path } geschrieben; zum Loslegen den Abschnitt [azure] ausfüllen
+
+calling-api = API wird aufgerufen...
+describing-image = Bild wird beschrieben...
+still-waiting = Warte auf die API, { $seconds } Sekunden vergangen
+video-status = Video { $status }, { $seconds } Sekunden vergangen
+watching = Beobachte { $path } auf Änderungen
+
+image-saved = Bild gespeichert unter: { $path }
+animation-saved = Animation gespeichert unter: { $path }
+video-saved = Video gespeichert unter: { $path }
+asset-saved = Asset gespeichert unter: { $path }
+job-submitted = In die Warteschlange gestellt als Auftrag { $id }
+schedule-added = Zeitplan { $name } hinzugefügt
+image-uploaded = Bild hochgeladen nach: { $url }
+comparison-saved = Vergleich gespeichert unter: { $path }
+report-saved = Bericht gespeichert unter: { $path }
+icon-set-saved = Icon-Satz gespeichert unter: { $path }
+run-log-saved = Protokoll gespeichert unter: { $path }
+manifest-saved = Manifest gespeichert unter: { $path }
+cleaned = { $count } Generierungen gelöscht, { $size }
+clean-dry-run = Würde { $count } Generierungen löschen, { $size }
+clean-no-limit = --older-than, --max-size oder beides angeben
+invalid-age = Ungültiges Alter { $value }, erwartet wird eine Zahl und s, m, h, d oder w
+invalid-size = Ungültige Größe { $value }, erwartet wird eine Zahl und K, M, G oder T
+state-exported = Zustand exportiert nach: { $path }
+state-imported = Zustand importiert aus: { $path }
+state-exists = { $path } existiert bereits; mit --force ersetzen
+state-secrets-missing = Geheimnisse wurden beim Export weggelassen; bitte in { $path } wieder eintragen
+signature-saved = Signatur gespeichert unter: { $path }
+
+generation-finished = Bildgenerierung abgeschlossen
+generation-failed = Bildgenerierung fehlgeschlagen
+saved-count = { $count } Bild(er) gespeichert
+generation-failed-with = Generierung fehlgeschlagen: { $error }
+image-save-failed = Bild { $index } konnte nicht gespeichert werden: { $error }
+post-save-failed = Befehl nach dem Speichern fehlgeschlagen: { $error }
+alt-text-failed = Alternativtext konnte nicht geschrieben werden: { $error }
+regenerating-for-text = Bild { $index } zeigt den Text nicht, wird neu erzeugt ({ $attempt }/{ $attempts })...
+text-not-found = Bild { $index } zeigt den erwarteten Text weiterhin nicht; gefunden: { $found }
+regenerating-for-score = Bild { $index } erhielt { $score } Punkte, wird neu erzeugt ({ $attempt }/{ $attempts })...
+jitter-generating = Erzeuge Bild { $index } mit "{ $fragment }"
+low-score-kept = Bild { $index } erhielt { $score } Punkte, weniger als { $min }, wird trotzdem behalten
+low-score-dropped = Bild { $index } erhielt { $score } Punkte, weniger als { $min }, wird verworfen
+script-hook-failed = Skript-Hook fehlgeschlagen: { $error }
+webhook-failed = Senden an den Webhook fehlgeschlagen: { $error }
+
+prompt-too-long = Der Prompt hat { $len } Zeichen, erlaubt sind { $max }; kürzen oder --truncate angeben
+prompt-blocked = Der Prompt enthält { $entry }, was vom Administrator gesperrt ist
+pii-scrubbed = { $count } personenbezogene Angaben im Prompt maskiert:
+pii-confirm = Diesen Prompt senden? [J/n]
+pii-cancelled = Abgebrochen; es wurde nichts gesendet
+prompt-truncated = Warnung: Prompt von { $len } auf { $max } Zeichen gekürzt
+prompt-near-limit = Warnung: Der Prompt hat { $len } Zeichen und ist nahe am Limit von { $max }
+content-filter-flagged = Warnung: Inhaltsfilter hat Bild { $index } markiert: { $categories }
+not-tileable = Warnung: Bild { $index } zeigt beim Kacheln Nähte an den Kanten { $edges }
+
+dir-not-writable = Ausgabeverzeichnis { $dir } ist nicht beschreibbar: { $error }
+not-enough-space = Nicht genug freier Speicher in { $dir }: { $needed } MB benötigt, { $available } MB verfügbar
+corrupt-image = Beschädigtes Bild in der Antwort: { $reason }
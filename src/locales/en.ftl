@@ -0,0 +1,73 @@
+# User-facing messages. Placeholders are written { $name }.
+
+error = Error: { $error }
+no-prompt = No prompt given; pass one as an argument or use --edit-prompt
+config-not-found = Config file not found at: { $path }; run `imgmc config init` to create one
+azure-config-missing = Azure configuration is missing
+config-invalid = Invalid config in { $file }: { $error }
+config-invalid-key = Invalid config in { $file } at `{ $key }`: { $error }
+config-unknown-key = Unknown key in { $file }: `{ $key }`
+config-version-unsupported = { $path } has config version { $version }, but this imgmc only reads up to version { $supported }; update imgmc
+config-example = Expected something like:
+config-init-hint = Run `imgmc config init --force` to start over from a commented example config
+config-exists = { $path } already exists; pass --force to replace it
+config-created = Wrote { $path }; fill in the [azure] section to get started
+
+calling-api = Calling API...
+describing-image = Describing image...
+still-waiting = Waiting for API, { $seconds } seconds elapsed
+video-status = Video { $status }, { $seconds } seconds elapsed
+watching = Watching { $path } for changes
+
+image-saved = Image saved to: { $path }
+animation-saved = Animation saved to: { $path }
+video-saved = Video saved to: { $path }
+asset-saved = Asset saved to: { $path }
+job-submitted = Queued as job { $id }
+schedule-added = Added schedule { $name }
+image-uploaded = Image uploaded to: { $url }
+comparison-saved = Comparison saved to: { $path }
+report-saved = Report saved to: { $path }
+icon-set-saved = Icon set saved to: { $path }
+run-log-saved = Run log saved to: { $path }
+manifest-saved = Manifest saved to: { $path }
+cleaned = Deleted { $count } generations, { $size }
+clean-dry-run = Would delete { $count } generations, { $size }
+clean-no-limit = Pass --older-than, --max-size or both
+invalid-age = Invalid age { $value }, expected a number and s, m, h, d or w
+invalid-size = Invalid size { $value }, expected a number and K, M, G or T
+state-exported = State exported to: { $path }
+state-imported = State imported from: { $path }
+state-exists = { $path } already exists; pass --force to replace it
+state-secrets-missing = Secrets were left out on export; fill them in again in { $path }
+signature-saved = Signature saved to: { $path }
+
+generation-finished = Image generation finished
+generation-failed = Image generation failed
+saved-count = Saved { $count } image(s)
+generation-failed-with = Generation failed: { $error }
+image-save-failed = Could not save image { $index }: { $error }
+post-save-failed = Post-save command failed: { $error }
+alt-text-failed = Could not write alt text: { $error }
+regenerating-for-text = Image { $index } does not show the text, regenerating ({ $attempt }/{ $attempts })...
+text-not-found = Image { $index } still does not show the expected text; found: { $found }
+regenerating-for-score = Image { $index } scored { $score }, regenerating ({ $attempt }/{ $attempts })...
+jitter-generating = Generating image { $index } with "{ $fragment }"
+low-score-kept = Image { $index } scored { $score }, below { $min }, keeping it anyway
+low-score-dropped = Image { $index } scored { $score }, below { $min }, dropping it
+script-hook-failed = Script hook failed: { $error }
+webhook-failed = Posting to webhook failed: { $error }
+
+prompt-too-long = Prompt has { $len } characters, the limit is { $max }; shorten it or pass --truncate
+prompt-blocked = The prompt matches { $entry }, which is blocked by the administrator
+pii-scrubbed = Masked { $count } pieces of personal data in the prompt:
+pii-confirm = Send this prompt? [Y/n]
+pii-cancelled = Cancelled; nothing was sent
+prompt-truncated = Warning: prompt truncated from { $len } to { $max } characters
+prompt-near-limit = Warning: prompt is { $len } characters, close to the limit of { $max }
+content-filter-flagged = Warning: content filter flagged image { $index }: { $categories }
+not-tileable = Warning: image { $index } will show seams when tiled at the { $edges } edges
+
+dir-not-writable = Output directory { $dir } is not writable: { $error }
+not-enough-space = Not enough free space in { $dir }: { $needed } MB needed, { $available } MB available
+corrupt-image = Corrupt image in response: { $reason }
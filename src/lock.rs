@@ -0,0 +1,32 @@
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+
+/// An advisory lock shared by all imgmc processes, for read-modify-write
+/// access to a store such as the schedules or the prompt history. It is held
+/// until dropped.
+///
+/// The lock is taken on `<store>.lock` rather than the store itself, as
+/// stores are replaced atomically and a lock on the old file would not keep
+/// anyone from reading the new one.
+pub struct StoreLock(File);
+
+impl StoreLock {
+    /// Waits until no other process holds the lock for `store`.
+    pub fn acquire(store: &Path) -> std::io::Result<StoreLock> {
+        let mut path = store.as_os_str().to_owned();
+        path.push(".lock");
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+        file.lock()?;
+        Ok(StoreLock(file))
+    }
+}
+
+impl Drop for StoreLock {
+    fn drop(&mut self) {
+        let _ = self.0.unlock();
+    }
+}
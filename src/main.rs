@@ -1,236 +1,1619 @@
-use std::fs::File;
-use std::io::Write;
-
-use base64::Engine;
-use base64::engine::general_purpose::STANDARD as BASE64_STD;
-use clap::Parser;
-use clap::ValueEnum;
-use figment::{
-    Figment,
-    providers::{Format, Toml},
-};
-use serde::Deserialize;
-use slug::slugify;
-use ureq::unversioned::multipart::Form;
+use std::io::{BufRead, IsTerminal};
 
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+
+use i18n::t;
+use request::{Background, Fit, ImageQuality, ImageRequest, Moderation};
+
+mod alt;
+mod animate;
+mod asset;
+mod audit;
+mod azure;
+mod c2pa;
+mod cassette;
+mod clean;
+mod compare;
+mod config;
+mod debug_log;
+mod editor;
+mod emit;
+mod encode;
+mod exit;
+mod hooks;
+mod i18n;
+mod icon;
+mod jitter;
+mod job;
+mod lock;
+mod manifest;
+mod markdown;
+mod matrix;
+mod metrics;
+mod mock;
+mod notify;
+mod ocr;
+mod output;
+mod palette;
+mod pii;
+mod pipeline;
+mod plugin;
+mod png;
+mod process;
+mod provenance;
+mod provider;
+mod queue;
+mod redact;
+mod repl;
+mod request;
+mod run_log;
+mod schedule;
+mod score;
+mod script;
+mod serve;
+mod sheet;
+mod signing;
+mod size;
 mod spinner;
+mod state;
+#[cfg(feature = "stub-server")]
+mod stub;
+mod telemetry;
+mod update;
+mod upload;
+mod vault;
+mod video;
+mod wallpaper;
+#[cfg(feature = "wasm")]
+mod wasm;
+mod watch;
+mod webhook;
 
-#[derive(Deserialize)]
-struct ImageData {
-    #[serde(rename = "b64_json")]
-    b64_json: String,
-}
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
 
-#[derive(Deserialize)]
-struct GenerationResponse {
-    data: Vec<ImageData>,
-}
+    /// Print plain progress lines instead of animating a spinner, e.g. for screen readers
+    #[arg(long, visible_alias = "no-animation", global = true)]
+    no_spinner: bool,
+
+    /// Fail on config keys imgmc does not know, such as misspelled ones
+    #[arg(long, global = true)]
+    strict_config: bool,
+
+    #[command(flatten)]
+    client: ClientOptions,
 
-#[derive(clap::ValueEnum, Clone)]
-enum Provider {
-    Azure,
-    OpenAI,
+    #[command(flatten)]
+    generate: GenerateArgs,
 }
 
-#[derive(ValueEnum, Clone)]
-enum ImageQuality {
-    High,
-    Medium,
-    Low,
+/// Settings for the API client, shared by every subcommand that makes requests.
+#[derive(Args)]
+struct ClientOptions {
+    /// Append a redacted JSON record of every HTTP exchange to this file
+    #[arg(long, global = true, value_name = "PATH")]
+    debug_log: Option<std::path::PathBuf>,
+
+    /// Leave out the [prompt] prefix and suffix from the config
+    #[arg(long, global = true)]
+    no_affixes: bool,
+
+    /// Report sizes and timings of every API request
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    /// Extra field for the request body, overriding [azure.extra_params]; JSON values allowed
+    #[arg(
+        long = "param",
+        global = true,
+        value_name = "NAME=VALUE",
+        value_parser = azure::parse_param
+    )]
+    params: Vec<(String, serde_json::Value)>,
+
+    /// Extra header for every API request, in addition to [http] headers
+    #[arg(
+        long = "header",
+        global = true,
+        value_name = "NAME: VALUE",
+        value_parser = azure::parse_header
+    )]
+    headers: Vec<(String, String)>,
+
+    /// Write every image request and its response to this file
+    #[arg(long, global = true, value_name = "PATH", conflicts_with = "replay")]
+    record: Option<std::path::PathBuf>,
+
+    /// Answer image requests from a file written by --record instead of the API
+    #[arg(long, global = true, value_name = "PATH")]
+    replay: Option<std::path::PathBuf>,
+
+    /// Opened from --record or --replay, shared by all clients
+    #[arg(skip)]
+    cassette: Option<std::sync::Arc<cassette::Cassette>>,
 }
 
-impl std::fmt::Display for ImageQuality {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let quality_str = match self {
-            ImageQuality::High => "high",
-            ImageQuality::Medium => "medium",
-            ImageQuality::Low => "low",
-        };
-        write!(f, "{quality_str}")
-    }
+#[derive(Subcommand)]
+enum Command {
+    /// Expose image generation over a local HTTP endpoint
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8787")]
+        http: std::net::SocketAddr,
+
+        #[command(flatten)]
+        output: OutputOptions,
+    },
+    /// Regenerate whenever a prompt file changes
+    Watch {
+        /// File containing the prompt; outputs are written next to it
+        file: std::path::PathBuf,
+
+        #[command(flatten)]
+        options: ImageOptions,
+
+        #[command(flatten)]
+        output: OutputOptions,
+    },
+    /// Read prompts interactively, with history kept across sessions
+    Repl {
+        #[command(flatten)]
+        options: ImageOptions,
+
+        #[command(flatten)]
+        output: OutputOptions,
+    },
+    /// Generate every combination of the given qualities and resolutions
+    Matrix {
+        prompt: String,
+
+        /// Comma-separated qualities to try
+        #[arg(long, value_delimiter = ',', default_value = "high")]
+        quality: Vec<ImageQuality>,
+
+        /// Comma-separated resolutions to try, e.g. 1024x1024,1536x1024
+        #[arg(
+            long,
+            value_delimiter = ',',
+            default_value = "1024x1024",
+            value_name = "WxH"
+        )]
+        resolution: Vec<size::Size>,
+
+        #[command(flatten)]
+        options: RequestOptions,
+
+        #[command(flatten)]
+        output: OutputOptions,
+    },
+    /// Generate the same prompt with several deployments and compare them side by side
+    Compare {
+        prompt: String,
+
+        /// Azure deployment to include; repeat for every deployment to compare
+        #[arg(long, required = true, value_name = "NAME")]
+        deployment: Vec<String>,
+
+        #[command(flatten)]
+        options: ImageOptions,
+
+        #[command(flatten)]
+        output: OutputOptions,
+    },
+    /// Generate a video with the Sora deployment set as video_deployment
+    Video {
+        prompt: String,
+
+        /// Length of the video
+        #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u8).range(1..=20))]
+        seconds: u8,
+
+        #[arg(long, default_value = "1280x720", value_name = "WxH")]
+        resolution: size::Size,
+
+        /// Container to save the video in; WebM is converted with ffmpeg
+        #[arg(long, default_value = "mp4")]
+        container: video::Container,
+
+        /// Base file name instead of one derived from the prompt
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Write alt text for an image with the vision model, saved next to it and in PNG metadata
+    Alt { file: std::path::PathBuf },
+    /// Generate a depth map or 3D model with a provider that has such a model
+    Asset {
+        prompt: String,
+
+        #[arg(long, default_value = "depth")]
+        kind: asset::Kind,
+
+        /// Provider to generate with, usually a plugin
+        #[arg(short, long, value_parser = provider::parse)]
+        provider: String,
+
+        /// Image to derive the asset from, e.g. to estimate its depth
+        #[arg(long)]
+        reference: Option<std::path::PathBuf>,
+
+        /// Base file name instead of one derived from the prompt
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Write a generation prompt for an existing image using a vision model
+    Describe {
+        image: std::path::PathBuf,
+
+        /// Generate new images from the description right away
+        #[arg(long)]
+        then_generate: bool,
+
+        #[command(flatten)]
+        options: ImageOptions,
+
+        #[command(flatten)]
+        output: OutputOptions,
+    },
+    /// Generate a square image and derive PNG, .ico and .icns icons from it
+    Icon {
+        prompt: String,
+
+        #[command(flatten)]
+        options: ImageOptions,
+
+        #[command(flatten)]
+        output: OutputOptions,
+    },
+    /// Generate an image at the screen resolution and make it the desktop background
+    Wallpaper {
+        prompt: String,
+
+        #[command(flatten)]
+        options: ImageOptions,
+
+        #[command(flatten)]
+        output: OutputOptions,
+    },
+    /// Replace fenced imgmc blocks in a Markdown file with generated images
+    Md {
+        /// Markdown file; images are saved next to it
+        file: std::path::PathBuf,
+
+        #[command(flatten)]
+        options: ImageOptions,
+
+        #[command(flatten)]
+        output: OutputOptions,
+    },
+    /// Generate as described by a TOML job file, whose keys are imgmc's flags
+    Run { file: std::path::PathBuf },
+    /// Generate from job objects on stdin, one JSON object per line, printing a result line each
+    Jobs {
+        /// Read the jobs from stdin, the only source so far
+        #[arg(long)]
+        stdin: bool,
+    },
+    /// Run the generate, edit and post-processing steps defined in a TOML file
+    Pipeline {
+        file: std::path::PathBuf,
+
+        #[command(flatten)]
+        output: OutputOptions,
+    },
+    /// List the deployments that can generate images
+    Models,
+    /// Show the format and size of an image file
+    Inspect {
+        file: std::path::PathBuf,
+
+        /// Report provenance data such as Content Credentials and AI source markers
+        #[arg(long)]
+        provenance: bool,
+    },
+    /// Run generations in the background
+    Queue {
+        #[command(subcommand)]
+        command: QueueCommand,
+    },
+    /// Queue generations at set times, like cron
+    Schedule {
+        #[command(subcommand)]
+        command: ScheduleCommand,
+    },
+    /// Set up the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Bundle config, prompt history and schedules into an archive such as state.tar.zst
+    Export {
+        /// Archive to write; tar compresses it as the extension says
+        file: std::path::PathBuf,
+    },
+    /// Restore what imgmc export bundled, e.g. on a new machine
+    ImportState {
+        file: std::path::PathBuf,
+
+        /// Replace existing files
+        #[arg(long)]
+        force: bool,
+    },
+    /// Read the audit log enabled under [audit]
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommand,
+    },
+    /// Imitate the Azure OpenAI endpoints with placeholder images, for tests
+    #[cfg(feature = "stub-server")]
+    #[command(hide = true)]
+    Stub {
+        /// Address to listen on; port 0 picks a free one
+        #[arg(long, default_value = "127.0.0.1:0")]
+        http: std::net::SocketAddr,
+    },
+    /// Delete old generations with their thumbnails, labeled copies and alt text
+    Clean {
+        /// Delete generations older than this, e.g. 30d, 12h or 2w
+        #[arg(long, value_name = "AGE", value_parser = clean::parse_age)]
+        older_than: Option<std::time::Duration>,
+
+        /// Then delete the oldest until the rest takes up at most this, e.g. 20G
+        #[arg(long, value_name = "SIZE", value_parser = clean::parse_size)]
+        max_size: Option<u64>,
+
+        /// Directory to clean, including its subdirectories
+        #[arg(long, default_value = ".")]
+        dir: std::path::PathBuf,
+
+        /// Only list what would be deleted
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Write the newest image in the current directory to stdout
+    Last {
+        /// Print its path instead
+        #[arg(long)]
+        path: bool,
+    },
+    /// Replace this binary with the latest release from GitHub
+    SelfUpdate {
+        /// Only report whether a newer release exists
+        #[arg(long)]
+        check: bool,
+    },
 }
 
-#[derive(ValueEnum, Clone)]
-enum Background {
-    #[value(name = "auto")]
-    Auto,
-    #[value(name = "transparent")]
-    Transparent,
-    #[value(name = "opaque")]
-    Opaque,
+#[derive(Subcommand)]
+enum QueueCommand {
+    /// Queue a generation, taking the same arguments as imgmc itself
+    Submit {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        args: Vec<String>,
+    },
+    /// List all jobs, or show one
+    Status { id: Option<String> },
+    /// Copy the images of a finished job into a directory
+    Fetch {
+        id: String,
+
+        /// Directory to copy into
+        #[arg(long, default_value = ".")]
+        to: std::path::PathBuf,
+    },
+    /// Work through the queue; started by submit
+    #[command(hide = true)]
+    Work,
 }
 
-impl std::fmt::Display for Background {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(self.to_possible_value().unwrap().get_name())
-    }
+#[derive(Subcommand)]
+enum ScheduleCommand {
+    /// Add a schedule with a cron expression such as "0 9 * * *" and imgmc arguments
+    Add {
+        cron: String,
+
+        /// Name for listing and removing the schedule
+        #[arg(long)]
+        name: String,
+
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        args: Vec<String>,
+    },
+    /// List all schedules
+    List,
+    /// Remove a schedule
+    Remove { name: String },
+    /// Queue generations as they come due until stopped
+    Run,
 }
 
-#[derive(ValueEnum, Clone)]
-enum ImageResolution {
-    #[value(name = "1024x1024")]
-    R1024x1024,
-    #[value(name = "1024x1536")]
-    R1024x1536,
-    #[value(name = "1536x1024")]
-    R1536x1024,
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Write a commented example config to fill in
+    Init {
+        /// Replace an existing config
+        #[arg(long)]
+        force: bool,
+    },
 }
 
-impl std::fmt::Display for ImageResolution {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(self.to_possible_value().unwrap().get_name())
-    }
+#[derive(Subcommand)]
+enum AuditCommand {
+    /// Print the audit log entries of a period as JSON Lines
+    Export {
+        /// First day to include, e.g. 2024-01-01
+        #[arg(long, value_name = "DATE")]
+        since: Option<chrono::NaiveDate>,
+
+        /// Last day to include
+        #[arg(long, value_name = "DATE")]
+        until: Option<chrono::NaiveDate>,
+    },
 }
 
+/// The flags read from a job file by `imgmc run`.
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
-struct Cli {
-    #[clap(short, long)]
-    provider: Provider,
+#[command(name = "imgmc run", no_binary_name = true)]
+struct JobArgs {
+    #[command(flatten)]
+    generate: GenerateArgs,
+}
+
+#[derive(Args)]
+struct GenerateArgs {
+    /// Provider to generate with: azure, openai, mock or a plugin, an
+    /// `imgmc-provider-<name>` executable on PATH
+    #[clap(short, long, required = true, value_parser = provider::parse)]
+    provider: Option<String>,
+
+    /// Prompt text; opens $EDITOR instead when left out on a terminal
+    prompt: Option<String>,
+
+    /// Compose the prompt in $EDITOR, starting from the given prompt if any
+    #[arg(long)]
+    edit_prompt: bool,
+
+    /// Generate one image set per {a,b,c} alternative in the prompt
+    #[arg(long)]
+    expand: bool,
+
+    /// Mask email addresses, phone numbers and names in the prompt, asking before sending
+    #[arg(long)]
+    scrub_pii: bool,
+
+    /// Show a desktop notification when generation finishes or fails
+    #[arg(long)]
+    notify: bool,
 
-    prompt: String,
+    /// Write a run-<timestamp>.log with the prompt, parameters, timing, files and warnings
+    #[arg(long)]
+    run_log: bool,
 
+    /// Write checksums of the saved images to SHA256SUMS, or manifest.json with =json
+    #[arg(long, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "sums")]
+    manifest: Option<manifest::Format>,
+
+    /// Sign the manifest with GPG, using the key set under [signing] if any
+    #[arg(long, requires = "manifest")]
+    sign: bool,
+
+    /// Generate one image per seed, e.g. 1000..1010, with providers that take seeds
+    #[arg(
+        long,
+        value_name = "START..END",
+        value_parser = request::parse_seed_range,
+        conflicts_with = "count"
+    )]
+    seed_range: Option<std::ops::RangeInclusive<u64>>,
+
+    #[command(flatten)]
+    options: ImageOptions,
+
+    #[command(flatten)]
+    output: OutputOptions,
+}
+
+#[derive(Args)]
+struct ImageOptions {
     #[arg(long, default_value_t = ImageQuality::High)]
     quality: ImageQuality,
 
-    #[arg(long, default_value_t = ImageResolution::R1024x1024)]
-    resolution: ImageResolution,
+    /// Size of the saved images, e.g. 1536x1024
+    #[arg(long, default_value = "1024x1024", value_name = "WxH")]
+    resolution: size::Size,
+
+    /// Aspect ratio such as 16:9, mapped to the closest supported resolution
+    #[arg(
+        long,
+        value_name = "W:H",
+        conflicts_with_all = ["resolution", "portrait", "landscape", "square"]
+    )]
+    ar: Option<size::AspectRatio>,
+
+    /// Shorthand for the closest supported 2:3 resolution
+    #[arg(long, conflicts_with_all = ["resolution", "landscape", "square"])]
+    portrait: bool,
+
+    /// Shorthand for the closest supported 3:2 resolution
+    #[arg(long, conflicts_with_all = ["resolution", "square"])]
+    landscape: bool,
+
+    /// Shorthand for the closest supported 1:1 resolution
+    #[arg(long, conflicts_with = "resolution")]
+    square: bool,
+
+    /// Social media size; generates the nearest supported size and crops it to fit
+    #[arg(
+        long,
+        conflicts_with_all = ["resolution", "ar", "portrait", "landscape", "square"]
+    )]
+    preset: Option<size::Preset>,
+
+    #[command(flatten)]
+    request: RequestOptions,
+}
+
+impl ImageOptions {
+    fn into_request(self, prompt: &str, client: &dyn provider::Provider) -> ImageRequest {
+        let ratio = |width, height| Some(size::AspectRatio { width, height });
+        let shape = if self.portrait {
+            ratio(2, 3)
+        } else if self.landscape {
+            ratio(3, 2)
+        } else if self.square {
+            ratio(1, 1)
+        } else {
+            self.ar
+        };
+        let resolution = match self.preset {
+            Some(preset) => preset.size(),
+            None => shape.map_or(self.resolution, |ratio| client.closest_size(ratio)),
+        };
+
+        let mut req = self.request.into_request(prompt, self.quality, resolution);
+        if self.preset.is_some() {
+            req.fit = Some(req.fit.unwrap_or(Fit::Crop));
+        }
+        req
+    }
+}
+
+/// Everything about a request but its quality and size, which `imgmc matrix`
+/// takes lists of instead.
+#[derive(Args)]
+struct RequestOptions {
+    /// Generate the nearest supported size and crop or pad it when --resolution is unsupported
+    #[arg(long)]
+    fit: Option<Fit>,
 
     #[arg(long, default_value_t = Background::Auto)]
     background: Background,
 
+    /// Cut prompts that exceed the model's length limit instead of failing
+    #[arg(long)]
+    truncate: bool,
+
+    /// Content moderation strictness (gpt-image-1 only)
+    #[arg(long)]
+    moderation: Option<Moderation>,
+
     #[arg(long, short, default_value_t = 1)]
     count: u8,
 
     #[clap(long, short)]
     reference: Option<std::path::PathBuf>,
+
+    /// Ask for a seamless texture and warn about images that show seams when tiled
+    #[arg(long)]
+    tileable: bool,
+
+    /// Colors to ask for, as #112233,#aabbcc or a .gpl or .ase palette file
+    #[arg(long, value_name = "COLORS|FILE")]
+    palette: Option<palette::Palette>,
+
+    /// Map every pixel of the saved images to the nearest --palette color
+    #[arg(long, requires = "palette")]
+    quantize: bool,
+
+    /// Value for a {name} placeholder in the prompt; {env:NAME} reads the environment
+    #[arg(long = "var", value_name = "NAME=VALUE", value_parser = request::parse_var)]
+    vars: Vec<(String, String)>,
 }
 
-#[derive(Deserialize)]
-struct AzureConfig {
-    api_base: String,
-    api_key: String,
-    deployment: String,
+impl RequestOptions {
+    fn into_request(
+        self,
+        prompt: &str,
+        quality: ImageQuality,
+        resolution: size::Size,
+    ) -> ImageRequest {
+        let mut req = ImageRequest {
+            prompt: String::new(),
+            truncate_prompt: self.truncate,
+            quality,
+            resolution,
+            fit: self.fit,
+            background: self.background,
+            moderation: self.moderation,
+            count: self.count,
+            reference: self.reference,
+            tileable: self.tileable,
+            palette: self.palette,
+            quantize: self.quantize,
+            vars: self.vars,
+            seed: None,
+        };
+        req.set_prompt(prompt);
+        req
+    }
 }
 
-#[derive(Deserialize)]
-struct Config {
-    azure: Option<AzureConfig>,
+#[derive(Args)]
+struct OutputOptions {
+    /// Base file name for saved images instead of one derived from the prompt
+    #[arg(long)]
+    name: Option<String>,
+
+    /// Command to run for every saved image; {file}, {prompt} and {provider} are substituted
+    #[arg(long)]
+    post_cmd: Option<String>,
+
+    /// Upload saved images to s3://, gs://, az://account/container or sftp://user@host/path
+    #[arg(long)]
+    upload: Option<upload::Destination>,
+
+    /// Only keep the uploaded copies
+    #[arg(long)]
+    no_local: bool,
+
+    /// Print an embed snippet for every image, using the prompt as alt text
+    #[arg(long)]
+    emit: Option<emit::Format>,
+
+    /// Post every image to a webhook defined under [webhooks.<name>] in the config
+    #[arg(long, value_name = "WEBHOOK_NAME")]
+    post_to: Option<String>,
+
+    /// Remove all metadata chunks so saved files carry no prompt or provider traces
+    #[arg(long)]
+    strip_metadata: bool,
+
+    /// Center-crop saved images to an aspect ratio such as 1:1 or 16:9
+    #[arg(long, value_name = "W:H")]
+    crop: Option<size::AspectRatio>,
+
+    /// Scale and crop saved images to exactly this size, e.g. 800x600
+    #[arg(long, value_name = "WxH")]
+    resize: Option<size::Size>,
+
+    /// Also write thumbnails no larger than this many pixels into thumbs/
+    #[arg(long, value_name = "PIXELS", value_parser = clap::value_parser!(u32).range(1..))]
+    thumbs: Option<u32>,
+
+    /// Fully decode every returned image before saving to catch corrupt responses
+    #[arg(long)]
+    verify_decode: bool,
+
+    /// Also write copies captioned with index, provider and settings into labeled/
+    #[arg(long)]
+    label_outputs: bool,
+
+    /// Embed C2PA Content Credentials marking the images as AI-generated (needs c2patool)
+    #[arg(long, conflicts_with = "strip_metadata")]
+    content_credentials: bool,
+
+    /// Save images under YYYY/MM/DD/ subdirectories
+    #[arg(long)]
+    date_dirs: bool,
+
+    /// Re-encode saved images, e.g. to AVIF for the web (HEIC needs heif-enc)
+    #[arg(long)]
+    format: Option<encode::Format>,
+
+    /// Quality from 1 to 100 for AVIF and HEIC [default: 80]
+    #[arg(long, value_name = "1-100", value_parser = clap::value_parser!(u8).range(1..=100))]
+    image_quality: Option<u8>,
+
+    /// Also join the images of a run into a looping GIF
+    #[arg(long, conflicts_with = "apng")]
+    gif: bool,
+
+    /// Also join the images of a run into a looping animated PNG
+    #[arg(long)]
+    apng: bool,
+
+    /// How long the animation shows each image
+    #[arg(long, value_name = "MS", default_value_t = 500)]
+    frame_delay: u16,
+
+    /// Read the text in every image and regenerate those that do not show this text
+    #[arg(long, value_name = "TEXT")]
+    verify_text: Option<String>,
+
+    /// How often an image may be regenerated for --verify-text
+    #[arg(long, value_name = "N", default_value_t = 3, requires = "verify_text")]
+    verify_attempts: u8,
+
+    /// How --verify-text reads images
+    #[arg(long, default_value = "tesseract", requires = "verify_text")]
+    ocr: ocr::Engine,
+
+    /// Score every image from 0 to 100 and regenerate or drop those below this
+    #[arg(long, value_name = "0-100", value_parser = clap::value_parser!(u8).range(0..=100))]
+    min_score: Option<u8>,
+
+    /// How --min-score rates images
+    #[arg(long, default_value = "local", requires = "min_score")]
+    judge: score::Judge,
+
+    /// How often an image may be regenerated for --min-score
+    #[arg(long, value_name = "N", default_value_t = 2, requires = "min_score")]
+    score_attempts: u8,
+
+    /// Keep images that stay below --min-score, with a warning, instead of dropping them
+    #[arg(long, requires = "min_score")]
+    keep_low_scores: bool,
+
+    /// Generate the images of a request one by one, each with its own style fragment
+    #[arg(long)]
+    jitter: bool,
+
+    /// Ask the vision deployment for alt text, kept in <image>.alt.txt and PNG metadata
+    #[arg(long)]
+    alt: bool,
+
+    /// Save into an Obsidian vault's attachment folder and print and copy ![[wikilinks]]
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["no_local", "emit"])]
+    vault: Option<std::path::PathBuf>,
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
+impl OutputOptions {
+    fn into_saver(
+        self,
+        config: &config::Config,
+        options: &ClientOptions,
+    ) -> Result<output::Saver, Box<dyn std::error::Error>> {
+        let post_save = self.post_cmd.or_else(|| config.hooks.post_save.clone());
 
-    let xdg_dirs = xdg::BaseDirectories::with_prefix("imgmc");
-    let xdg_file = xdg_dirs
-        .get_config_file("config.toml")
-        .ok_or("Could not get config file")?;
+        let mut upload = match (self.upload, &config.upload.destination) {
+            (Some(destination), _) => Some(destination),
+            (None, Some(destination)) => Some(destination.parse().map_err(config::ConfigError)?),
+            (None, None) => None,
+        };
+        if let Some(upload::Destination::Sftp { identity, .. }) = &mut upload {
+            identity.clone_from(&config.upload.ssh_key);
+        }
+        let no_local = self.no_local || config.upload.no_local;
+        if no_local && upload.is_none() {
+            return Err("--no-local requires an upload destination".into());
+        }
+
+        let webhook = match self.post_to {
+            Some(name) => match config.webhooks.get(&name) {
+                Some(webhook) => Some(webhook.clone()),
+                None => {
+                    let msg = format!("No webhook named {name} in the config");
+                    return Err(config::ConfigError(msg).into());
+                }
+            },
+            None => None,
+        };
+
+        let format = self.format.or(config.output.format).unwrap_or_default();
+        let image_quality = match self.image_quality.or(config.output.quality) {
+            Some(quality @ 1..=100) => quality,
+            Some(_) => {
+                let msg = "output.quality must be between 1 and 100";
+                return Err(config::ConfigError(msg.into()).into());
+            }
+            None => encode::DEFAULT_QUALITY,
+        };
 
-    if !std::path::Path::new(&xdg_file).exists() {
-        eprintln!("Config file not found at: {}", xdg_file.display());
-        std::process::exit(1);
+        let animate = match (self.gif, self.apng) {
+            (true, _) => Some(animate::Kind::Gif),
+            (_, true) => Some(animate::Kind::Apng),
+            _ => None,
+        };
+        if animate.is_some() && format != encode::Format::Png {
+            return Err("Animations can only be made from images saved as PNG".into());
+        }
+
+        let alt_text = if self.alt {
+            Some(vision_client(config, options)?)
+        } else {
+            None
+        };
+        let verify_text = match self.verify_text {
+            Some(expected) => Some(ocr::TextCheck {
+                expected,
+                attempts: self.verify_attempts,
+                vision: match self.ocr {
+                    ocr::Engine::Vision => Some(vision_client(config, options)?),
+                    ocr::Engine::Tesseract => None,
+                },
+            }),
+            None => None,
+        };
+        let quality_gate = match self.min_score {
+            Some(min_score) => Some(score::QualityGate {
+                min_score,
+                attempts: self.score_attempts,
+                keep_weak: self.keep_low_scores,
+                vision: match self.judge {
+                    score::Judge::Vision => Some(vision_client(config, options)?),
+                    score::Judge::Local => None,
+                },
+            }),
+            None => None,
+        };
+
+        Ok(output::Saver {
+            // Only used as scratch space when nothing is kept locally
+            dir: match &self.vault {
+                Some(vault) => vault::attachment_dir(vault).map_err(config::ConfigError)?,
+                None if no_local => std::env::temp_dir(),
+                None => std::path::PathBuf::new(),
+            },
+            name: self.name,
+            post_save: post_save.map(hooks::PostSave::new),
+            script: script::Hooks::load()?,
+            upload,
+            no_local,
+            emit: match &self.vault {
+                Some(_) => Some(emit::Format::Wikilink),
+                None => self.emit,
+            },
+            clipboard: self.vault.is_some(),
+            webhook,
+            strip_metadata: self.strip_metadata,
+            process: process::PostProcess {
+                crop: self.crop,
+                resize: self.resize,
+            },
+            thumbs: self.thumbs,
+            label: self.label_outputs,
+            content_credentials: self.content_credentials.then(|| config.c2pa.clone()),
+            verify_pixels: self.verify_decode,
+            date_dirs: self.date_dirs || config.output.date_dirs,
+            subdir: None,
+            provider: azure::NAME.to_string(),
+            format,
+            image_quality,
+            animate,
+            frame_delay: self.frame_delay,
+            alt_text,
+            verify_text,
+            quality_gate,
+            jitter: self
+                .jitter
+                .then(|| jitter::Jitter::new(&config.prompt.jitter)),
+            audit: audit::AuditLog::open(&config.audit)?,
+        })
     }
+}
 
-    let config: Config = Figment::new().merge(Toml::file(xdg_file)).extract()?;
+/// A client for the chat deployment set as `vision_deployment`, sending the
+/// same headers as the image client.
+fn vision_client(
+    config: &config::Config,
+    options: &ClientOptions,
+) -> Result<azure::Client, Box<dyn std::error::Error>> {
+    let azure = config.azure()?;
+    if azure.vision_deployment.is_none() {
+        let msg = "Set vision_deployment under [azure] to read images";
+        return Err(config::ConfigError(msg.into()).into());
+    }
+    client_for(config, azure, options)
+}
+
+fn main() -> std::process::ExitCode {
+    redact::install_panic_hook();
+    let _telemetry = telemetry::init();
+    match run() {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", t!("error", error = redact::redact(&e.to_string())));
+            exit::code(e.as_ref())
+        }
+    }
+}
 
-    let azure_config = match config.azure {
-        Some(cfg) => cfg,
-        None => {
-            eprintln!("Azure configuration is missing");
-            std::process::exit(1);
+/// Parses the command line. With `--help`, the plugins found on `PATH` are
+/// listed for `--provider`; other runs never search `PATH` for them.
+fn parse_cli() -> Cli {
+    let mut command = Cli::command();
+    let help = ["-h", "--help", "help"];
+    if std::env::args_os().any(|arg| help.iter().any(|h| arg == *h)) {
+        let plugins = plugin::discover();
+        if !plugins.is_empty() {
+            let list = |arg: clap::Arg| {
+                let help = arg.get_help().map(ToString::to_string).unwrap_or_default();
+                arg.help(format!("{help}; found: {}", plugins.join(", ")))
+            };
+            command = command
+                .mut_arg("provider", list)
+                .mut_subcommand("asset", |asset| asset.mut_arg("provider", list));
         }
+    }
+    Cli::from_arg_matches(&command.get_matches()).unwrap_or_else(|e| e.exit())
+}
+
+fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli = parse_cli();
+    if cli.no_spinner {
+        spinner::disable_animation();
+    }
+
+    if let Some(Command::SelfUpdate { check }) = cli.command {
+        return update::run(check);
+    }
+    if let Some(Command::Last { path }) = cli.command {
+        return last(path);
+    }
+    if let Some(Command::Clean {
+        older_than,
+        max_size,
+        dir,
+        dry_run,
+    }) = &cli.command
+    {
+        return clean::run(dir, *older_than, *max_size, *dry_run);
+    }
+    #[cfg(feature = "stub-server")]
+    if let Some(Command::Stub { http }) = cli.command {
+        return stub::run(http);
+    }
+    if let Some(Command::Inspect { file, provenance }) = &cli.command {
+        return inspect(file, *provenance);
+    }
+    if let Some(Command::Queue { command }) = &cli.command {
+        return match command {
+            QueueCommand::Submit { args } => queue::submit(args),
+            QueueCommand::Status { id } => queue::status(id.as_deref()),
+            QueueCommand::Fetch { id, to } => queue::fetch(id, to),
+            QueueCommand::Work => queue::work(),
+        };
+    }
+    if let Some(Command::Schedule { command }) = &cli.command {
+        return match command {
+            ScheduleCommand::Add { cron, name, args } => schedule::add(name, cron, args),
+            ScheduleCommand::List => schedule::list(),
+            ScheduleCommand::Remove { name } => schedule::remove(name),
+            ScheduleCommand::Run => schedule::run(),
+        };
+    }
+
+    if let Some(Command::ImportState { file, force }) = &cli.command {
+        return state::import(file, *force);
+    }
+    if let Some(Command::Config {
+        command: ConfigCommand::Init { force },
+    }) = cli.command
+    {
+        let path = config::init(force)?;
+        eprintln!("{}", t!("config-created", path = path.display()));
+        return Ok(());
+    }
+
+    // A job file stands in for the generation flags, while global flags such
+    // as --replay still come from the command line
+    if let Some(Command::Run { file }) = &cli.command {
+        let args = job::from_file(file)?;
+        cli.generate = JobArgs::try_parse_from(args)
+            .map_err(|e| format!("{}: {}", file.display(), e.to_string().trim_end()))?
+            .generate;
+        cli.command = None;
+    }
+
+    cli.client.cassette = match (&cli.client.record, &cli.client.replay) {
+        (Some(path), _) => Some(cassette::Cassette::record(path).into()),
+        (_, Some(path)) => Some(cassette::Cassette::replay(path)?.into()),
+        (None, None) => None,
     };
 
-    let api_key = azure_config.api_key;
-    let api_base = azure_config.api_base;
-    let api_version = "2025-04-01-preview";
-    let deployment = azure_config.deployment;
-
-    let gen_url = format!(
-        "{}/openai/deployments/{}/images/generations?api-version={}",
-        api_base, deployment, api_version
-    );
-
-    let edits_url = format!(
-        "{}/openai/deployments/{}/images/edits?api-version={}",
-        api_base, deployment, api_version
-    );
-
-    let size = cli.resolution.to_string();
-    let quality = cli.quality.to_string();
-    let background = cli.background.to_string();
-    let n = cli.count;
-
-    let sp = spinner::Spinner::start("Calling API...");
-
-    let gen_resp: GenerationResponse = if let Some(ref_path) = cli.reference.as_ref() {
-        let n = n.to_string();
-
-        // Use the edits endpoint with multipart/form-data
-        let form = Form::new()
-            .text("prompt", &cli.prompt)
-            .text("n", &n)
-            .text("size", &size)
-            .text("quality", &quality)
-            .text("background", &background)
-            .text("output_format", "png")
-            .file("image", ref_path)?;
-
-        ureq::post(&edits_url)
-            .header("api-key", &api_key)
-            .send(form)?
-            .body_mut()
-            .read_json::<GenerationResponse>()?
+    // The mock provider and replays need no setup, so scripts and tests can
+    // run anywhere
+    let offline = cli.client.replay.is_some()
+        || cli.command.is_none() && cli.generate.provider.as_deref() == Some(mock::NAME);
+    let config = match config::load(cli.strict_config) {
+        Err(e) if offline && e.is::<config::ConfigError>() => config::Config::default(),
+        config => config?,
+    };
+    i18n::init(config.locale.as_deref());
+
+    match cli.command {
+        Some(Command::Serve { http, output }) => {
+            let saver = output.into_saver(&config, &cli.client)?;
+            let client = client(&config, &cli.client)?;
+            return serve::run(http, client, saver);
+        }
+        Some(Command::Watch {
+            file,
+            options,
+            output,
+        }) => {
+            let saver = output.into_saver(&config, &cli.client)?;
+            let client = client(&config, &cli.client)?;
+            return watch::run(&file, &client, options.into_request("", &client), saver);
+        }
+        Some(Command::Repl { options, output }) => {
+            let saver = output.into_saver(&config, &cli.client)?;
+            let client = client(&config, &cli.client)?;
+            return repl::run(&client, options.into_request("", &client), saver);
+        }
+        Some(Command::Matrix {
+            prompt,
+            quality,
+            resolution,
+            options,
+            output,
+        }) => {
+            let saver = output.into_saver(&config, &cli.client)?;
+            let client = client(&config, &cli.client)?;
+            // Every combination sets its own quality and resolution
+            let req = options.into_request(
+                &prompt,
+                ImageQuality::default(),
+                size::Size::new(1024, 1024),
+            );
+            return matrix::run(&client, req, &quality, &resolution, saver);
+        }
+        Some(Command::Compare {
+            prompt,
+            deployment,
+            options,
+            output,
+        }) => {
+            let saver = output.into_saver(&config, &cli.client)?;
+            let mut clients = Vec::with_capacity(deployment.len());
+            for name in deployment {
+                let mut azure = config.azure()?.clone();
+                azure.deployment.clone_from(&name);
+                // Deployments usually serve different models
+                azure.model = None;
+                let client = client_for(&config, &azure, &cli.client)?;
+                clients.push((name, client));
+            }
+            let req = options.into_request(&prompt, &clients[0].1);
+            return compare::run(&clients, &req, saver);
+        }
+        Some(Command::Video {
+            prompt,
+            seconds,
+            resolution,
+            container,
+            name,
+        }) => {
+            let client = client(&config, &cli.client)?;
+            let audit = audit::AuditLog::open(&config.audit)?;
+            let provider: &dyn provider::Provider = &client;
+            let req = video::VideoRequest {
+                prompt,
+                seconds,
+                resolution,
+            };
+            let sp = spinner::Spinner::start(t!("calling-api"));
+            let mp4 = provider.generate_video(&req, &|status| sp.set_message(status));
+            drop(sp);
+            let saved = mp4.and_then(|mp4| {
+                let bytes = container.convert(mp4)?;
+                let stem = name.unwrap_or_else(|| output::prompt_stem(&req.prompt));
+                let dir = std::path::Path::new("");
+                output::write_new(dir, &stem, 1, container.extension(), &bytes)
+            });
+            if let Some(audit) = &audit {
+                let outcome = saved.as_ref().map(std::slice::from_ref);
+                let outcome = outcome.map_err(ToString::to_string);
+                let resolution = Some(req.resolution);
+                if let Err(e) =
+                    audit.record_asset(azure::NAME, "video", &req.prompt, resolution, outcome)
+                {
+                    run_log::warn(format!("Could not write to the audit log: {e}"));
+                }
+            }
+            println!("{}", t!("video-saved", path = saved?.display()));
+            return Ok(());
+        }
+        Some(Command::Asset {
+            prompt,
+            kind,
+            provider: provider_name,
+            reference,
+            name,
+        }) => {
+            let provider = provider(Some(&provider_name), &config, &cli.client)?;
+            let audit = audit::AuditLog::open(&config.audit)?;
+            let sp = spinner::Spinner::start(t!("calling-api"));
+            let bytes = provider.generate_asset(kind, &prompt, reference.as_deref());
+            drop(sp);
+            let saved = bytes.and_then(|bytes| {
+                kind.check(&bytes).map_err(output::CorruptImage)?;
+                let stem = name.unwrap_or_else(|| output::prompt_stem(&prompt));
+                let dir = std::path::Path::new("");
+                output::write_new(dir, &stem, 1, kind.extension(), &bytes)
+            });
+            if let Some(audit) = &audit {
+                let outcome = saved.as_ref().map(std::slice::from_ref);
+                let outcome = outcome.map_err(ToString::to_string);
+                if let Err(e) =
+                    audit.record_asset(&provider_name, kind.name(), &prompt, None, outcome)
+                {
+                    run_log::warn(format!("Could not write to the audit log: {e}"));
+                }
+            }
+            println!("{}", t!("asset-saved", path = saved?.display()));
+            return Ok(());
+        }
+        Some(Command::Alt { file }) => {
+            let client = client(&config, &cli.client)?;
+            let sp = spinner::Spinner::start(t!("describing-image"));
+            let text = client.alt_text(&file);
+            drop(sp);
+            let text = text?;
+            alt::store(&file, &text)?;
+            println!("{text}");
+            return Ok(());
+        }
+        Some(Command::Describe {
+            image,
+            then_generate,
+            options,
+            output,
+        }) => {
+            let saver = output.into_saver(&config, &cli.client)?;
+            let client = client(&config, &cli.client)?;
+            let sp = spinner::Spinner::start(t!("describing-image"));
+            let description = client.describe(&image);
+            drop(sp);
+            let description = description?;
+            println!("{description}");
+
+            if then_generate {
+                let mut req = options.into_request(&description, &client);
+                client.validate(&mut req)?;
+                let sp = spinner::Spinner::start(t!("calling-api"));
+                let saved = saver.generate(&client, &req, &sp);
+                drop(sp);
+                for filename in saved? {
+                    println!("{}", t!("image-saved", path = filename.display()));
+                }
+            }
+            return Ok(());
+        }
+        Some(Command::Icon {
+            prompt,
+            options,
+            output,
+        }) => {
+            let saver = output.into_saver(&config, &cli.client)?;
+            let client = client(&config, &cli.client)?;
+            let mut req = options.into_request(&prompt, &client);
+            req.resolution = size::Size::new(1024, 1024);
+            req.count = 1;
+            client.validate(&mut req)?;
+            return icon::run(&client, &req, &saver);
+        }
+        Some(Command::Wallpaper {
+            prompt,
+            options,
+            output,
+        }) => {
+            let mut saver = output.into_saver(&config, &cli.client)?;
+            saver.dir = match &config.wallpaper.dir {
+                Some(dir) => dir.clone(),
+                None => wallpaper::default_dir()?,
+            };
+            let client = client(&config, &cli.client)?;
+            let mut req = options.into_request(&prompt, &client);
+            req.resolution = config
+                .wallpaper
+                .resolution
+                .or_else(wallpaper::screen_size)
+                .ok_or(
+                    "Could not detect the screen resolution; set resolution under [wallpaper]",
+                )?;
+            // Screens rarely match a size the model supports
+            req.fit = Some(req.fit.unwrap_or(Fit::Crop));
+            req.count = 1;
+            client.validate(&mut req)?;
+
+            let sp = spinner::Spinner::start(t!("calling-api"));
+            let saved = saver.generate(&client, &req, &sp);
+            drop(sp);
+            let image = saved?.into_iter().next().ok_or("No image was saved")?;
+            println!("{}", t!("image-saved", path = image.display()));
+            wallpaper::set(&image)?;
+            return Ok(());
+        }
+        Some(Command::Md {
+            file,
+            options,
+            output,
+        }) => {
+            let saver = output.into_saver(&config, &cli.client)?;
+            let client = client(&config, &cli.client)?;
+            let req = options.into_request("", &client);
+            return markdown::run(&file, &client, req, saver);
+        }
+        Some(Command::Jobs { stdin: true }) => return jobs(&config, &cli.client),
+        Some(Command::Jobs { stdin: false }) => return Err("Pass --stdin to read jobs".into()),
+        Some(Command::Pipeline { file, output }) => {
+            let pipeline = pipeline::load(&file)?;
+            let saver = output.into_saver(&config, &cli.client)?;
+            let client = client(&config, &cli.client)?;
+            return pipeline::run(&pipeline, &client, &saver);
+        }
+        // After loading the config, to know which secrets to leave out
+        Some(Command::Export { file }) => return state::export(&file),
+        Some(Command::Audit {
+            command: AuditCommand::Export { since, until },
+        }) => {
+            return audit::export(&config.audit, since, until, std::io::stdout().lock());
+        }
+        Some(Command::Models) => {
+            let client = client(&config, &cli.client)?;
+            for deployment in client.image_deployments()? {
+                println!("{}\t{}", deployment.id, deployment.model);
+            }
+            return Ok(());
+        }
+        Some(
+            Command::SelfUpdate { .. }
+            | Command::Last { .. }
+            | Command::Clean { .. }
+            | Command::Inspect { .. }
+            | Command::Queue { .. }
+            | Command::Schedule { .. }
+            | Command::Config { .. }
+            | Command::ImportState { .. }
+            | Command::Run { .. },
+        )
+        | None => {}
+        #[cfg(feature = "stub-server")]
+        Some(Command::Stub { .. }) => {}
+    }
+
+    let args = cli.generate;
+    let (mut saver, client) = setup(args.provider.as_deref(), args.output, &config, &cli.client)?;
+    let client = client.as_ref();
+    let prompt = match args.prompt {
+        Some(prompt) if !args.edit_prompt => prompt,
+        prompt if args.edit_prompt || std::io::stdin().is_terminal() => {
+            editor::compose(prompt.as_deref().unwrap_or_default())?
+        }
+        _ => return Err(t!("no-prompt").into()),
+    };
+    let prompt = if args.scrub_pii || config.prompt.scrub_pii {
+        pii::confirm(&prompt)?
+    } else {
+        prompt
+    };
+    let templates = if args.expand {
+        request::expand_alternatives(&prompt)
     } else {
-        // Use the generations endpoint with JSON
-        let body = serde_json::json!({
-            "prompt": cli.prompt,
-            "n": n,
-            "size": size,
-            "background": background,
-            "quality": quality,
-            "output_format": "png"
-        });
-
-        ureq::post(&gen_url)
-            .header("Content-Type", "application/json")
-            .header("api-key", &api_key)
-            .send_json(body)?
-            .body_mut()
-            .read_json::<GenerationResponse>()
-            .unwrap()
+        vec![prompt]
     };
 
-    drop(sp);
+    let requests = requests(client, args.options, args.seed_range, &templates)?;
 
-    // Save each returned image
-    for (i, item) in gen_resp.data.iter().enumerate() {
-        let bytes = BASE64_STD
-            .decode(&item.b64_json)
-            .map_err(|e| format!("Base64 decode failed: {e}"))?;
+    let started = chrono::Local::now();
+    let timer = std::time::Instant::now();
+    // Several prompts from --expand get a bar over all of them
+    let progress = match requests.len() {
+        1 => spinner::Spinner::start(t!("calling-api")),
+        n => spinner::Spinner::bar(t!("calling-api"), n),
+    };
+    let result = generate_all(client, &mut saver, &requests, &progress);
+    drop(progress);
 
-        let slug = slugify(&cli.prompt);
-        let trimmed_slug = if slug.len() > 50 {
-            slug[..50].to_string()
+    let out_dir = saver.out_dir()?;
+    if args.run_log {
+        let dir = if out_dir.as_os_str().is_empty() {
+            std::path::Path::new(".")
         } else {
-            slug
+            out_dir.as_path()
         };
+        let outcome = result.as_deref().map_err(ToString::to_string);
+        match run_log::write(
+            dir,
+            started,
+            timer.elapsed(),
+            &saver.provider,
+            &requests,
+            outcome,
+        ) {
+            Ok(path) => eprintln!("{}", t!("run-log-saved", path = path.display())),
+            Err(e) => eprintln!("Could not write run log: {e}"),
+        }
+    }
 
-        let mut counter = i + 1;
-        let filename = loop {
-            let candidate = format!("{trimmed_slug}_{counter}.png");
-            if !std::path::Path::new(&candidate).exists() {
-                break candidate;
-            }
-            counter = counter
-                .checked_add(1)
-                .ok_or("Counter overflow: too many files with similar names")?;
-        };
+    if let Some(format) = args.manifest
+        && let Ok(files) = &result
+    {
+        let path = manifest::write(&out_dir, files, format)?;
+        eprintln!("{}", t!("manifest-saved", path = path.display()));
+        if args.sign {
+            let signature = signing::sign(&path, config.signing.key.as_deref())?;
+            eprintln!("{}", t!("signature-saved", path = signature.display()));
+        }
+    }
+
+    if args.notify {
+        match &result {
+            Ok(files) => notify::send(
+                &t!("generation-finished"),
+                &t!("saved-count", count = files.len()),
+            ),
+            Err(e) => notify::send(&t!("generation-failed"), &e.to_string()),
+        }
+    }
+
+    for filename in result? {
+        println!("{}", t!("image-saved", path = filename.display()));
+    }
+
+    Ok(())
+}
+
+fn last(path: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let link = output::latest_links(std::path::Path::new("."))?
+        .into_iter()
+        .next();
+    let link = link.ok_or(format!("No {}.* in the current directory", output::LATEST))?;
+    let latest = std::fs::canonicalize(&link).map_err(|e| {
+        let msg = format!("{} points nowhere: {e}", link.display());
+        std::io::Error::new(e.kind(), msg)
+    })?;
+    if path {
+        println!("{}", latest.display());
+        return Ok(());
+    }
+
+    let mut stdout = std::io::stdout();
+    if stdout.is_terminal() {
+        return Err("Not writing image data to a terminal; redirect it or use --path".into());
+    }
+    std::io::copy(&mut std::fs::File::open(latest)?, &mut stdout)?;
+    Ok(())
+}
+
+fn inspect(file: &std::path::Path, provenance: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let bytes = std::fs::read(file)?;
+    let format = if bytes.starts_with(&png::SIGNATURE) {
+        "PNG"
+    } else if bytes.starts_with(&[0xff, 0xd8]) {
+        "JPEG"
+    } else if bytes.get(4..12) == Some(b"ftypavif") {
+        "AVIF"
+    } else if bytes.get(4..12) == Some(b"ftypheic") {
+        "HEIC"
+    } else {
+        "unknown"
+    };
+    println!("Format: {format}");
+    if let Some((width, height)) = png::dimensions(&bytes) {
+        println!("Size:   {width}x{height}");
+    }
+    println!("Bytes:  {}", bytes.len());
+
+    if provenance {
+        let found = provenance::inspect(&bytes);
+        if found.is_empty() {
+            println!("No provenance data found");
+        }
+        for entry in found {
+            println!("Provenance: {entry}");
+        }
+        // Worth saying, since their absence proves nothing
+        println!(
+            "Invisible watermarks such as SynthID can only be checked with the vendor's tools"
+        );
+    }
+    Ok(())
+}
+
+/// The provider `name` stands for, Azure unless given.
+fn provider(
+    name: Option<&str>,
+    config: &config::Config,
+    options: &ClientOptions,
+) -> Result<Box<dyn provider::Provider>, Box<dyn std::error::Error>> {
+    if let Some(name) = name {
+        config.policy.allow_provider(name)?;
+    }
+    Ok(match name {
+        Some(name) if !provider::BUILT_IN.contains(&name) => {
+            let mut plugin = plugin::Plugin::find(name)?;
+            plugin.script = script::Hooks::load()?;
+            plugin.policy = config.policy.clone();
+            plugin.scrub_pii = config.prompt.scrub_pii;
+            Box::new(plugin)
+        }
+        Some(mock::NAME) => {
+            let mut mock = mock::Mock::new(&config.mock);
+            mock.script = script::Hooks::load()?;
+            mock.policy = config.policy.clone();
+            mock.scrub_pii = config.prompt.scrub_pii;
+            Box::new(mock)
+        }
+        _ => Box::new(client(config, options)?),
+    })
+}
+
+fn client(
+    config: &config::Config,
+    options: &ClientOptions,
+) -> Result<azure::Client, Box<dyn std::error::Error>> {
+    match (config.azure(), &options.replay) {
+        (Err(_), Some(_)) => client_for(config, &config::AzureConfig::offline(), options),
+        (azure, _) => client_for(config, azure?, options),
+    }
+}
 
-        let mut file = File::create(&filename)?;
-        file.write_all(&bytes)?;
-        println!("Image saved to: {filename}");
+fn client_for(
+    config: &config::Config,
+    azure: &config::AzureConfig,
+    options: &ClientOptions,
+) -> Result<azure::Client, Box<dyn std::error::Error>> {
+    config.policy.allow_provider(azure::NAME)?;
+    let mut client = azure::Client::new(azure);
+    client.verbose = options.verbose;
+    client.policy = config.policy.clone();
+    client.scrub_pii = config.prompt.scrub_pii;
+    if !options.no_affixes {
+        client.affixes = config.prompt.clone();
     }
+    client.script = script::Hooks::load()?;
+    client.extra_params.extend(options.params.iter().cloned());
+    client.headers = config.http.headers.clone().into_iter().collect();
+    for (name, value) in &options.headers {
+        if redact::is_sensitive_header(name) {
+            redact::register(value);
+        }
+    }
+    client.headers.extend(options.headers.iter().cloned());
+    client.cassette.clone_from(&options.cassette);
+    if let Some(path) = &options.debug_log {
+        client.debug_log = Some(debug_log::DebugLog::open(path)?);
+    }
+    Ok(client)
+}
 
+/// The saver for `output` and the provider `name` stands for, with the
+/// saver naming the provider in metadata.
+fn setup(
+    name: Option<&str>,
+    output: OutputOptions,
+    config: &config::Config,
+    options: &ClientOptions,
+) -> Result<(output::Saver, Box<dyn provider::Provider>), Box<dyn std::error::Error>> {
+    let mut saver = output.into_saver(config, options)?;
+    let client = provider(name, config, options)?;
+    if let Some(name) = name
+        && (name == mock::NAME || !provider::BUILT_IN.contains(&name))
+    {
+        saver.provider = name.to_string();
+    }
+    Ok((saver, client))
+}
+
+/// One validated request per prompt in `templates`, or per prompt and seed
+/// with `--seed-range`.
+fn requests(
+    client: &dyn provider::Provider,
+    options: ImageOptions,
+    seeds: Option<std::ops::RangeInclusive<u64>>,
+    templates: &[String],
+) -> Result<Vec<ImageRequest>, Box<dyn std::error::Error>> {
+    let base = options.into_request("", client);
+    let mut requests = Vec::with_capacity(templates.len());
+    for template in templates {
+        let mut req = base.clone();
+        req.set_prompt(template);
+        match &seeds {
+            Some(seeds) => {
+                for seed in seeds.clone() {
+                    let mut req = req.clone();
+                    req.seed = Some(seed);
+                    client.validate(&mut req)?;
+                    requests.push(req);
+                }
+            }
+            None => {
+                client.validate(&mut req)?;
+                requests.push(req);
+            }
+        }
+    }
+    Ok(requests)
+}
+
+/// Generates and saves every request in turn and stops at the first failure.
+fn generate_all(
+    client: &dyn provider::Provider,
+    saver: &mut output::Saver,
+    requests: &[ImageRequest],
+    progress: &spinner::Spinner,
+) -> Result<Vec<std::path::PathBuf>, Box<dyn std::error::Error>> {
+    let batch = requests.iter().any(|req| req.prompt != requests[0].prompt);
+    let name = saver.name.clone();
+    let result = requests.iter().try_fold(Vec::new(), |mut saved, req| {
+        // One subdirectory per prompt keeps large batches apart
+        if batch {
+            saver.subdir = Some(output::prompt_stem(&req.prompt));
+        }
+        if let Some(seed) = req.seed {
+            saver.name.clone_from(&name);
+            saver.name = Some(format!("{}_seed{seed}", saver.base_name(&req.prompt)));
+        }
+        progress.set_message(t!("calling-api"));
+        saved.extend(saver.generate(client, req, progress)?);
+        progress.inc();
+        Ok::<_, Box<dyn std::error::Error>>(saved)
+    });
+    saver.subdir = None;
+    saver.name = name;
+    result
+}
+
+/// Reads job objects from stdin, one JSON object per line with the keys of a
+/// job file, and prints a JSON line per job with its `files` or `error`. An
+/// `id` in a job is passed through to its result. A failed job does not stop
+/// the others.
+fn jobs(
+    config: &config::Config,
+    options: &ClientOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut result = serde_json::Map::new();
+        let outcome = match serde_json::from_str::<serde_json::Map<_, _>>(&line) {
+            Ok(mut job) => {
+                if let Some(id) = job.remove("id") {
+                    result.insert("id".into(), id);
+                }
+                run_job(job, config, options)
+            }
+            Err(e) => Err(e.into()),
+        };
+        match outcome {
+            Ok(files) => result.insert("files".into(), serde_json::json!(files)),
+            Err(e) => result.insert("error".into(), redact::redact(&e.to_string()).into()),
+        };
+        println!("{}", serde_json::Value::Object(result));
+    }
     Ok(())
 }
+
+fn run_job(
+    job: serde_json::Map<String, serde_json::Value>,
+    config: &config::Config,
+    options: &ClientOptions,
+) -> Result<Vec<std::path::PathBuf>, Box<dyn std::error::Error>> {
+    let args = JobArgs::try_parse_from(job::args(job, std::path::Path::new(""))?)
+        .map_err(|e| e.to_string().trim_end().to_string())?
+        .generate;
+    let (mut saver, client) = setup(args.provider.as_deref(), args.output, config, options)?;
+    let client = client.as_ref();
+    let prompt = args.prompt.ok_or_else(|| t!("no-prompt"))?;
+    // There is no one to confirm with, as stdin carries the jobs
+    let prompt = if args.scrub_pii || config.prompt.scrub_pii {
+        pii::scrub(&prompt).0
+    } else {
+        prompt
+    };
+    let templates = if args.expand {
+        request::expand_alternatives(&prompt)
+    } else {
+        vec![prompt]
+    };
+    let requests = requests(client, args.options, args.seed_range, &templates)?;
+    let progress = spinner::Spinner::start(t!("calling-api"));
+    generate_all(client, &mut saver, &requests, &progress)
+}
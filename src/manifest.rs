@@ -0,0 +1,58 @@
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use sha2::{Digest, Sha256};
+
+use crate::output;
+
+#[derive(ValueEnum, Clone, Copy)]
+pub enum Format {
+    /// SHA256SUMS, as read by `sha256sum -c`
+    Sums,
+    /// manifest.json with path, size and hash of every file
+    Json,
+}
+
+/// Writes checksums of `files` into `dir`, with paths relative to it, and
+/// returns where. An existing manifest from an earlier run is replaced.
+pub fn write(dir: &Path, files: &[PathBuf], format: Format) -> io::Result<PathBuf> {
+    let mut entries = Vec::with_capacity(files.len());
+    for file in files {
+        let mut hasher = Sha256::new();
+        let size = io::copy(&mut File::open(file)?, &mut hasher)?;
+        let hash: String = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        let path = file.strip_prefix(dir).unwrap_or(file);
+        // Forward slashes keep the manifest usable on every platform
+        entries.push((path.to_string_lossy().replace('\\', "/"), size, hash));
+    }
+
+    let (name, contents) = match format {
+        Format::Sums => {
+            let lines: String = entries
+                .iter()
+                .map(|(path, _, hash)| format!("{hash}  {path}\n"))
+                .collect();
+            ("SHA256SUMS", lines)
+        }
+        Format::Json => {
+            let files: Vec<_> = entries
+                .iter()
+                .map(|(path, size, hash)| {
+                    serde_json::json!({ "path": path, "bytes": size, "sha256": hash })
+                })
+                .collect();
+            let json = serde_json::json!({ "files": files });
+            ("manifest.json", serde_json::to_string_pretty(&json)? + "\n")
+        }
+    };
+
+    let path = dir.join(name);
+    output::write_atomic(&path, contents.as_bytes())?;
+    Ok(path)
+}
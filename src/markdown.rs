@@ -0,0 +1,198 @@
+use std::fs;
+use std::path::Path;
+
+use figment::{
+    Figment,
+    providers::{Format as _, Toml},
+};
+use serde::Deserialize;
+
+use crate::azure::Client;
+use crate::emit;
+use crate::output::{self, Saver};
+use crate::request::{Background, ImageQuality, ImageRequest};
+use crate::size::Size;
+use crate::spinner;
+
+const FENCE: &str = "```";
+const FRONT_MATTER: &str = "+++";
+
+/// Defaults for every block, from the `[imgmc]` table of TOML front matter.
+#[derive(Deserialize, Default)]
+struct Defaults {
+    quality: Option<ImageQuality>,
+    resolution: Option<Size>,
+    background: Option<Background>,
+}
+
+#[derive(Deserialize, Default)]
+struct FrontMatter {
+    #[serde(default)]
+    imgmc: Defaults,
+}
+
+/// Generates an image for every fenced `imgmc` block in the Markdown file at
+/// `path` and replaces the block with a reference to the saved image.
+///
+/// Blocks done before a failure are still rewritten, so running it again only
+/// generates what is left.
+pub fn run(
+    path: &Path,
+    client: &Client,
+    mut req: ImageRequest,
+    mut saver: Saver,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let document = fs::read_to_string(path)?;
+    let lines: Vec<&str> = document.lines().collect();
+
+    let defaults = front_matter(&lines)?;
+    if let Some(quality) = defaults.quality {
+        req.quality = quality;
+    }
+    if let Some(resolution) = defaults.resolution {
+        req.resolution = resolution;
+    }
+    if let Some(background) = defaults.background {
+        req.background = background;
+    }
+
+    saver.dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    let blocks = blocks(&lines).map_err(|e| format!("{}: {e}", path.display()))?;
+    if blocks.is_empty() {
+        return Err(format!("No ```imgmc blocks in {}", path.display()).into());
+    }
+
+    let progress = spinner::Spinner::bar("", blocks.len());
+    let mut out = Vec::with_capacity(lines.len());
+    let mut result = Ok(());
+    let mut copied = 0;
+    for (start, end) in blocks {
+        out.extend(lines[copied..start].iter().map(ToString::to_string));
+        copied = end + 1;
+        let block = &lines[start..=end];
+        if result.is_err() {
+            out.extend(block.iter().map(ToString::to_string));
+            continue;
+        }
+
+        let prompt = lines[start + 1..end].join("\n").trim().to_string();
+        progress.set_message(prompt.as_str());
+        match generate(client, &mut req, &saver, &prompt, &progress) {
+            Ok(references) if !references.is_empty() => out.extend(references),
+            outcome => {
+                // Keep the block so the prompt is not lost
+                out.extend(block.iter().map(ToString::to_string));
+                result = outcome.map(|_| ());
+            }
+        }
+        progress.inc();
+    }
+    out.extend(lines[copied..].iter().map(ToString::to_string));
+    drop(progress);
+
+    let mut rewritten = out.join("\n");
+    if document.ends_with('\n') {
+        rewritten.push('\n');
+    }
+    output::write_atomic(path, rewritten.as_bytes())?;
+    result
+}
+
+/// The line numbers of the opening and closing fence of every `imgmc` block.
+/// A block that is never closed is an error, as it would otherwise take the
+/// rest of the document for its prompt, and the rewrite would drop it.
+fn blocks(lines: &[&str]) -> Result<Vec<(usize, usize)>, String> {
+    let mut blocks = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        if is_block_start(lines[i]) {
+            let end = lines[i + 1..]
+                .iter()
+                .position(|line| line.trim() == FENCE)
+                .map(|n| i + 1 + n)
+                .ok_or_else(|| format!("The ```imgmc block on line {} is never closed", i + 1))?;
+            blocks.push((i, end));
+            i = end;
+        }
+        i += 1;
+    }
+    Ok(blocks)
+}
+
+fn is_block_start(line: &str) -> bool {
+    line.trim().strip_prefix(FENCE).map(str::trim) == Some("imgmc")
+}
+
+/// Reads the defaults from `+++`-delimited TOML at the top of the document.
+fn front_matter(lines: &[&str]) -> Result<Defaults, Box<dyn std::error::Error>> {
+    if lines.first().map(|l| l.trim()) != Some(FRONT_MATTER) {
+        return Ok(Defaults::default());
+    }
+    let Some(end) = lines[1..].iter().position(|l| l.trim() == FRONT_MATTER) else {
+        return Ok(Defaults::default());
+    };
+    let toml = lines[1..=end].join("\n");
+    let front: FrontMatter = Figment::new().merge(Toml::string(&toml)).extract()?;
+    Ok(front.imgmc)
+}
+
+/// Generates the images for one block and returns a Markdown reference to
+/// each, relative to the document.
+fn generate(
+    client: &Client,
+    req: &mut ImageRequest,
+    saver: &Saver,
+    prompt: &str,
+    progress: &spinner::Spinner,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    if prompt.is_empty() {
+        return Err("Empty ```imgmc block".into());
+    }
+    req.set_prompt(prompt);
+    client.validate(req)?;
+    let saved = saver.generate(client, req, progress)?;
+
+    Ok(saved
+        .iter()
+        .map(|file| {
+            let target = file.strip_prefix(&saver.dir).unwrap_or(file);
+            // Forward slashes work for Markdown renderers on every platform
+            let target = target.to_string_lossy().replace('\\', "/");
+            emit::Format::Markdown.snippet(&req.prompt, &target)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_finds_every_imgmc_block() {
+        let doc = [
+            "# Title",
+            "```imgmc",
+            "a fox",
+            "```",
+            "text",
+            "  ``` imgmc ",
+            "```",
+            "end",
+        ];
+        assert_eq!(blocks(&doc), Ok(vec![(1, 3), (5, 6)]));
+    }
+
+    #[test]
+    fn blocks_skips_other_code_and_empty_documents() {
+        assert_eq!(blocks(&[]), Ok(vec![]));
+        assert_eq!(blocks(&["```rust", "fn main() {}", "```"]), Ok(vec![]));
+    }
+
+    #[test]
+    fn blocks_rejects_an_unterminated_fence() {
+        let doc = ["```imgmc", "a fox", "```", "", "```imgmc", "a wolf"];
+        let err = blocks(&doc).unwrap_err();
+        assert!(err.contains("line 5"), "{err}");
+        assert!(blocks(&["```imgmc"]).is_err());
+    }
+}
@@ -0,0 +1,68 @@
+use crate::azure::Client;
+use crate::i18n::t;
+use crate::output::Saver;
+use crate::redact;
+use crate::request::{ImageQuality, ImageRequest};
+use crate::size::Size;
+use crate::spinner;
+
+/// Generates `req` once for every combination of quality and resolution,
+/// naming each output after its parameters, e.g. `fox_low_1024x1024_1.png`.
+///
+/// Every combination is validated on its own, as limits depend on quality
+/// and resolution. A failed combination does not stop the others; the run
+/// only fails at the end if any of them did.
+pub fn run(
+    client: &Client,
+    req: ImageRequest,
+    qualities: &[ImageQuality],
+    resolutions: &[Size],
+    mut saver: Saver,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let base = saver.base_name(&req.prompt);
+    let total = qualities.len() * resolutions.len();
+    let mut failed = 0;
+    let progress = spinner::Spinner::bar("", total);
+
+    for quality in qualities {
+        for &resolution in resolutions {
+            let mut combination = req.clone();
+            combination.quality = quality.clone();
+            combination.resolution = resolution;
+            saver.name = Some(format!("{base}_{quality}_{resolution}"));
+
+            progress.set_message(format!("Generating {quality} at {resolution}..."));
+            let result = generate(client, &saver, combination, &progress);
+            progress.suspend(|| match result {
+                Ok(files) => {
+                    for filename in files {
+                        println!("{}", t!("image-saved", path = filename.display()));
+                    }
+                }
+                Err(e) => {
+                    let e = redact::redact(&e.to_string());
+                    eprintln!("{quality} at {resolution} failed: {e}");
+                    failed += 1;
+                }
+            });
+            progress.inc();
+        }
+    }
+
+    drop(progress);
+
+    if failed > 0 {
+        return Err(format!("{failed} of {total} combinations failed").into());
+    }
+    Ok(())
+}
+
+fn generate(
+    client: &Client,
+    saver: &Saver,
+    mut req: ImageRequest,
+    progress: &spinner::Spinner,
+) -> Result<Vec<std::path::PathBuf>, Box<dyn std::error::Error>> {
+    client.validate(&mut req)?;
+    saver.generate(client, &req, progress)
+}
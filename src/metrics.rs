@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds in seconds of the latency histogram buckets. Generating an
+/// image takes seconds to minutes, so the buckets start at one second.
+const LATENCY_BUCKETS: [f64; 9] = [1.0, 2.5, 5.0, 10.0, 20.0, 30.0, 60.0, 120.0, 300.0];
+
+/// Counters for `imgmc serve`, rendered in the Prometheus text format.
+#[derive(Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    requests: BTreeMap<u16, u64>,
+    failures: BTreeMap<&'static str, u64>,
+    latency_buckets: [u64; LATENCY_BUCKETS.len()],
+    latency_sum: f64,
+    latency_count: u64,
+    spend_usd: f64,
+}
+
+impl Metrics {
+    fn inner(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Records a finished generation request.
+    pub fn request(&self, status: u16, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let mut inner = self.inner();
+        *inner.requests.entry(status).or_default() += 1;
+        for (count, bound) in inner.latency_buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= bound {
+                *count += 1;
+            }
+        }
+        inner.latency_sum += seconds;
+        inner.latency_count += 1;
+    }
+
+    pub fn failure(&self, class: &'static str) {
+        *self.inner().failures.entry(class).or_default() += 1;
+    }
+
+    /// Adds the list price of a generation, if the model has one.
+    pub fn spend(&self, usd: Option<f64>) {
+        self.inner().spend_usd += usd.unwrap_or_default();
+    }
+
+    pub fn render(&self) -> String {
+        let inner = self.inner();
+        let mut out = String::new();
+
+        let _ = writeln!(
+            out,
+            "# HELP imgmc_requests_total Generation requests by HTTP status."
+        );
+        let _ = writeln!(out, "# TYPE imgmc_requests_total counter");
+        for (status, count) in &inner.requests {
+            let _ = writeln!(out, "imgmc_requests_total{{status=\"{status}\"}} {count}");
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP imgmc_failures_total Failed generation requests by class."
+        );
+        let _ = writeln!(out, "# TYPE imgmc_failures_total counter");
+        for (class, count) in &inner.failures {
+            let _ = writeln!(out, "imgmc_failures_total{{class=\"{class}\"}} {count}");
+        }
+
+        let name = "imgmc_request_duration_seconds";
+        let _ = writeln!(out, "# HELP {name} Time taken by generation requests.");
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        for (count, bound) in inner.latency_buckets.iter().zip(LATENCY_BUCKETS) {
+            let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {count}");
+        }
+        let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {}", inner.latency_count);
+        let _ = writeln!(out, "{name}_sum {}", inner.latency_sum);
+        let _ = writeln!(out, "{name}_count {}", inner.latency_count);
+
+        let name = "imgmc_estimated_spend_usd_total";
+        let _ = writeln!(out, "# HELP {name} Spend estimated from list prices.");
+        let _ = writeln!(out, "# TYPE {name} counter");
+        let _ = writeln!(out, "{name} {}", inner.spend_usd);
+        out
+    }
+}
@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use sha2::{Digest, Sha256};
+
+use crate::azure::{GenerationResponse, ImageData};
+use crate::config::{MockConfig, PolicyConfig};
+use crate::pii;
+use crate::provider::{self, Provider};
+use crate::request::ImageRequest;
+use crate::script::Hooks;
+use crate::sheet;
+use crate::size::{AspectRatio, Size};
+
+pub const NAME: &str = "mock";
+
+/// Makes placeholder images locally instead of calling an API, for scripts
+/// and tests that should run without network access or keys.
+///
+/// Every image is a solid color derived from the prompt and its index with
+/// the prompt written on it, so the same request always gives the same files.
+pub struct Mock {
+    /// Waited before every response, to stand in for a real API.
+    latency: Duration,
+    pub script: Option<Hooks>,
+    pub policy: PolicyConfig,
+    pub scrub_pii: bool,
+}
+
+impl Mock {
+    pub fn new(config: &MockConfig) -> Mock {
+        Mock {
+            latency: Duration::from_millis(config.latency_ms),
+            script: None,
+            policy: PolicyConfig::default(),
+            scrub_pii: false,
+        }
+    }
+}
+
+impl Provider for Mock {
+    fn validate(&self, req: &mut ImageRequest) -> Result<(), String> {
+        if let Some(script) = &self.script {
+            script.before_request(req)?;
+        }
+        if self.scrub_pii {
+            pii::scrub_request(req);
+        }
+        self.policy.check(req)
+    }
+
+    fn closest_size(&self, ratio: AspectRatio) -> Size {
+        provider::megapixel_size(ratio)
+    }
+
+    fn generate(
+        &self,
+        req: &ImageRequest,
+    ) -> Result<GenerationResponse, Box<dyn std::error::Error>> {
+        std::thread::sleep(self.latency);
+        let data = (0..req.count)
+            .map(|i| {
+                let mut hash = Sha256::new()
+                    .chain_update(req.prompt.as_bytes())
+                    .chain_update([i]);
+                // Each seed gives other images, and the same seed the same ones
+                if let Some(seed) = req.seed {
+                    hash.update(seed.to_le_bytes());
+                }
+                let hash = hash.finalize();
+                let png =
+                    sheet::placeholder(req.resolution, [hash[0], hash[1], hash[2]], &req.prompt)?;
+                Ok(ImageData::from_png(&png))
+            })
+            .collect::<Result<_, Box<dyn std::error::Error>>>()?;
+        Ok(GenerationResponse { data })
+    }
+}
@@ -0,0 +1,13 @@
+use notify_rust::Notification;
+
+/// Shows a desktop notification, only warning when none can be displayed.
+pub fn send(summary: &str, body: &str) {
+    if let Err(e) = Notification::new()
+        .appname("imgmc")
+        .summary(summary)
+        .body(body)
+        .show()
+    {
+        eprintln!("Could not show notification: {e}");
+    }
+}
@@ -0,0 +1,110 @@
+use std::fs;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use clap::ValueEnum;
+
+use crate::azure::{Client, ImageData};
+use crate::i18n::t;
+use crate::output;
+use crate::provider::{self, Provider};
+use crate::request::ImageRequest;
+use crate::run_log;
+use crate::spinner::Spinner;
+
+/// Ways to read the text in an image.
+#[derive(ValueEnum, Clone, Copy)]
+pub enum Engine {
+    /// The `tesseract` command, free and local.
+    Tesseract,
+    /// The chat deployment set as `vision_deployment`, better with stylized
+    /// lettering.
+    Vision,
+}
+
+/// Text that has to appear in the generated images.
+pub struct TextCheck {
+    pub expected: String,
+    /// How often an image may be regenerated before it is kept as it is.
+    pub attempts: u8,
+    /// Reads the text instead of tesseract.
+    pub vision: Option<Client>,
+}
+
+impl TextCheck {
+    /// Reads every image in `data` and regenerates those that do not show the
+    /// expected text, replacing them in place. Images still wrong after all
+    /// attempts are kept with a warning.
+    pub fn ensure(
+        &self,
+        client: &dyn Provider,
+        req: &ImageRequest,
+        data: &mut [ImageData],
+        progress: &Spinner,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        for (i, item) in data.iter_mut().enumerate() {
+            for attempt in 1.. {
+                let found = self.read(&output::decode(req, item)?)?;
+                if contains(&found, &self.expected) {
+                    break;
+                }
+                if attempt > self.attempts {
+                    let found = found.split_whitespace().collect::<Vec<_>>().join(" ");
+                    run_log::warn(t!("text-not-found", index = i + 1, found = found));
+                    break;
+                }
+                progress.set_message(t!(
+                    "regenerating-for-text",
+                    index = i + 1,
+                    attempt = attempt,
+                    attempts = self.attempts
+                ));
+                *item = provider::regenerate_one(client, req)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read(&self, png: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
+        match &self.vision {
+            Some(client) => client.read_text(png),
+            None => tesseract(png),
+        }
+    }
+}
+
+/// Whether `found` contains `expected`, ignoring case, punctuation and how
+/// the words are spaced or broken across lines.
+fn contains(found: &str, expected: &str) -> bool {
+    let normalize = |text: &str| {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let expected = normalize(expected);
+    format!(" {} ", normalize(found)).contains(&format!(" {expected} "))
+}
+
+fn tesseract(png: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
+    // Several batches may check their images at the same time
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    let input = std::env::temp_dir().join(format!("imgmc-ocr-{}-{n}.png", std::process::id()));
+    fs::write(&input, png)?;
+
+    let result = Command::new("tesseract")
+        .arg(&input)
+        .arg("stdout")
+        .stderr(Stdio::null())
+        .output();
+    let _ = fs::remove_file(&input);
+    match result {
+        Ok(output) if output.status.success() => {
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        }
+        Ok(output) => Err(format!("tesseract failed with {}", output.status).into()),
+        Err(e) => Err(format!("Could not run tesseract: {e}").into()),
+    }
+}
@@ -0,0 +1,739 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, ErrorKind, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64_STD;
+use base64::read::DecoderReader;
+use chrono::Local;
+use fs4::fs_std::FileExt;
+use sha2::{Digest, Sha256};
+use slug::slugify;
+
+use crate::alt;
+use crate::animate;
+use crate::audit::AuditLog;
+use crate::azure::{Client, ImageData};
+use crate::c2pa;
+use crate::config::C2paConfig;
+use crate::emit;
+use crate::encode;
+use crate::hooks::PostSave;
+use crate::i18n::t;
+use crate::jitter::Jitter;
+use crate::ocr::TextCheck;
+use crate::png;
+use crate::process::{self, PostProcess};
+use crate::provider::Provider;
+use crate::request::ImageRequest;
+use crate::run_log;
+use crate::score::QualityGate;
+use crate::script::Hooks;
+use crate::sheet;
+use crate::spinner::Spinner;
+use crate::upload::Destination;
+use crate::vault;
+use crate::webhook::Webhook;
+
+/// File stem of the pointer to the newest image, kept in the output directory
+/// with that image's extension, such as `latest.avif`.
+pub const LATEST: &str = "latest";
+
+/// Write buffer size, large enough that a multi-megabyte image takes only a
+/// few system calls.
+const WRITE_BUFFER: usize = 1 << 20;
+
+/// Base64 of the PNG signature, which starts every image the API returns.
+const PNG_BASE64_PREFIX: &str = "iVBORw0KGgo";
+
+/// An image in the API response that did not survive the trip intact, e.g.
+/// base64 cut short by a proxy.
+#[derive(Debug)]
+pub struct CorruptImage(pub String);
+
+impl std::fmt::Display for CorruptImage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&t!("corrupt-image", reason = self.0))
+    }
+}
+
+impl std::error::Error for CorruptImage {}
+
+/// Checks the container structure of a decoded image without decoding pixels.
+fn verify(bytes: &[u8]) -> Result<(), String> {
+    if bytes.starts_with(&png::SIGNATURE) {
+        png::verify(bytes)
+    } else if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+        if bytes.ends_with(&[0xff, 0xd9]) {
+            Ok(())
+        } else {
+            Err("JPEG is truncated: no end-of-image marker".into())
+        }
+    } else {
+        Err("not a PNG or JPEG image".into())
+    }
+}
+
+/// Where and how generated images end up on disk.
+pub struct Saver {
+    pub dir: PathBuf,
+    /// Overrides the prompt-derived file names.
+    pub name: Option<String>,
+    pub post_save: Option<PostSave>,
+    /// User script whose `after_save` runs for every saved image.
+    pub script: Option<Hooks>,
+    pub upload: Option<Destination>,
+    /// Remove local copies once they have been uploaded.
+    pub no_local: bool,
+    pub emit: Option<emit::Format>,
+    /// Also copy the snippets printed for `emit` to the clipboard.
+    pub clipboard: bool,
+    pub webhook: Option<Webhook>,
+    /// Drop every PNG chunk not needed to render the image.
+    pub strip_metadata: bool,
+    pub process: PostProcess,
+    /// Also write a copy no larger than this many pixels into `thumbs/`.
+    pub thumbs: Option<u32>,
+    /// Also write a copy captioned with its index and settings into `labeled/`.
+    pub label: bool,
+    /// Embed Content Credentials marking every image as AI-generated.
+    pub content_credentials: Option<C2paConfig>,
+    /// Fully decode every image before writing it, not just check its structure.
+    pub verify_pixels: bool,
+    /// Save into a `YYYY/MM/DD/` subdirectory of `dir` for the current day.
+    pub date_dirs: bool,
+    /// A further subdirectory, such as one per prompt of a batch.
+    pub subdir: Option<String>,
+    /// Name of the provider the images come from, for hooks and labels.
+    pub provider: String,
+    /// Format the images are saved in. Thumbnails and labeled copies stay PNG.
+    pub format: encode::Format,
+    /// Quality from 1 to 100 for lossy formats.
+    pub image_quality: u8,
+    /// Also join the images of a run into an animation.
+    pub animate: Option<animate::Kind>,
+    /// How long the animation shows each image.
+    pub frame_delay: u16,
+    /// Vision client that writes alt text for every image.
+    pub alt_text: Option<Client>,
+    /// Text the images must show, regenerating those that get it wrong.
+    pub verify_text: Option<TextCheck>,
+    /// Minimum score, regenerating or dropping weaker images.
+    pub quality_gate: Option<QualityGate>,
+    /// Varies the prompt for every image of a request.
+    pub jitter: Option<Jitter>,
+    /// Where every generation is recorded, whether it was saved or failed.
+    pub audit: Option<AuditLog>,
+}
+
+impl Saver {
+    /// Generates `req` with `client` and saves the images. Every subcommand
+    /// goes through here or [`Saver::fetch`], so the output options that
+    /// change what is generated apply everywhere.
+    pub fn generate(
+        &self,
+        client: &dyn Provider,
+        req: &ImageRequest,
+        progress: &Spinner,
+    ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        self.preflight(req)?;
+        let data = self.fetch(client, req, progress)?;
+        // Save each returned image; hooks and uploads may print
+        progress.suspend(|| self.save(req, &data))
+    }
+
+    /// Generates `req` with `client` without saving, varying the prompt with
+    /// `--jitter` and regenerating images that fail `--verify-text` or
+    /// `--min-score`.
+    pub fn fetch(
+        &self,
+        client: &dyn Provider,
+        req: &ImageRequest,
+        progress: &Spinner,
+    ) -> Result<Vec<ImageData>, Box<dyn std::error::Error>> {
+        let result = self.fetch_checked(client, req, progress);
+        // Successful ones are recorded once saved, with their files
+        if let Err(e) = &result {
+            self.record(req, Err(e.to_string()));
+        }
+        result
+    }
+
+    fn fetch_checked(
+        &self,
+        client: &dyn Provider,
+        req: &ImageRequest,
+        progress: &Spinner,
+    ) -> Result<Vec<ImageData>, Box<dyn std::error::Error>> {
+        let mut data = match &self.jitter {
+            Some(jitter) if req.count > 1 => jitter.generate(client, req, progress)?,
+            _ => client.generate(req)?.data,
+        };
+        if let Some(check) = &self.verify_text {
+            check.ensure(client, req, &mut data, progress)?;
+        }
+        if let Some(gate) = &self.quality_gate {
+            gate.ensure(client, req, &mut data, progress)?;
+        }
+        Ok(data)
+    }
+
+    /// Adds `req` and the files it produced or its error to the audit log,
+    /// if there is one.
+    pub fn record(&self, req: &ImageRequest, outcome: Result<&[PathBuf], String>) {
+        if let Some(audit) = &self.audit
+            && let Err(e) = audit.record(&self.provider, req, outcome)
+        {
+            run_log::warn(format!("Could not write to the audit log: {e}"));
+        }
+    }
+
+    /// Saves the images generated for `req` and records them in the audit log.
+    #[tracing::instrument(skip_all, err, fields(images = data.len()))]
+    pub fn save(
+        &self,
+        req: &ImageRequest,
+        data: &[ImageData],
+    ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        let result = self.store(req, data);
+        self.record(req, result.as_deref().map_err(ToString::to_string));
+        result
+    }
+
+    fn store(
+        &self,
+        req: &ImageRequest,
+        data: &[ImageData],
+    ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        let prompt = req.prompt.as_str();
+        let mut saved = self.write_images(req, data)?;
+        let mut snippets = Vec::new();
+
+        if let Some(kind) = self.animate
+            && let Some(dir) = saved.first().and_then(|file| file.parent())
+        {
+            let animation = kind.assemble(&saved, self.frame_delay)?;
+            let stem = format!("{}_anim", self.base_name(prompt));
+            let file = write_new(dir, &stem, 1, kind.extension(), &animation)?;
+            println!("{}", t!("animation-saved", path = file.display()));
+        }
+
+        for file in &saved {
+            if let Some(hook) = &self.post_save
+                && let Err(e) = hook.run(file, prompt, &self.provider)
+            {
+                run_log::warn(t!("post-save-failed", error = e));
+            }
+            if let Some(script) = &self.script
+                && let Err(e) = script.after_save(file, prompt, &self.provider)
+            {
+                run_log::warn(t!("script-hook-failed", error = e));
+            }
+
+            let uploaded_url = match &self.upload {
+                Some(destination) => {
+                    let url = destination.upload(file)?;
+                    println!("{}", t!("image-uploaded", url = url));
+                    Some(url)
+                }
+                None => None,
+            };
+
+            if let Some(webhook) = &self.webhook
+                && let Err(e) = webhook.post(file, prompt, uploaded_url.as_deref())
+            {
+                run_log::warn(t!("webhook-failed", error = e));
+            }
+
+            if uploaded_url.is_some() && self.no_local {
+                fs::remove_file(file)?;
+            }
+
+            let location = uploaded_url.unwrap_or_else(|| file.display().to_string());
+
+            if let Some(format) = self.emit {
+                let alt = alt::load(file);
+                let snippet = format.snippet(alt.as_deref().unwrap_or(prompt), &location);
+                println!("{snippet}");
+                snippets.push(snippet);
+            }
+        }
+
+        if self.clipboard
+            && !snippets.is_empty()
+            && let Err(e) = vault::copy_to_clipboard(&snippets.join("\n"))
+        {
+            run_log::warn(format!("Could not copy to the clipboard: {e}"));
+        }
+
+        if self.upload.is_some() && self.no_local {
+            saved.clear();
+        } else if let Some(last) = saved.last()
+            && let Err(e) = update_latest(&self.dir, last)
+        {
+            run_log::warn(format!(
+                "Could not update {LATEST}.{}: {e}",
+                extension(last)
+            ));
+        }
+
+        Ok(saved)
+    }
+
+    /// The directory images are written to, created if it does not exist yet.
+    pub fn out_dir(&self) -> std::io::Result<PathBuf> {
+        let mut dir = self.dir.clone();
+        if self.date_dirs {
+            dir.push(Local::now().format("%Y/%m/%d").to_string());
+        }
+        if let Some(subdir) = &self.subdir {
+            dir.push(subdir);
+        }
+        if dir != self.dir {
+            fs::create_dir_all(&dir)?;
+        }
+        Ok(dir)
+    }
+
+    /// Checks that `dir` can be written to and has room for the images `req`
+    /// will produce, so a generation that could not be saved is never paid for.
+    pub fn preflight(&self, req: &ImageRequest) -> std::io::Result<()> {
+        let out_dir = self.out_dir()?;
+        let dir = if out_dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            out_dir.as_path()
+        };
+
+        let probe = dir.join(format!(".imgmc-preflight.{}", std::process::id()));
+        let created = OpenOptions::new().write(true).create_new(true).open(&probe);
+        if let Err(e) = created {
+            let msg = t!("dir-not-writable", dir = dir.display(), error = e);
+            return Err(std::io::Error::new(e.kind(), msg));
+        }
+        let _ = fs::remove_file(&probe);
+
+        // Uncompressed RGBA is an upper bound for what a PNG can take up
+        let size = self.process.resize.unwrap_or(req.resolution);
+        let needed = u64::from(size.width) * u64::from(size.height) * 4 * u64::from(req.count);
+        let available = fs4::available_space(dir)?;
+        if available < needed {
+            let msg = t!(
+                "not-enough-space",
+                dir = dir.display(),
+                needed = needed.div_ceil(1 << 20),
+                available = available / (1 << 20),
+            );
+            return Err(std::io::Error::new(ErrorKind::StorageFull, msg));
+        }
+        Ok(())
+    }
+
+    /// The file name stem: `--name` if given, else [`prompt_stem`].
+    pub fn base_name(&self, prompt: &str) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => prompt_stem(prompt),
+        }
+    }
+
+    /// Whether images are written exactly as received, which allows decoding
+    /// them straight to disk instead of holding them in memory first.
+    fn writes_unchanged(&self, req: &ImageRequest) -> bool {
+        req.fit.is_none()
+            && self.process.is_noop()
+            && !self.strip_metadata
+            && !self.verify_pixels
+            && self.thumbs.is_none()
+            && !self.label
+            && !req.tileable
+            && !req.quantize
+            && req.seed.is_none()
+            && self.format == encode::Format::Png
+    }
+
+    /// Writes every returned image into `dir`, named after the prompt.
+    ///
+    /// Images are decoded and written by a pool of worker threads. A failed
+    /// image is reported and skipped; only when none could be saved is the
+    /// first error returned.
+    fn write_images(
+        &self,
+        req: &ImageRequest,
+        data: &[ImageData],
+    ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        let dir = self.out_dir()?;
+        let workers = thread::available_parallelism()
+            .map_or(1, NonZeroUsize::get)
+            .min(data.len());
+        let next = AtomicUsize::new(0);
+        let span = tracing::Span::current();
+
+        let mut results: Vec<(usize, Result<PathBuf, SendError>)> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let _span = span.enter();
+                        let mut done = Vec::new();
+                        loop {
+                            let i = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(item) = data.get(i) else {
+                                return done;
+                            };
+                            let result = self.write_image(&dir, req, item, i, data.len());
+                            done.push((i, result.map_err(sendable)));
+                        }
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+                .collect()
+        });
+        results.sort_by_key(|(i, _)| *i);
+
+        let mut saved = Vec::with_capacity(data.len());
+        let mut errors = Vec::new();
+        for (i, result) in results {
+            match result {
+                Ok(filename) => saved.push(filename),
+                Err(e) => errors.push((i, e)),
+            }
+        }
+        if saved.is_empty()
+            && let Some((_, e)) = errors.into_iter().next()
+        {
+            return Err(e);
+        }
+        for (i, e) in errors {
+            run_log::warn(t!("image-save-failed", index = i + 1, error = e));
+        }
+        Ok(saved)
+    }
+
+    /// Decodes, processes and writes the `i`th of `total` images.
+    #[tracing::instrument(name = "save_image", skip_all, err, fields(index = i + 1))]
+    fn write_image(
+        &self,
+        dir: &Path,
+        req: &ImageRequest,
+        item: &ImageData,
+        i: usize,
+        total: usize,
+    ) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let stem = self.base_name(&req.prompt);
+        if self.writes_unchanged(req) && item.b64_json.starts_with(PNG_BASE64_PREFIX) {
+            let filename = stream_new(dir, &stem, i + 1, &item.b64_json)?;
+            return self.finish(filename, req);
+        }
+
+        let mut bytes = decode(req, item)?;
+        if self.verify_pixels {
+            process::verify(&bytes).map_err(CorruptImage)?;
+        }
+        if !self.process.is_noop() {
+            bytes = self.process.apply(&bytes)?;
+        }
+        if req.quantize
+            && let Some(palette) = &req.palette
+        {
+            bytes = process::quantize(&bytes, &palette.colors)?;
+        }
+        if self.strip_metadata {
+            bytes = png::strip_metadata(&bytes)?;
+        } else if let Some(seed) = req.seed {
+            bytes = png::add_text(&bytes, "Seed", &seed.to_string())?;
+        }
+
+        if req.tileable {
+            let seams = process::seams(&bytes)?;
+            if !seams.is_empty() {
+                let edges = seams.join(", ");
+                run_log::warn(t!("not-tileable", index = i + 1, edges = edges));
+            }
+        }
+
+        let filename = match self.format {
+            encode::Format::Png => write_new(dir, &stem, i + 1, "png", &bytes)?,
+            format => {
+                let encoded = format.encode(&bytes, self.image_quality)?;
+                write_new(dir, &stem, i + 1, format.extension(), &encoded)?
+            }
+        };
+
+        if let Some(max) = self.thumbs {
+            let thumbs_dir = dir.join("thumbs");
+            fs::create_dir_all(&thumbs_dir)?;
+            let name = png_name(&filename)?;
+            write_atomic(&thumbs_dir.join(name), &process::thumbnail(&bytes, max)?)?;
+        }
+
+        if self.label {
+            let labeled_dir = dir.join("labeled");
+            fs::create_dir_all(&labeled_dir)?;
+            let name = png_name(&filename)?;
+            let label = format!(
+                "{}/{total} {} {} {}",
+                i + 1,
+                self.provider,
+                req.quality,
+                req.resolution
+            );
+            write_atomic(&labeled_dir.join(name), &sheet::caption(&bytes, &label)?)?;
+        }
+
+        self.finish(filename, req)
+    }
+
+    /// Adds alt text and then Content Credentials, which sign the final bytes.
+    /// Stripped images only get the alt text file next to them.
+    fn finish(
+        &self,
+        filename: PathBuf,
+        req: &ImageRequest,
+    ) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        if let Some(client) = &self.alt_text {
+            match client.alt_text(&filename) {
+                Ok(text) if self.strip_metadata => {
+                    write_atomic(&alt::sidecar(&filename), text.as_bytes())?;
+                }
+                Ok(text) => alt::store(&filename, &text)?,
+                Err(e) => run_log::warn(t!("alt-text-failed", error = e)),
+            }
+        }
+        if let Some(config) = &self.content_credentials {
+            c2pa::embed(&filename, &req.prompt, &self.provider, config)?;
+        }
+        Ok(filename)
+    }
+}
+
+/// The file name of `file` with a `.png` extension, for the copies derived
+/// from it, which are always PNG.
+fn png_name(file: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let name = file.file_name().ok_or("Invalid image file name")?;
+    Ok(Path::new(name).with_extension("png"))
+}
+
+type SendError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Turns an error into one that can leave a worker thread, keeping the types
+/// that decide the exit code.
+fn sendable(e: Box<dyn std::error::Error>) -> SendError {
+    if let Some(corrupt) = e.downcast_ref::<CorruptImage>() {
+        return Box::new(CorruptImage(corrupt.0.clone()));
+    }
+    if let Some(io) = e.downcast_ref::<io::Error>() {
+        return Box::new(io::Error::new(io.kind(), io.to_string()));
+    }
+    e.to_string().into()
+}
+
+/// The prompt transliterated to ASCII, or (e.g. for prompts made only of
+/// symbols) a timestamp plus a short hash of the prompt.
+pub fn prompt_stem(prompt: &str) -> String {
+    let slug = slugify(prompt);
+    if !slug.is_empty() {
+        return if slug.len() > 50 {
+            slug[..50].to_string()
+        } else {
+            slug
+        };
+    }
+
+    let hash: String = Sha256::digest(prompt.as_bytes())[..4]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    format!("{}-{hash}", Local::now().format("%Y%m%d-%H%M%S"))
+}
+
+/// Whether `path` is a `latest.<ext>` pointer rather than a saved image.
+pub fn is_latest(path: &Path) -> bool {
+    path.file_stem().is_some_and(|stem| stem == LATEST) && path.extension().is_some()
+}
+
+/// The `latest.<ext>` pointers in `dir`, normally just one.
+pub fn latest_links(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut links = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if is_latest(&path) {
+            links.push(path);
+        }
+    }
+    Ok(links)
+}
+
+fn extension(file: &Path) -> String {
+    file.extension()
+        .map_or_else(|| "png".into(), |ext| ext.to_string_lossy().into_owned())
+}
+
+/// Points `latest.<ext>` in `dir` at `file`, named after its extension so the
+/// link's contents match its name, with a relative symlink where possible and
+/// a copy elsewhere. Links left over from another format are removed.
+fn update_latest(dir: &Path, file: &Path) -> io::Result<()> {
+    let link = dir.join(format!("{LATEST}.{}", extension(file)));
+    let tmp = dir.join(format!(".{LATEST}.{}.tmp", std::process::id()));
+    #[cfg(unix)]
+    let result = std::os::unix::fs::symlink(file.strip_prefix(dir).unwrap_or(file), &tmp);
+    // Symlinks need extra privileges on Windows
+    #[cfg(not(unix))]
+    let result = fs::copy(file, &tmp).map(|_| ());
+
+    let result = result.and_then(|()| fs::rename(&tmp, &link));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+        return result;
+    }
+    for stale in latest_links(dir)?.into_iter().filter(|path| *path != link) {
+        fs::remove_file(stale)?;
+    }
+    Ok(())
+}
+
+/// Creates `path` with `len` bytes reserved up front where the file system
+/// supports it, so large images are not fragmented while being written.
+fn create_sized(path: &Path, len: u64) -> io::Result<File> {
+    let file = File::create(path)?;
+    // Only an optimization, so file systems without preallocation are fine
+    let _ = file.allocate(len);
+    Ok(file)
+}
+
+fn write_synced(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let file = create_sized(path, bytes.len() as u64)?;
+    let mut writer = BufWriter::with_capacity(WRITE_BUFFER, file);
+    writer.write_all(bytes)?;
+    writer
+        .into_inner()
+        .map_err(io::IntoInnerError::into_error)?
+        .sync_all()
+}
+
+/// Writes `bytes` to a temporary file next to `path` and renames it into place,
+/// so an interrupted run never leaves a truncated image behind.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = path.with_file_name(format!(".{name}.{}.tmp", std::process::id()));
+
+    let result = write_synced(&tmp, bytes).and_then(|()| fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Writes `bytes` to the first free `{stem}_{n}.{ext}` in `dir`, counting
+/// from `first`.
+///
+/// The data is written to a temporary file and then hard-linked to its final
+/// name. Linking fails rather than overwrites when another imgmc process took
+/// the name in the meantime, so concurrent runs never clobber each other.
+pub fn write_new(
+    dir: &Path,
+    stem: &str,
+    first: usize,
+    ext: &str,
+    bytes: &[u8],
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let tmp = dir.join(format!(".{stem}.{}.{first}.tmp", std::process::id()));
+    write_synced(&tmp, bytes)?;
+    let result = claim_name(&tmp, dir, stem, first, ext);
+    let _ = fs::remove_file(&tmp);
+    result
+}
+
+/// Like [`write_new`], but decodes the base64 straight into the file and
+/// verifies it from there, so the decoded image is never held in memory.
+fn stream_new(
+    dir: &Path,
+    stem: &str,
+    first: usize,
+    b64: &str,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let tmp = dir.join(format!(".{stem}.{}.{first}.tmp", std::process::id()));
+    let result = stream_decode(&tmp, b64).and_then(|()| claim_name(&tmp, dir, stem, first, "png"));
+    let _ = fs::remove_file(&tmp);
+    result
+}
+
+fn stream_decode(path: &Path, b64: &str) -> Result<(), Box<dyn std::error::Error>> {
+    // Padding can make this a byte or two more than the decoded length
+    let estimate = b64.len() as u64 / 4 * 3;
+    let mut writer = BufWriter::with_capacity(WRITE_BUFFER, create_sized(path, estimate)?);
+    let mut decoder = DecoderReader::new(b64.as_bytes(), &BASE64_STD);
+    let written = match io::copy(&mut decoder, &mut writer) {
+        // The decoder reports malformed base64 as invalid data
+        Err(e) if e.kind() == ErrorKind::InvalidData => {
+            return Err(CorruptImage(format!("base64 decode failed: {e}")).into());
+        }
+        result => result?,
+    };
+    let file = writer
+        .into_inner()
+        .map_err(io::IntoInnerError::into_error)?;
+    // Drop whatever the preallocation reserved beyond the actual data
+    file.set_len(written)?;
+    file.sync_all()?;
+
+    png::verify(BufReader::new(File::open(path)?)).map_err(CorruptImage)?;
+    Ok(())
+}
+
+fn claim_name(
+    tmp: &Path,
+    dir: &Path,
+    stem: &str,
+    first: usize,
+    ext: &str,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let mut counter = first;
+    loop {
+        let candidate = dir.join(format!("{stem}_{counter}.{ext}"));
+        match fs::hard_link(tmp, &candidate) {
+            Ok(()) => return Ok(candidate),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+            // Without hard links, reserve the name first and move the data onto it
+            Err(_) => match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&candidate)
+            {
+                Ok(_) => {
+                    fs::rename(tmp, &candidate)?;
+                    return Ok(candidate);
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e.into()),
+            },
+        }
+        counter = counter
+            .checked_add(1)
+            .ok_or("Counter overflow: too many files with similar names")?;
+    }
+}
+
+/// Decodes an image, fitting it to the requested size when the provider was
+/// asked for a substitute one.
+#[tracing::instrument(skip_all, err)]
+pub fn decode(req: &ImageRequest, item: &ImageData) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let bytes = BASE64_STD
+        .decode(&item.b64_json)
+        .map_err(|e| CorruptImage(format!("base64 decode failed: {e}")))?;
+    verify(&bytes).map_err(CorruptImage)?;
+
+    match req.fit {
+        Some(fit)
+            if png::dimensions(&bytes) != Some((req.resolution.width, req.resolution.height)) =>
+        {
+            process::fit(&bytes, req.resolution, fit)
+        }
+        _ => Ok(bytes),
+    }
+}
@@ -0,0 +1,143 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+/// Colors images should stick to, given as `#112233,#aabbcc` or read from a
+/// GIMP `.gpl` or Adobe `.ase` palette file.
+#[derive(Deserialize, Clone)]
+#[serde(try_from = "String")]
+pub struct Palette {
+    pub colors: Vec<[u8; 3]>,
+}
+
+impl Palette {
+    /// Guidance for the prompt, e.g. `Use only these colors: #112233, #aabbcc.`
+    pub fn hint(&self) -> String {
+        let colors: Vec<String> = self
+            .colors
+            .iter()
+            .map(|[r, g, b]| format!("#{r:02x}{g:02x}{b:02x}"))
+            .collect();
+        format!("Use only these colors: {}.", colors.join(", "))
+    }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let path = Path::new(s);
+        let colors = match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("gpl") => {
+                let text = fs::read_to_string(path).map_err(|e| format!("{s}: {e}"))?;
+                parse_gpl(&text).map_err(|e| format!("{s}: {e}"))?
+            }
+            Some(ext) if ext.eq_ignore_ascii_case("ase") => {
+                let data = fs::read(path).map_err(|e| format!("{s}: {e}"))?;
+                parse_ase(&data).map_err(|e| format!("{s}: {e}"))?
+            }
+            _ => s.split(',').map(parse_hex).collect::<Result<_, _>>()?,
+        };
+        if colors.is_empty() {
+            return Err(format!("Palette {s} contains no colors"));
+        }
+        Ok(Palette { colors })
+    }
+}
+
+impl TryFrom<String> for Palette {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+fn parse_hex(s: &str) -> Result<[u8; 3], String> {
+    let invalid = || format!("Invalid color {s}, expected #rrggbb");
+    let hex = s.trim().strip_prefix('#').unwrap_or(s.trim());
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Reads a GIMP palette: a `GIMP Palette` header, optional `Name:` and
+/// `Columns:` lines and comments, then one `R G B name` line per color.
+fn parse_gpl(text: &str) -> Result<Vec<[u8; 3]>, String> {
+    let mut lines = text.lines();
+    if lines.next().map(str::trim) != Some("GIMP Palette") {
+        return Err("not a GIMP palette".into());
+    }
+    let mut colors = Vec::new();
+    for line in lines {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.contains(':') {
+            continue;
+        }
+        let mut channels = line.split_whitespace().map(str::parse::<u8>);
+        match (channels.next(), channels.next(), channels.next()) {
+            (Some(Ok(r)), Some(Ok(g)), Some(Ok(b))) => colors.push([r, g, b]),
+            _ => return Err(format!("invalid color line: {line}")),
+        }
+    }
+    Ok(colors)
+}
+
+/// Reads the RGB, CMYK and gray colors of an Adobe Swatch Exchange file,
+/// skipping groups and Lab colors.
+fn parse_ase(data: &[u8]) -> Result<Vec<[u8; 3]>, String> {
+    let truncated = || "truncated ASE file".to_string();
+    let u16_at = |at: usize| -> Result<u16, String> {
+        let bytes = data.get(at..at + 2).ok_or_else(truncated)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+    let u32_at = |at: usize| -> Result<u32, String> {
+        let bytes = data.get(at..at + 4).ok_or_else(truncated)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+    let f32_at = |at: usize| u32_at(at).map(f32::from_bits);
+    // Components are stored as 0.0 to 1.0
+    let byte = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+    if !data.starts_with(b"ASEF") {
+        return Err("not an ASE file".into());
+    }
+    let blocks = u32_at(8)?;
+    let mut at = 12;
+    let mut colors = Vec::new();
+    for _ in 0..blocks {
+        let kind = u16_at(at)?;
+        let len = u32_at(at + 2)? as usize;
+        let body = at + 6;
+        at = body + len;
+
+        // Only color entries matter, not group starts and ends
+        if kind != 0x0001 {
+            continue;
+        }
+        let name_chars = usize::from(u16_at(body)?);
+        let model_at = body + 2 + name_chars * 2;
+        let model = data.get(model_at..model_at + 4).ok_or_else(truncated)?;
+        let values = model_at + 4;
+        let color = match model {
+            b"RGB " => [f32_at(values)?, f32_at(values + 4)?, f32_at(values + 8)?].map(byte),
+            b"CMYK" => {
+                let [c, m, y, k] = [
+                    f32_at(values)?,
+                    f32_at(values + 4)?,
+                    f32_at(values + 8)?,
+                    f32_at(values + 12)?,
+                ];
+                [c, m, y].map(|v| byte((1.0 - v) * (1.0 - k)))
+            }
+            b"Gray" => [byte(f32_at(values)?); 3],
+            _ => continue,
+        };
+        colors.push(color);
+    }
+    Ok(colors)
+}
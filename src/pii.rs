@@ -0,0 +1,128 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::sync::LazyLock;
+
+use regex::{Captures, Regex};
+
+use crate::i18n::t;
+use crate::request::ImageRequest;
+use crate::run_log;
+
+static EMAIL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"[\w.+-]+@[\w-]+(?:\.[\w-]+)+").unwrap());
+
+/// Digit runs with the usual separators. Whether there are enough digits for a
+/// phone number is checked separately, so years and sizes are left alone.
+static PHONE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\+?\(?\d[\d\s().-]{5,}\d").unwrap());
+
+/// Names after a title, or introduced as someone's name. Names elsewhere
+/// cannot be told from places, brands or characters without a model.
+static NAME: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(concat!(
+        r"\b(?:(?:Mr|Mrs|Ms|Miss|Mx|Dr|Prof)\.?|(?i:named|called))",
+        r"\s+\p{Lu}[\p{L}'-]+(?:\s+\p{Lu}[\p{L}'-]+)?",
+    ))
+    .unwrap()
+});
+
+/// Masks email addresses, phone numbers and names in `prompt`, returning the
+/// result and how many were masked.
+pub fn scrub(prompt: &str) -> (String, usize) {
+    let mut count = 0;
+    let scrubbed = EMAIL.replace_all(prompt, |_: &Captures| {
+        count += 1;
+        "[email]"
+    });
+    let scrubbed = PHONE.replace_all(&scrubbed, |caps: &Captures| {
+        let phone = &caps[0];
+        let digits = phone.chars().filter(char::is_ascii_digit).count();
+        if digits >= 9 || phone.starts_with('+') && digits >= 7 {
+            count += 1;
+            "[phone]".to_string()
+        } else {
+            phone.to_string()
+        }
+    });
+    let scrubbed = NAME.replace_all(&scrubbed, |caps: &Captures| {
+        count += 1;
+        // Keep the word that introduced the name, so the prompt still reads
+        let intro = caps[0].split_whitespace().next().unwrap_or_default();
+        format!("{intro} [name]")
+    });
+    (scrubbed.into_owned(), count)
+}
+
+/// Scrubs the prompt of `req` without asking, for prompts that come from
+/// files or over HTTP rather than from someone at a terminal.
+pub fn scrub_request(req: &mut ImageRequest) {
+    let (scrubbed, count) = scrub(&req.prompt);
+    if count > 0 {
+        run_log::warn(format!("{} {scrubbed}", t!("pii-scrubbed", count = count)));
+        req.prompt = scrubbed;
+    }
+}
+
+/// Scrubs `prompt` and, if anything was masked, shows the result and asks
+/// whether to send it. Without a terminal to ask on, the scrubbed prompt is
+/// sent after showing it.
+pub fn confirm(prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let (scrubbed, count) = scrub(prompt);
+    if count == 0 {
+        return Ok(scrubbed);
+    }
+    eprintln!("{}", t!("pii-scrubbed", count = count));
+    eprintln!("  {scrubbed}");
+    if !io::stdin().is_terminal() {
+        return Ok(scrubbed);
+    }
+
+    eprint!("{} ", t!("pii-confirm"));
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    match answer.trim().to_lowercase().as_str() {
+        "" | "y" | "yes" | "j" | "ja" => Ok(scrubbed),
+        _ => Err(t!("pii-cancelled").into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scrub_leaves_ordinary_prompts_alone() {
+        for prompt in [
+            "",
+            "a red fox in 1999",
+            "a 1024x1024 poster",
+            "called out at dusk",
+        ] {
+            assert_eq!(scrub(prompt), (prompt.to_string(), 0));
+        }
+    }
+
+    #[test]
+    fn scrub_masks_emails_and_phone_numbers() {
+        assert_eq!(
+            scrub("a card for jane.doe+work@example.co.uk"),
+            ("a card for [email]".to_string(), 1)
+        );
+        assert_eq!(
+            scrub("call 030 1234 5678 or +49 30 12345"),
+            ("call [phone] or [phone]".to_string(), 2)
+        );
+        // Too few digits for a phone number
+        assert_eq!(scrub("version 1.2.3-4").1, 0);
+    }
+
+    #[test]
+    fn scrub_masks_names_but_keeps_the_intro() {
+        assert_eq!(
+            scrub("a portrait of Dr. Jane Smith and a dog named Rex"),
+            (
+                "a portrait of Dr. [name] and a dog named [name]".to_string(),
+                2
+            )
+        );
+    }
+}
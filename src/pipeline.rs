@@ -0,0 +1,298 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use figment::{
+    Figment,
+    providers::{Format, Toml},
+};
+use serde::Deserialize;
+
+use crate::azure::{Client, ImageData};
+use crate::config::ConfigError;
+use crate::i18n::t;
+use crate::output::{self, Saver};
+use crate::png;
+use crate::process::{self, PostProcess};
+use crate::request::{Background, Fit, ImageQuality, ImageRequest};
+use crate::size::{AspectRatio, Size};
+use crate::spinner;
+use crate::upload::Destination;
+#[cfg(feature = "wasm")]
+use crate::wasm;
+
+const REMOVE_BG_PROMPT: &str = "Keep the subject exactly as it is and remove the background";
+
+#[cfg(not(feature = "wasm"))]
+const NO_WASM: &str = "This imgmc was built without WASM steps, rebuild with `--features wasm`";
+
+/// Steps run in order, each working on the images the previous one produced.
+#[derive(Deserialize)]
+pub struct Pipeline {
+    steps: Vec<Step>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum Step {
+    Generate {
+        prompt: String,
+        #[serde(default)]
+        quality: ImageQuality,
+        #[serde(default = "default_resolution")]
+        resolution: Size,
+        #[serde(default)]
+        background: Background,
+        #[serde(default = "default_count")]
+        count: u8,
+    },
+    /// Changes every image according to a prompt.
+    Edit {
+        prompt: String,
+    },
+    /// Replaces the background of every image with transparency.
+    RemoveBg,
+    /// Enlarges every image locally by a whole factor.
+    Upscale {
+        factor: u32,
+    },
+    Resize {
+        size: Size,
+    },
+    Crop {
+        ratio: AspectRatio,
+    },
+    /// Transforms every image with a WebAssembly module, see `wasm::Plugin`.
+    Wasm {
+        module: PathBuf,
+        /// Passed to the module as JSON.
+        #[serde(default)]
+        params: serde_json::Value,
+    },
+    /// Saves the images if that has not happened yet and uploads them.
+    Upload {
+        destination: String,
+    },
+}
+
+fn default_resolution() -> Size {
+    Size::new(1024, 1024)
+}
+
+fn default_count() -> u8 {
+    1
+}
+
+impl Step {
+    fn name(&self) -> &'static str {
+        match self {
+            Step::Generate { .. } => "generate",
+            Step::Edit { .. } => "edit",
+            Step::RemoveBg => "remove-bg",
+            Step::Upscale { .. } => "upscale",
+            Step::Resize { .. } => "resize",
+            Step::Crop { .. } => "crop",
+            Step::Wasm { .. } => "wasm",
+            Step::Upload { .. } => "upload",
+        }
+    }
+}
+
+/// Reads a pipeline definition from a TOML file.
+pub fn load(path: &Path) -> Result<Pipeline, Box<dyn std::error::Error>> {
+    if !path.exists() {
+        let msg = format!("Pipeline file not found at: {}", path.display());
+        return Err(ConfigError(msg).into());
+    }
+    let pipeline: Pipeline = Figment::new().merge(Toml::file(path)).extract()?;
+    if !matches!(pipeline.steps.first(), Some(Step::Generate { .. })) {
+        return Err(ConfigError("A pipeline must start with a generate step".into()).into());
+    }
+    #[cfg(not(feature = "wasm"))]
+    if pipeline
+        .steps
+        .iter()
+        .any(|s| matches!(s, Step::Wasm { .. }))
+    {
+        return Err(ConfigError(NO_WASM.into()).into());
+    }
+    Ok(pipeline)
+}
+
+/// Runs every step and saves the final images with `saver`.
+pub fn run(
+    pipeline: &Pipeline,
+    client: &Client,
+    saver: &Saver,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut req = request(String::new());
+    let mut images: Vec<Vec<u8>> = Vec::new();
+    // Files written for the current images, empty once they change again
+    let mut saved: Vec<PathBuf> = Vec::new();
+
+    for (i, step) in pipeline.steps.iter().enumerate() {
+        eprintln!("Step {}: {}", i + 1, step.name());
+        match step {
+            Step::Generate {
+                prompt,
+                quality,
+                resolution,
+                background,
+                count,
+            } => {
+                req = request(prompt.clone());
+                req.quality = quality.clone();
+                req.resolution = *resolution;
+                req.background = background.clone();
+                req.count = *count;
+                client.validate(&mut req)?;
+                saver.preflight(&req)?;
+
+                // Through the saver, so --min-score and the other checks
+                // judge the generated images before any step changes them
+                let sp = spinner::Spinner::start(t!("calling-api"));
+                let data = saver.fetch(client, &req, &sp);
+                drop(sp);
+                images = data?
+                    .iter()
+                    .map(|item| output::decode(&req, item))
+                    .collect::<Result<_, _>>()?;
+            }
+            Step::Edit { prompt } => {
+                images = edit_all(client, saver, &images, prompt, Background::Auto)?;
+            }
+            Step::RemoveBg => {
+                images = edit_all(
+                    client,
+                    saver,
+                    &images,
+                    REMOVE_BG_PROMPT,
+                    Background::Transparent,
+                )?;
+            }
+            Step::Upscale { factor } => {
+                images = map(&images, |bytes| process::upscale(bytes, *factor))?;
+            }
+            Step::Resize { size } => {
+                let process = PostProcess {
+                    crop: None,
+                    resize: Some(*size),
+                };
+                images = map(&images, |bytes| process.apply(bytes))?;
+            }
+            Step::Crop { ratio } => {
+                let process = PostProcess {
+                    crop: Some(*ratio),
+                    resize: None,
+                };
+                images = map(&images, |bytes| process.apply(bytes))?;
+            }
+            #[cfg(feature = "wasm")]
+            Step::Wasm { module, params } => {
+                let plugin = wasm::Plugin::load(module)?;
+                images = map(&images, |bytes| plugin.transform(bytes, params))?;
+            }
+            #[cfg(not(feature = "wasm"))]
+            Step::Wasm { .. } => return Err(ConfigError(NO_WASM.into()).into()),
+            Step::Upload { destination } => {
+                let destination: Destination = destination.parse().map_err(ConfigError)?;
+                if saved.is_empty() {
+                    saved = save(saver, &req, &images)?;
+                }
+                for file in &saved {
+                    let url = destination.upload(file)?;
+                    println!("{}", t!("image-uploaded", url = url));
+                }
+                continue;
+            }
+        }
+        saved.clear();
+    }
+
+    if saved.is_empty() {
+        save(saver, &req, &images)?;
+    }
+    Ok(())
+}
+
+fn request(prompt: String) -> ImageRequest {
+    ImageRequest {
+        prompt,
+        truncate_prompt: false,
+        quality: ImageQuality::default(),
+        resolution: default_resolution(),
+        fit: None,
+        background: Background::default(),
+        moderation: None,
+        count: 1,
+        reference: None,
+        tileable: false,
+        palette: None,
+        quantize: false,
+        vars: Vec::new(),
+        seed: None,
+    }
+}
+
+fn map(
+    images: &[Vec<u8>],
+    f: impl Fn(&[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>>,
+) -> Result<Vec<Vec<u8>>, Box<dyn std::error::Error>> {
+    images.iter().map(|bytes| f(bytes)).collect()
+}
+
+/// Sends every image through the edits endpoint, keeping its size. Edits
+/// are recorded in the audit log as they happen, as they are not saved.
+fn edit_all(
+    client: &Client,
+    saver: &Saver,
+    images: &[Vec<u8>],
+    prompt: &str,
+    background: Background,
+) -> Result<Vec<Vec<u8>>, Box<dyn std::error::Error>> {
+    let reference = std::env::temp_dir().join(format!("imgmc-pipeline-{}.png", std::process::id()));
+    let result = images
+        .iter()
+        .map(|bytes| {
+            let (width, height) = png::dimensions(bytes).ok_or("Pipeline images must be PNG")?;
+            let mut req = request(prompt.to_string());
+            req.resolution = Size::new(width, height);
+            // Sizes the model cannot produce come back cropped to the original
+            req.fit = Some(Fit::Crop);
+            req.background = background.clone();
+            req.reference = Some(reference.clone());
+            client.validate(&mut req)?;
+            fs::write(&reference, bytes)?;
+
+            let sp = spinner::Spinner::start(t!("calling-api"));
+            let resp = client.generate(&req);
+            drop(sp);
+            let outcome = resp.as_ref().map(|_| [].as_slice());
+            saver.record(&req, outcome.map_err(ToString::to_string));
+            let resp = resp?;
+            let item = resp
+                .data
+                .first()
+                .ok_or("The response contained no images")?;
+            output::decode(&req, item)
+        })
+        .collect();
+    let _ = fs::remove_file(&reference);
+    result
+}
+
+fn save(
+    saver: &Saver,
+    req: &ImageRequest,
+    images: &[Vec<u8>],
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    // Go through the regular save path so output options apply as usual
+    let data: Vec<ImageData> = images
+        .iter()
+        .map(|bytes| ImageData::from_png(bytes))
+        .collect();
+    let saved = saver.save(req, &data)?;
+    for filename in &saved {
+        println!("{}", t!("image-saved", path = filename.display()));
+    }
+    Ok(saved)
+}
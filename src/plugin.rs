@@ -0,0 +1,159 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64_STD;
+
+use crate::asset;
+use crate::azure::GenerationResponse;
+use crate::config::PolicyConfig;
+use crate::pii;
+use crate::provider::{self, Provider};
+use crate::request::ImageRequest;
+use crate::script::Hooks;
+use crate::size::{AspectRatio, Size};
+
+/// Executables named like this on `PATH` provide `--provider <name>`.
+const PREFIX: &str = "imgmc-provider-";
+
+/// Names of the plugins found on `PATH`, sorted and without duplicates.
+pub fn discover() -> Vec<String> {
+    let Some(path) = std::env::var_os("PATH") else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = std::env::split_paths(&path)
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| {
+            let file_name = entry.file_name().into_string().ok()?;
+            let name = file_name.strip_prefix(PREFIX)?;
+            let name = name
+                .strip_suffix(std::env::consts::EXE_SUFFIX)
+                .unwrap_or(name);
+            (!name.is_empty()).then(|| name.to_string())
+        })
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// A provider implemented by an external executable.
+///
+/// The plugin is run once per request and gets the request as a JSON object
+/// on stdin: `prompt`, `quality`, `resolution` (`WxH`), `background`, `count`
+/// and `reference` (a file path or null). It answers on stdout in the shape of
+/// the OpenAI images API, `{"data": [{"b64_json": "..."}]}`, or exits with a
+/// non-zero status and an error message on stderr.
+///
+/// Requests for other assets have `prompt`, `output` (see [`asset::Kind`])
+/// and `reference`, and are answered the same way with a single item.
+pub struct Plugin {
+    name: String,
+    path: PathBuf,
+    /// User script that may change requests before they are sent.
+    pub script: Option<Hooks>,
+    pub policy: PolicyConfig,
+    pub scrub_pii: bool,
+}
+
+impl Plugin {
+    pub fn find(name: &str) -> Result<Plugin, String> {
+        let file_name = format!("{PREFIX}{name}{}", std::env::consts::EXE_SUFFIX);
+        let path = std::env::var_os("PATH")
+            .iter()
+            .flat_map(std::env::split_paths)
+            .map(|dir| dir.join(&file_name))
+            .find(|path| path.is_file())
+            .ok_or_else(|| format!("No provider plugin {file_name} on PATH"))?;
+        Ok(Plugin {
+            name: name.to_string(),
+            path,
+            script: None,
+            policy: PolicyConfig::default(),
+            scrub_pii: false,
+        })
+    }
+}
+
+impl Provider for Plugin {
+    /// Only runs the `before_request` script hook and checks the system
+    /// policy; plugins check their own limits when they get the request.
+    fn validate(&self, req: &mut ImageRequest) -> Result<(), String> {
+        if let Some(script) = &self.script {
+            script.before_request(req)?;
+        }
+        if self.scrub_pii {
+            pii::scrub_request(req);
+        }
+        self.policy.check(req)
+    }
+
+    fn closest_size(&self, ratio: AspectRatio) -> Size {
+        provider::megapixel_size(ratio)
+    }
+
+    fn generate(
+        &self,
+        req: &ImageRequest,
+    ) -> Result<GenerationResponse, Box<dyn std::error::Error>> {
+        let request = serde_json::json!({
+            "prompt": req.prompt,
+            "quality": req.quality.to_string(),
+            "resolution": req.resolution.to_string(),
+            "background": req.background.to_string(),
+            "count": req.count,
+            "reference": req.reference,
+            "seed": req.seed,
+        });
+        self.run(&request)
+    }
+
+    fn generate_asset(
+        &self,
+        kind: asset::Kind,
+        prompt: &str,
+        reference: Option<&Path>,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        self.policy.check_prompt(prompt)?;
+        let request = serde_json::json!({
+            "prompt": prompt,
+            "output": kind.name(),
+            "reference": reference,
+        });
+        let resp = self.run(&request)?;
+        let item = resp
+            .data
+            .first()
+            .ok_or_else(|| format!("Provider {} sent no {} asset", self.name, kind.name()))?;
+        Ok(BASE64_STD.decode(&item.b64_json)?)
+    }
+}
+
+impl Plugin {
+    /// Sends `request` to the plugin and reads its answer.
+    fn run(
+        &self,
+        request: &serde_json::Value,
+    ) -> Result<GenerationResponse, Box<dyn std::error::Error>> {
+        let mut child = Command::new(&self.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Could not run {}: {e}", self.path.display()))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(&serde_json::to_vec(request)?)?;
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("Provider {} failed: {}", self.name, stderr.trim()).into());
+        }
+        serde_json::from_slice(&output.stdout)
+            .map_err(|e| format!("Provider {} sent an invalid response: {e}", self.name).into())
+    }
+}
@@ -0,0 +1,213 @@
+use std::io::Read;
+
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Chunks needed to display an image the same way; everything else is
+/// metadata (text, timestamps, EXIF, provenance manifests, private chunks).
+const RENDERING_CHUNKS: [&[u8; 4]; 12] = [
+    b"IHDR", b"PLTE", b"IDAT", b"IEND", b"tRNS", b"gAMA", b"cHRM", b"sRGB", b"iCCP", b"sBIT",
+    b"bKGD", b"pHYs",
+];
+
+/// Width and height from the IHDR chunk, without decoding the image.
+pub fn dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let header = bytes.strip_prefix(&SIGNATURE)?.get(..16)?;
+    if &header[4..8] != b"IHDR" {
+        return None;
+    }
+    let width = u32::from_be_bytes(header[8..12].try_into().ok()?);
+    let height = u32::from_be_bytes(header[12..16].try_into().ok()?);
+    Some((width, height))
+}
+
+pub struct Chunk<'a> {
+    pub kind: [u8; 4],
+    /// The whole chunk including length, type and CRC.
+    pub raw: &'a [u8],
+}
+
+/// Splits a PNG file into its chunks, failing on anything that is not a PNG.
+pub fn chunks(bytes: &[u8]) -> Result<Vec<Chunk<'_>>, String> {
+    let mut rest = bytes
+        .strip_prefix(&SIGNATURE)
+        .ok_or("Not a PNG file: bad signature")?;
+    let mut chunks = Vec::new();
+
+    while !rest.is_empty() {
+        if rest.len() < 12 {
+            return Err("Truncated PNG chunk".into());
+        }
+        let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let end = len
+            .checked_add(12)
+            .filter(|&end| end <= rest.len())
+            .ok_or("Truncated PNG chunk")?;
+        let (raw, tail) = rest.split_at(end);
+        chunks.push(Chunk {
+            kind: [raw[4], raw[5], raw[6], raw[7]],
+            raw,
+        });
+        rest = tail;
+    }
+
+    Ok(chunks)
+}
+
+/// Checks that a PNG arrived complete: IHDR first, an IEND, some image data
+/// and a matching CRC on every chunk.
+///
+/// Works chunk by chunk, so a file can be checked without loading it whole.
+pub fn verify(mut reader: impl Read) -> Result<(), String> {
+    let truncated = |_| "PNG is truncated: no IEND chunk".to_string();
+
+    let mut signature = [0; 8];
+    reader.read_exact(&mut signature).map_err(truncated)?;
+    if signature != SIGNATURE {
+        return Err("Not a PNG file: bad signature".into());
+    }
+
+    let mut has_data = false;
+    let mut buf = [0; 8192];
+    for index in 0.. {
+        let mut header = [0; 8];
+        reader.read_exact(&mut header).map_err(truncated)?;
+        let mut remaining = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let kind = [header[4], header[5], header[6], header[7]];
+        if index == 0 && &kind != b"IHDR" {
+            return Err("PNG does not start with an IHDR chunk".into());
+        }
+        has_data |= &kind == b"IDAT";
+
+        // The CRC covers the chunk type and data but not the length
+        let mut crc = crc32_update(!0, &kind);
+        while remaining > 0 {
+            let n = buf.len().min(remaining as usize);
+            reader.read_exact(&mut buf[..n]).map_err(truncated)?;
+            crc = crc32_update(crc, &buf[..n]);
+            remaining -= n as u32;
+        }
+        let mut stored = [0; 4];
+        reader.read_exact(&mut stored).map_err(truncated)?;
+        if (!crc).to_be_bytes() != stored {
+            let kind = String::from_utf8_lossy(&kind);
+            return Err(format!("PNG {kind} chunk is corrupt: CRC mismatch"));
+        }
+
+        if &kind == b"IEND" {
+            break;
+        }
+    }
+
+    if !has_data {
+        return Err("PNG has no image data".into());
+    }
+    Ok(())
+}
+
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+/// Rewrites a PNG keeping only the chunks needed to render it.
+pub fn strip_metadata(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(&SIGNATURE);
+    for chunk in chunks(bytes)? {
+        if RENDERING_CHUNKS.contains(&&chunk.kind) {
+            out.extend_from_slice(chunk.raw);
+        }
+    }
+    Ok(out)
+}
+
+/// Joins PNG frames into an APNG that loops forever, showing each frame for
+/// `delay_ms`. The frames must share their IHDR, i.e. size and pixel format.
+pub fn animate(frames: &[Vec<u8>], delay_ms: u16) -> Result<Vec<u8>, String> {
+    let first = frames.first().ok_or("No frames to animate")?;
+    let header = chunks(first)?
+        .into_iter()
+        .next()
+        .filter(|chunk| &chunk.kind == b"IHDR")
+        .ok_or("PNG does not start with an IHDR chunk")?
+        .raw;
+    let size = header.get(8..16).ok_or("Truncated PNG chunk")?;
+
+    let mut out = SIGNATURE.to_vec();
+    out.extend_from_slice(header);
+    let mut control = (frames.len() as u32).to_be_bytes().to_vec();
+    control.extend_from_slice(&0u32.to_be_bytes());
+    write_chunk(&mut out, b"acTL", &control);
+
+    // Frame controls and data after the first frame share one sequence
+    let mut sequence = 0u32;
+    for (index, frame) in frames.iter().enumerate() {
+        let chunks = chunks(frame)?;
+        if chunks.first().map(|chunk| chunk.raw) != Some(header) {
+            return Err("Frames differ in size or pixel format".into());
+        }
+
+        let mut control = sequence.to_be_bytes().to_vec();
+        control.extend_from_slice(size);
+        control.extend_from_slice(&[0; 8]);
+        control.extend_from_slice(&delay_ms.to_be_bytes());
+        control.extend_from_slice(&1000u16.to_be_bytes());
+        control.extend_from_slice(&[0, 0]);
+        write_chunk(&mut out, b"fcTL", &control);
+        sequence += 1;
+
+        for chunk in chunks.iter().filter(|chunk| &chunk.kind == b"IDAT") {
+            // The first frame doubles as the still image for viewers without
+            // APNG support
+            if index == 0 {
+                out.extend_from_slice(chunk.raw);
+            } else {
+                let mut fdat = sequence.to_be_bytes().to_vec();
+                fdat.extend_from_slice(&chunk.raw[8..chunk.raw.len() - 4]);
+                write_chunk(&mut out, b"fdAT", &fdat);
+                sequence += 1;
+            }
+        }
+    }
+
+    write_chunk(&mut out, b"IEND", &[]);
+    Ok(out)
+}
+
+/// Adds an uncompressed international text chunk, e.g. `Description`, before
+/// the image data.
+pub fn add_text(bytes: &[u8], keyword: &str, text: &str) -> Result<Vec<u8>, String> {
+    let mut data = keyword.as_bytes().to_vec();
+    // No compression, no language tag, no translated keyword
+    data.extend_from_slice(&[0, 0, 0, 0, 0]);
+    data.extend_from_slice(text.as_bytes());
+
+    let mut out = Vec::with_capacity(bytes.len() + data.len() + 12);
+    out.extend_from_slice(&SIGNATURE);
+    let mut added = false;
+    for chunk in chunks(bytes)? {
+        if !added && &chunk.kind == b"IDAT" {
+            write_chunk(&mut out, b"iTXt", &data);
+            added = true;
+        }
+        out.extend_from_slice(chunk.raw);
+    }
+    Ok(out)
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32_update(crc32_update(!0, kind), data);
+    out.extend_from_slice(&(!crc).to_be_bytes());
+}
@@ -0,0 +1,159 @@
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageFormat, Rgb, RgbaImage, imageops, imageops::FilterType};
+
+use crate::request::Fit;
+use crate::size::{AspectRatio, Size};
+
+/// Local edits applied to every image before it is written.
+pub struct PostProcess {
+    pub crop: Option<AspectRatio>,
+    pub resize: Option<Size>,
+}
+
+impl PostProcess {
+    pub fn is_noop(&self) -> bool {
+        self.crop.is_none() && self.resize.is_none()
+    }
+
+    /// Center-crops to the aspect ratio, then scales and crops to the exact size.
+    pub fn apply(&self, bytes: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut img = image::load_from_memory(bytes)?;
+
+        if let Some(ratio) = self.crop {
+            img = crop_to_ratio(&img, ratio);
+        }
+        if let Some(size) = self.resize {
+            img = img.resize_to_fill(size.width, size.height, FilterType::Lanczos3);
+        }
+
+        encode_png(&img)
+    }
+}
+
+/// Decodes every pixel, catching corruption inside otherwise well-formed data.
+pub fn verify(bytes: &[u8]) -> Result<(), String> {
+    image::load_from_memory(bytes)
+        .map(drop)
+        .map_err(|e| e.to_string())
+}
+
+/// Enlarges an image by a whole factor with Lanczos resampling.
+pub fn upscale(bytes: &[u8], factor: u32) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let img = image::load_from_memory(bytes)?;
+    let width = img
+        .width()
+        .checked_mul(factor)
+        .ok_or("Upscaled image is too large")?;
+    let height = img
+        .height()
+        .checked_mul(factor)
+        .ok_or("Upscaled image is too large")?;
+    encode_png(&img.resize_exact(width, height, FilterType::Lanczos3))
+}
+
+/// Scales an image down so neither side exceeds `max`, keeping its aspect ratio.
+pub fn thumbnail(bytes: &[u8], max: u32) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let img = image::load_from_memory(bytes)?;
+    encode_png(&img.thumbnail(max, max))
+}
+
+/// Brings an image generated at a nearby supported size to exactly `size`.
+pub fn fit(bytes: &[u8], size: Size, fit: Fit) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let img = image::load_from_memory(bytes)?;
+    let img = match fit {
+        Fit::Crop => img.resize_to_fill(size.width, size.height, FilterType::Lanczos3),
+        Fit::Pad => {
+            let scaled = img.resize(size.width, size.height, FilterType::Lanczos3);
+            let mut canvas = RgbaImage::new(size.width, size.height);
+            let x = size.width.saturating_sub(scaled.width()) / 2;
+            let y = size.height.saturating_sub(scaled.height()) / 2;
+            imageops::overlay(&mut canvas, &scaled.to_rgba8(), x.into(), y.into());
+            DynamicImage::ImageRgba8(canvas)
+        }
+    };
+    encode_png(&img)
+}
+
+/// How much more a wrapped edge may differ than neighbouring rows or columns
+/// inside the image before it counts as a visible seam.
+const SEAM_TOLERANCE: f64 = 2.0;
+
+/// The edges along which an image does not repeat smoothly: the difference
+/// across each seam formed when tiling is compared with the average difference
+/// between neighbouring columns or rows of the image itself.
+pub fn seams(bytes: &[u8]) -> Result<Vec<&'static str>, Box<dyn std::error::Error>> {
+    let img = image::load_from_memory(bytes)?.to_rgb8();
+    let (width, height) = img.dimensions();
+    if width < 3 || height < 3 {
+        return Ok(Vec::new());
+    }
+
+    let diff = |a: &Rgb<u8>, b: &Rgb<u8>| -> f64 {
+        a.0.iter()
+            .zip(b.0)
+            .map(|(x, y)| f64::from(x.abs_diff(y)))
+            .sum()
+    };
+    let columns = |x1, x2| -> f64 {
+        (0..height)
+            .map(|y| diff(img.get_pixel(x1, y), img.get_pixel(x2, y)))
+            .sum::<f64>()
+            / f64::from(height)
+    };
+    let rows = |y1, y2| -> f64 {
+        (0..width)
+            .map(|x| diff(img.get_pixel(x, y1), img.get_pixel(x, y2)))
+            .sum::<f64>()
+            / f64::from(width)
+    };
+
+    let mut seams = Vec::new();
+    let inside = (1..width).map(|x| columns(x - 1, x)).sum::<f64>() / f64::from(width - 1);
+    if columns(width - 1, 0) > inside * SEAM_TOLERANCE + 1.0 {
+        seams.push("left/right");
+    }
+    let inside = (1..height).map(|y| rows(y - 1, y)).sum::<f64>() / f64::from(height - 1);
+    if rows(height - 1, 0) > inside * SEAM_TOLERANCE + 1.0 {
+        seams.push("top/bottom");
+    }
+    Ok(seams)
+}
+
+/// Replaces every pixel with the nearest of `colors`, keeping transparency.
+pub fn quantize(bytes: &[u8], colors: &[[u8; 3]]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut img = image::load_from_memory(bytes)?.to_rgba8();
+    // Weighted for how strongly the eye notices differences in each channel
+    let distance = |a: [u8; 3], b: [u8; 3]| -> u32 {
+        let d = |i: usize| u32::from(a[i].abs_diff(b[i])).pow(2);
+        2 * d(0) + 4 * d(1) + 3 * d(2)
+    };
+    for pixel in img.pixels_mut() {
+        let [r, g, b, a] = pixel.0;
+        if let Some(&[r, g, b]) = colors.iter().min_by_key(|&&c| distance(c, [r, g, b])) {
+            pixel.0 = [r, g, b, a];
+        }
+    }
+    encode_png(&DynamicImage::ImageRgba8(img))
+}
+
+pub fn encode_png(img: &DynamicImage) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut out = Vec::new();
+    img.write_to(&mut Cursor::new(&mut out), ImageFormat::Png)?;
+    Ok(out)
+}
+
+/// Cuts the largest centered region with the given aspect ratio.
+fn crop_to_ratio(img: &DynamicImage, ratio: AspectRatio) -> DynamicImage {
+    let (w, h) = (u64::from(img.width()), u64::from(img.height()));
+    let (rw, rh) = (u64::from(ratio.width), u64::from(ratio.height));
+
+    let (cw, ch) = if w * rh > h * rw {
+        (h * rw / rh, h)
+    } else {
+        (w, w * rh / rw)
+    };
+    let (x, y) = ((w - cw) / 2, (h - ch) / 2);
+
+    img.crop_imm(x as u32, y as u32, cw as u32, ch as u32)
+}
@@ -0,0 +1,95 @@
+use crate::png;
+
+/// IPTC digital source types that mark an image as made by a generative model.
+const AI_SOURCE_TYPES: [&str; 2] = [
+    "trainedAlgorithmicMedia",
+    "compositeWithTrainedAlgorithmicMedia",
+];
+
+/// Provenance data found in an image file: Content Credentials, IPTC source
+/// types in XMP, and the generation parameters other tools leave in PNG text
+/// chunks. Invisible watermarks such as SynthID need the vendor's detector
+/// and are not looked for.
+pub fn inspect(bytes: &[u8]) -> Vec<String> {
+    if bytes.starts_with(&png::SIGNATURE) {
+        inspect_png(bytes)
+    } else if bytes.starts_with(&[0xff, 0xd8]) {
+        inspect_jpeg(bytes)
+    } else if contains(bytes, b"c2pa") {
+        vec!["Possible C2PA manifest".into()]
+    } else {
+        Vec::new()
+    }
+}
+
+fn inspect_png(bytes: &[u8]) -> Vec<String> {
+    let Ok(chunks) = png::chunks(bytes) else {
+        return Vec::new();
+    };
+    let mut found = Vec::new();
+    for chunk in chunks {
+        // Length and type come first, the CRC last
+        let data = &chunk.raw[8..chunk.raw.len() - 4];
+        match &chunk.kind {
+            b"caBX" => found.push("C2PA manifest (Content Credentials)".into()),
+            b"eXIf" => found.push("EXIF data".into()),
+            b"tEXt" | b"iTXt" | b"zTXt" => {
+                let keyword = data.split(|&b| b == 0).next().unwrap_or_default();
+                let keyword = String::from_utf8_lossy(keyword);
+                match keyword.as_ref() {
+                    "XML:com.adobe.xmp" => found.extend(inspect_xmp(data)),
+                    "parameters" => found.push("Stable Diffusion parameters".into()),
+                    "prompt" | "workflow" => found.push(format!("ComfyUI {keyword}")),
+                    _ => found.push(format!("Text chunk \"{keyword}\"")),
+                }
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+fn inspect_jpeg(bytes: &[u8]) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut at = 2;
+    // Walk the marker segments up to the start of the image data
+    while let Some(&[0xff, marker, hi, lo]) = bytes.get(at..at + 4) {
+        if marker == 0xda {
+            break;
+        }
+        let len = usize::from(u16::from_be_bytes([hi, lo]));
+        let Some(data) = bytes.get(at + 4..at + 2 + len) else {
+            break;
+        };
+        match marker {
+            0xeb if contains(data, b"jumb") || contains(data, b"c2pa") => {
+                found.push("C2PA manifest (Content Credentials)".into());
+            }
+            0xe1 if data.starts_with(b"Exif\0") => found.push("EXIF data".into()),
+            0xe1 if data.starts_with(b"http://ns.adobe.com/xap/1.0/") => {
+                found.extend(inspect_xmp(data));
+            }
+            _ => {}
+        }
+        at += 2 + len;
+    }
+    found.dedup();
+    found
+}
+
+fn inspect_xmp(data: &[u8]) -> Vec<String> {
+    let mut found = vec!["XMP metadata".to_string()];
+    if let Some(kind) = AI_SOURCE_TYPES
+        .iter()
+        .find(|kind| contains(data, format!("digitalsourcetype/{kind}").as_bytes()))
+    {
+        found.push(format!("IPTC digital source type: {kind}"));
+    }
+    found
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
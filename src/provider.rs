@@ -0,0 +1,134 @@
+use std::path::Path;
+
+use crate::azure::{self, GenerationResponse, ImageData};
+use crate::request::ImageRequest;
+use crate::size::{AspectRatio, Size};
+use crate::video::VideoRequest;
+use crate::{asset, mock, plugin};
+
+/// Providers compiled into imgmc; every other `--provider` is a plugin.
+pub const BUILT_IN: [&str; 3] = [azure::NAME, "openai", mock::NAME];
+
+/// Something that turns requests into images, and perhaps videos or other
+/// assets.
+pub trait Provider {
+    /// Checks `req` against what the provider supports, adjusting it where
+    /// that is allowed (e.g. truncating the prompt).
+    fn validate(&self, req: &mut ImageRequest) -> Result<(), String>;
+
+    /// The supported size whose aspect ratio is closest to `ratio`.
+    fn closest_size(&self, ratio: AspectRatio) -> Size;
+
+    fn generate(
+        &self,
+        req: &ImageRequest,
+    ) -> Result<GenerationResponse, Box<dyn std::error::Error>>;
+
+    /// Generates a video as MP4, calling `progress` with status updates while
+    /// waiting. Only some providers have a video model.
+    fn generate_video(
+        &self,
+        _req: &VideoRequest,
+        _progress: &dyn Fn(&str),
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Err("This provider cannot generate videos".into())
+    }
+
+    /// Generates a depth map or 3D model from a prompt and optionally an
+    /// image to base it on. Only some providers have such models.
+    fn generate_asset(
+        &self,
+        kind: asset::Kind,
+        _prompt: &str,
+        _reference: Option<&Path>,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Err(format!("This provider cannot generate {} assets", kind.name()).into())
+    }
+}
+
+impl Provider for azure::Client {
+    fn validate(&self, req: &mut ImageRequest) -> Result<(), String> {
+        azure::Client::validate(self, req)
+    }
+
+    fn closest_size(&self, ratio: AspectRatio) -> Size {
+        azure::Client::closest_size(self, ratio)
+    }
+
+    fn generate(
+        &self,
+        req: &ImageRequest,
+    ) -> Result<GenerationResponse, Box<dyn std::error::Error>> {
+        azure::Client::generate(self, req)
+    }
+
+    fn generate_video(
+        &self,
+        req: &VideoRequest,
+        progress: &dyn Fn(&str),
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        azure::Client::generate_video(self, req, progress)
+    }
+}
+
+/// Generates one more image for `req`, to replace one that did not pass a check.
+pub fn regenerate_one(
+    client: &dyn Provider,
+    req: &ImageRequest,
+) -> Result<ImageData, Box<dyn std::error::Error>> {
+    let mut single = req.clone();
+    single.count = 1;
+    let resp = client.generate(&single)?;
+    Ok(resp
+        .data
+        .into_iter()
+        .next()
+        .ok_or("The response contained no images")?)
+}
+
+/// Scales `ratio` to about a megapixel in multiples of 64, which most image
+/// models accept.
+pub fn megapixel_size(ratio: AspectRatio) -> Size {
+    let scale = (1024.0 * 1024.0 / (f64::from(ratio.width) * f64::from(ratio.height))).sqrt();
+    let side = |n: u32| ((f64::from(n) * scale / 64.0).round() as u32).max(1) * 64;
+    Size::new(side(ratio.width), side(ratio.height))
+}
+
+/// Accepts the built-in providers and plugins on `PATH`, which is only
+/// searched for names that are not built in.
+pub fn parse(s: &str) -> Result<String, String> {
+    if BUILT_IN.contains(&s) || plugin::Plugin::find(s).is_ok() {
+        return Ok(s.to_string());
+    }
+    let known: Vec<String> = BUILT_IN
+        .iter()
+        .map(|name| name.to_string())
+        .chain(plugin::discover())
+        .collect();
+    Err(format!(
+        "Unknown provider {s}, expected one of: {}",
+        known.join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn size(width: u32, height: u32) -> String {
+        megapixel_size(AspectRatio { width, height }).to_string()
+    }
+
+    #[test]
+    fn megapixel_size_keeps_the_ratio() {
+        assert_eq!(size(1, 1), "1024x1024");
+        assert_eq!(size(16, 9), "1344x768");
+        assert_eq!(size(2, 3), "832x1280");
+    }
+
+    #[test]
+    fn megapixel_size_survives_extreme_ratios() {
+        assert_eq!(size(u32::MAX, u32::MAX), "1024x1024");
+        assert!(size(u32::MAX, 1).ends_with("x64"));
+    }
+}
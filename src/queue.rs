@@ -0,0 +1,250 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::t;
+use crate::{output, redact};
+
+const JOB_FILE: &str = "job.json";
+const LOG_FILE: &str = "log.txt";
+/// Held by the worker while it runs, so only one works through the queue.
+const WORKER_LOCK: &str = "worker.lock";
+
+/// A generation waiting in or run from the queue. Every job has a directory
+/// in the queue directory holding this as `job.json`, the output of the run
+/// and the images it saved.
+#[derive(Serialize, Deserialize)]
+struct Job {
+    id: String,
+    /// Arguments for an imgmc generation, as given on the command line.
+    args: Vec<String>,
+    submitted: String,
+    state: State,
+    error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum State {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+impl std::fmt::Display for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            State::Queued => "queued",
+            State::Running => "running",
+            State::Done => "done",
+            State::Failed => "failed",
+        })
+    }
+}
+
+impl Job {
+    fn load(dir: &Path) -> Result<Job, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_slice(&fs::read(dir.join(JOB_FILE))?)?)
+    }
+
+    fn store(&self, dir: &Path) -> std::io::Result<()> {
+        output::write_atomic(&dir.join(JOB_FILE), &serde_json::to_vec_pretty(self)?)
+    }
+}
+
+fn queue_dir() -> std::io::Result<PathBuf> {
+    xdg::BaseDirectories::with_prefix("imgmc").create_data_directory("queue")
+}
+
+fn job_dir(id: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let dir = queue_dir()?.join(id);
+    if id.contains(['/', '\\']) || !dir.join(JOB_FILE).is_file() {
+        return Err(format!("No queued job {id}").into());
+    }
+    Ok(dir)
+}
+
+/// Jobs in the order they were submitted.
+fn jobs(queue: &Path) -> std::io::Result<Vec<(PathBuf, Job)>> {
+    let mut jobs: Vec<(PathBuf, Job)> = fs::read_dir(queue)?
+        .flatten()
+        .filter_map(|entry| Job::load(&entry.path()).ok().map(|job| (entry.path(), job)))
+        .collect();
+    jobs.sort_by(|(_, a), (_, b)| a.id.cmp(&b.id));
+    Ok(jobs)
+}
+
+/// Adds a generation to the queue and starts a worker unless one is running.
+///
+/// The job runs in its own directory, so arguments naming existing files,
+/// such as `--reference`, are made absolute first.
+pub fn submit(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let args = args
+        .iter()
+        .map(|arg| match Path::new(arg) {
+            path if path.is_relative() && path.exists() => std::path::absolute(path)
+                .map_or_else(|_| arg.clone(), |path| path.display().to_string()),
+            _ => arg.clone(),
+        })
+        .collect();
+
+    let now = chrono::Local::now();
+    let id = format!("{}-{}", now.format("%Y%m%d-%H%M%S%3f"), std::process::id());
+    let dir = queue_dir()?.join(&id);
+    fs::create_dir_all(&dir)?;
+    let job = Job {
+        id: id.clone(),
+        args,
+        submitted: now.to_rfc3339(),
+        state: State::Queued,
+        error: None,
+    };
+    job.store(&dir)?;
+
+    start_worker()?;
+    println!("{}", t!("job-submitted", id = id));
+    Ok(())
+}
+
+fn start_worker() -> std::io::Result<()> {
+    let mut worker = Command::new(std::env::current_exe()?);
+    worker
+        .args(["queue", "work"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // Keep the worker alive when the terminal that started it closes
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut worker, 0);
+    worker.spawn()?;
+    Ok(())
+}
+
+/// Prints every job, or the details of one.
+pub fn status(id: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(id) = id else {
+        for (_, job) in jobs(&queue_dir()?)? {
+            let args = redact::redact(&job.args.join(" "));
+            println!("{}  {:<7}  {args}", job.id, job.state);
+        }
+        return Ok(());
+    };
+
+    let dir = job_dir(id)?;
+    let job = Job::load(&dir)?;
+    println!("{}  {}", job.id, job.state);
+    println!("Submitted: {}", job.submitted);
+    println!("Arguments: {}", redact::redact(&job.args.join(" ")));
+    if let Some(error) = &job.error {
+        println!("Error:     {error}");
+        println!("Log:       {}", dir.join(LOG_FILE).display());
+    }
+    Ok(())
+}
+
+/// Copies the images of a finished job into `to`.
+pub fn fetch(id: &str, to: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = job_dir(id)?;
+    let job = Job::load(&dir)?;
+    match job.state {
+        State::Done => {}
+        State::Failed => {
+            let error = job.error.unwrap_or_default();
+            return Err(format!("Job {id} failed: {error}").into());
+        }
+        state => return Err(format!("Job {id} is still {state}").into()),
+    }
+
+    let mut files = Vec::new();
+    collect_files(&dir, &dir, &mut files)?;
+    for relative in files {
+        let target = to.join(&relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(dir.join(&relative), &target)?;
+        println!("{}", t!("image-saved", path = target.display()));
+    }
+    Ok(())
+}
+
+/// Files a job saved, relative to its directory, leaving out its own records.
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(root, &path, files)?;
+        } else if let Ok(relative) = path.strip_prefix(root)
+            && relative != Path::new(JOB_FILE)
+            && relative != Path::new(LOG_FILE)
+        {
+            files.push(relative.to_path_buf());
+        }
+    }
+    files.sort();
+    Ok(())
+}
+
+/// Runs queued jobs one after another until none are left. Returns at once
+/// when another worker holds the lock.
+pub fn work() -> Result<(), Box<dyn std::error::Error>> {
+    let queue = queue_dir()?;
+    let exe = std::env::current_exe()?;
+    loop {
+        let lock = File::create(queue.join(WORKER_LOCK))?;
+        if lock.try_lock().is_err() {
+            return Ok(());
+        }
+
+        // Jobs left running by a worker that was killed
+        for (dir, mut job) in jobs(&queue)? {
+            if job.state == State::Running {
+                job.state = State::Failed;
+                job.error = Some("The queue worker stopped while running it".into());
+                job.store(&dir)?;
+            }
+        }
+
+        while let Some((dir, mut job)) = jobs(&queue)?
+            .into_iter()
+            .find(|(_, job)| job.state == State::Queued)
+        {
+            job.state = State::Running;
+            job.store(&dir)?;
+
+            let log = File::create(dir.join(LOG_FILE))?;
+            let status = Command::new(&exe)
+                .args(&job.args)
+                .current_dir(&dir)
+                .stdin(Stdio::null())
+                .stdout(log.try_clone()?)
+                .stderr(log)
+                .status();
+            match status {
+                Ok(status) if status.success() => job.state = State::Done,
+                Ok(status) => {
+                    job.state = State::Failed;
+                    job.error = Some(format!("imgmc exited with {status}"));
+                }
+                Err(e) => {
+                    job.state = State::Failed;
+                    job.error = Some(e.to_string());
+                }
+            }
+            job.store(&dir)?;
+        }
+
+        // A job submitted while the last one finished would find the lock
+        // still taken, so look once more after letting go of it
+        drop(lock);
+        if !jobs(&queue)?
+            .iter()
+            .any(|(_, job)| job.state == State::Queued)
+        {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+pub const REDACTED: &str = "<redacted>";
+/// Shorter values are too likely to occur in ordinary text to be replaced.
+const MIN_SECRET_LEN: usize = 8;
+
+/// Secrets known to this process, such as API keys from the config.
+static SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+fn secrets() -> MutexGuard<'static, Vec<String>> {
+    SECRETS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Makes sure `secret` never shows up in anything passed through [`redact`].
+pub fn register(secret: &str) {
+    if secret.len() >= MIN_SECRET_LEN {
+        let mut secrets = secrets();
+        if !secrets.iter().any(|s| s == secret) {
+            secrets.push(secret.to_string());
+        }
+    }
+}
+
+/// Whether a header carries credentials rather than, say, a billing tag.
+pub fn is_sensitive_header(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    [
+        "authorization",
+        "cookie",
+        "key",
+        "token",
+        "secret",
+        "password",
+    ]
+    .iter()
+    .any(|part| name.contains(part))
+}
+
+/// Replaces every registered secret and any bearer token in `text`.
+///
+/// Everything that may end up in front of a user or in a file goes through
+/// here: error messages, warnings, debug logs and panic messages.
+pub fn redact(text: &str) -> String {
+    let mut text = text.to_string();
+    for secret in secrets().iter() {
+        text = text.replace(secret.as_str(), REDACTED);
+    }
+    bearer_tokens(&text)
+}
+
+/// Replaces what follows `Bearer ` up to the next space or quote, which
+/// catches tokens that were never registered, e.g. echoed by a gateway.
+fn bearer_tokens(text: &str) -> String {
+    const BEARER: &str = "bearer ";
+    let lower = text.to_ascii_lowercase();
+    let mut out = String::with_capacity(text.len());
+    let mut rest = 0;
+    while let Some(found) = lower[rest..].find(BEARER) {
+        let start = rest + found + BEARER.len();
+        let end = text[start..]
+            .find(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | ',' | ';'))
+            .map_or(text.len(), |i| start + i);
+        out.push_str(&text[rest..start]);
+        if end > start {
+            out.push_str(REDACTED);
+        }
+        rest = end;
+    }
+    out.push_str(&text[rest..]);
+    out
+}
+
+/// Prints panics like the default hook does, but redacted.
+pub fn install_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("Box<dyn Any>");
+        let location = info
+            .location()
+            .map(|l| format!(" at {}:{}", l.file(), l.line()))
+            .unwrap_or_default();
+        let thread = std::thread::current();
+        let name = thread.name().unwrap_or("<unnamed>");
+        eprintln!("thread '{name}' panicked{location}:\n{}", redact(message));
+
+        let backtrace = std::backtrace::Backtrace::capture();
+        if backtrace.status() == std::backtrace::BacktraceStatus::Captured {
+            eprintln!("{backtrace}");
+        }
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bearer_tokens_are_replaced() {
+        assert_eq!(
+            bearer_tokens("Authorization: Bearer abc.def-123"),
+            "Authorization: Bearer <redacted>"
+        );
+        assert_eq!(
+            bearer_tokens(r#"{"auth":"bearer xyz","next":"BEARER uvw; x"}"#),
+            r#"{"auth":"bearer <redacted>","next":"BEARER <redacted>; x"}"#
+        );
+    }
+
+    #[test]
+    fn bearer_without_token_is_kept() {
+        for text in ["", "no secrets here", "Bearer ", "Bearer \"x\"", "bearers"] {
+            assert_eq!(bearer_tokens(text), text);
+        }
+    }
+
+    #[test]
+    fn bearer_tokens_keep_text_around_non_ascii() {
+        assert_eq!(
+            bearer_tokens("ünïcode Bearer tök en"),
+            "ünïcode Bearer <redacted> en"
+        );
+    }
+}
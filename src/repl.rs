@@ -0,0 +1,50 @@
+use rustyline::DefaultEditor;
+use rustyline::error::ReadlineError;
+
+use crate::azure::Client;
+use crate::lock::StoreLock;
+use crate::output::Saver;
+use crate::request::ImageRequest;
+use crate::watch;
+
+/// Reads prompts line by line and generates each one, keeping a history in
+/// the XDG data directory so earlier prompts can be recalled across sessions.
+pub fn run(
+    client: &Client,
+    mut req: ImageRequest,
+    saver: Saver,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let history = xdg::BaseDirectories::with_prefix("imgmc").place_data_file("history.txt")?;
+    let mut editor = DefaultEditor::new()?;
+    // There is no history yet on the first run
+    let _ = editor.load_history(&history);
+
+    loop {
+        let line = match editor.readline("prompt> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let prompt = line.trim();
+        if prompt.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(prompt)?;
+        // Appended under a lock, so sessions running side by side keep each
+        // other's prompts
+        let saved = StoreLock::acquire(&history)
+            .map_err(ReadlineError::from)
+            .and_then(|_lock| editor.append_history(&history));
+        if let Err(e) = saved {
+            eprintln!("Could not save prompt history: {e}");
+        }
+
+        req.set_prompt(prompt);
+        match client.validate(&mut req) {
+            Ok(()) => watch::regenerate(client, &req, &saver),
+            Err(e) => eprintln!("{e}"),
+        }
+    }
+
+    Ok(())
+}
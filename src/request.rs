@@ -0,0 +1,260 @@
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::hooks;
+use crate::palette::Palette;
+use crate::size::Size;
+
+#[derive(ValueEnum, Deserialize, Clone, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageQuality {
+    #[default]
+    High,
+    Medium,
+    Low,
+    Auto,
+}
+
+impl std::fmt::Display for ImageQuality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let quality_str = match self {
+            ImageQuality::High => "high",
+            ImageQuality::Medium => "medium",
+            ImageQuality::Low => "low",
+            ImageQuality::Auto => "auto",
+        };
+        write!(f, "{quality_str}")
+    }
+}
+
+#[derive(ValueEnum, Deserialize, Clone, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Background {
+    #[default]
+    #[value(name = "auto")]
+    Auto,
+    #[value(name = "transparent")]
+    Transparent,
+    #[value(name = "opaque")]
+    Opaque,
+}
+
+impl std::fmt::Display for Background {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.to_possible_value().unwrap().get_name())
+    }
+}
+
+#[derive(ValueEnum, Deserialize, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum Moderation {
+    Low,
+    Auto,
+}
+
+impl std::fmt::Display for Moderation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.to_possible_value().unwrap().get_name())
+    }
+}
+
+/// How to reach a size the provider cannot generate directly.
+#[derive(ValueEnum, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Fit {
+    /// Generate the nearest supported size and center-crop it
+    Crop,
+    /// Generate the nearest supported size and pad it with transparency
+    Pad,
+}
+
+/// Everything needed to ask a provider for one or more images.
+#[derive(Clone)]
+pub struct ImageRequest {
+    pub prompt: String,
+    /// Cut prompts over the model's limit instead of refusing them.
+    pub truncate_prompt: bool,
+    pub quality: ImageQuality,
+    /// Size of the saved images, which the provider may not support directly.
+    pub resolution: Size,
+    pub fit: Option<Fit>,
+    pub background: Background,
+    pub moderation: Option<Moderation>,
+    pub count: u8,
+    pub reference: Option<PathBuf>,
+    /// Ask for a texture that repeats without visible seams.
+    pub tileable: bool,
+    /// Colors the prompt asks the images to use.
+    pub palette: Option<Palette>,
+    /// Map every pixel to the nearest palette color after generating.
+    pub quantize: bool,
+    /// Values for `{name}` placeholders in prompts.
+    pub vars: Vec<(String, String)>,
+    /// For providers that take one, so the same seed gives the same image.
+    pub seed: Option<u64>,
+}
+
+impl ImageRequest {
+    /// Sets the prompt from a template, replacing `{name}` with the matching
+    /// variable and `{env:NAME}` with the environment variable. Placeholders
+    /// without a value are left untouched.
+    pub fn set_prompt(&mut self, template: &str) {
+        self.prompt = hooks::expand(template, |name| match name.strip_prefix("env:") {
+            Some(var) => std::env::var(var).ok(),
+            None => self
+                .vars
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone()),
+        });
+    }
+}
+
+/// Expands `{a,b}` alternatives into one prompt per combination, so
+/// `"a {red,blue} {car,bike}"` gives four prompts. Braces without a comma,
+/// such as `{name}` placeholders, are kept as they are.
+pub fn expand_alternatives(prompt: &str) -> Vec<String> {
+    let mut prompts = vec![String::new()];
+    let mut rest = prompt;
+
+    while let Some(start) = rest.find('{') {
+        let after = &rest[start + 1..];
+        let group = after
+            .find('}')
+            .map(|end| &after[..end])
+            .filter(|group| group.contains(',') && !group.contains('{'));
+        let Some(group) = group else {
+            for prompt in &mut prompts {
+                prompt.push_str(&rest[..=start]);
+            }
+            rest = after;
+            continue;
+        };
+
+        let literal = &rest[..start];
+        prompts = prompts
+            .iter()
+            .flat_map(|prompt| {
+                group
+                    .split(',')
+                    .map(move |alt| format!("{prompt}{literal}{}", alt.trim()))
+            })
+            .collect();
+        rest = &after[group.len() + 1..];
+    }
+
+    for prompt in &mut prompts {
+        prompt.push_str(rest);
+    }
+    prompts
+}
+
+/// More would more likely be a typo than a sweep anyone means to pay for.
+const MAX_SEEDS: u64 = 100;
+
+/// Parses the seeds to sweep, written like Rust ranges: `1000..1010` leaves
+/// out the end, `1000..=1010` includes it.
+pub fn parse_seed_range(s: &str) -> Result<RangeInclusive<u64>, String> {
+    let invalid = || format!("Invalid seed range {s}, expected e.g. 1000..1010");
+    let empty = || format!("The seed range {s} is empty");
+    let (start, end) = s.split_once("..").ok_or_else(invalid)?;
+    let start: u64 = start.trim().parse().map_err(|_| invalid())?;
+    let end = match end.strip_prefix('=') {
+        Some(end) => end.trim().parse().map_err(|_| invalid())?,
+        None => end
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| invalid())?
+            .checked_sub(1)
+            .ok_or_else(empty)?,
+    };
+    if end < start {
+        return Err(empty());
+    }
+    if end - start >= MAX_SEEDS {
+        return Err(format!("A seed range can cover at most {MAX_SEEDS} seeds"));
+    }
+    Ok(start..=end)
+}
+
+/// Parses a `name=value` prompt variable.
+pub fn parse_var(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("Expected name=value, got {s}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alternatives_expand_every_group() {
+        assert_eq!(
+            expand_alternatives("a {red, blue} fox"),
+            ["a red fox", "a blue fox"]
+        );
+        assert_eq!(expand_alternatives("{a,b}{1,2}"), ["a1", "a2", "b1", "b2"]);
+        assert_eq!(
+            expand_alternatives("a {small, very big} dog"),
+            ["a small dog", "a very big dog"]
+        );
+    }
+
+    #[test]
+    fn alternatives_keep_braces_that_are_not_groups() {
+        for prompt in [
+            "",
+            "plain",
+            "{}",
+            "{single}",
+            "unclosed {a,b",
+            "stray } brace",
+        ] {
+            assert_eq!(expand_alternatives(prompt), [prompt]);
+        }
+        assert_eq!(expand_alternatives("{a,{b,c}}"), ["{a,b}", "{a,c}"]);
+    }
+
+    #[test]
+    fn seed_range_end_is_exclusive_unless_inclusive() {
+        assert_eq!(parse_seed_range("1000..1010"), Ok(1000..=1009));
+        assert_eq!(parse_seed_range("1000..=1010"), Ok(1000..=1010));
+        assert_eq!(parse_seed_range(" 7 ..= 7 "), Ok(7..=7));
+    }
+
+    #[test]
+    fn seed_range_rejects_empty_and_reversed() {
+        assert!(parse_seed_range("5..5").is_err());
+        assert!(parse_seed_range("0..0").is_err());
+        assert!(parse_seed_range("10..5").is_err());
+        assert!(parse_seed_range("10..=9").is_err());
+    }
+
+    #[test]
+    fn seed_range_is_capped() {
+        assert_eq!(parse_seed_range("0..100"), Ok(0..=99));
+        assert!(parse_seed_range("0..101").is_err());
+        assert!(parse_seed_range("0..=18446744073709551615").is_err());
+    }
+
+    #[test]
+    fn seed_range_rejects_malformed() {
+        for s in [
+            "",
+            "..",
+            "5",
+            "1..b",
+            "a..5",
+            "-1..5",
+            "1...5",
+            "1..=18446744073709551616",
+        ] {
+            assert!(parse_seed_range(s).is_err(), "{s}");
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+
+use crate::redact;
+use crate::request::ImageRequest;
+
+/// Warnings printed so far, kept for the run log.
+static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Prints a warning to stderr and remembers it for [`write`].
+pub fn warn(msg: String) {
+    let msg = redact::redact(&msg);
+    eprintln!("{msg}");
+    if let Ok(mut warnings) = WARNINGS.lock() {
+        warnings.push(msg);
+    }
+}
+
+/// Writes `run-<timestamp>.log` into `dir`, describing what was asked for and
+/// what came of it, so a folder of images can be handed on with its context.
+pub fn write(
+    dir: &Path,
+    started: DateTime<Local>,
+    elapsed: Duration,
+    provider: &str,
+    requests: &[ImageRequest],
+    result: Result<&[PathBuf], String>,
+) -> std::io::Result<PathBuf> {
+    let mut log = String::new();
+    let _ = writeln!(
+        log,
+        "Started:    {}",
+        started.format("%Y-%m-%d %H:%M:%S %:z")
+    );
+    let _ = writeln!(log, "Duration:   {:.1}s", elapsed.as_secs_f64());
+    let _ = writeln!(log, "Provider:   {provider}");
+
+    for req in requests {
+        let _ = writeln!(log, "\nPrompt:     {}", req.prompt);
+        let _ = writeln!(log, "Quality:    {}", req.quality);
+        let _ = writeln!(log, "Resolution: {}", req.resolution);
+        let _ = writeln!(log, "Background: {}", req.background);
+        if let Some(moderation) = &req.moderation {
+            let _ = writeln!(log, "Moderation: {moderation}");
+        }
+        let _ = writeln!(log, "Count:      {}", req.count);
+        if let Some(reference) = &req.reference {
+            let _ = writeln!(log, "Reference:  {}", reference.display());
+        }
+        if let Some(seed) = req.seed {
+            let _ = writeln!(log, "Seed:       {seed}");
+        }
+    }
+
+    match result {
+        Ok(files) => {
+            let _ = writeln!(log, "\nFiles:");
+            for file in files {
+                let name = file.file_name().unwrap_or(file.as_os_str());
+                let _ = writeln!(log, "  {}", name.to_string_lossy());
+            }
+        }
+        Err(e) => {
+            let _ = writeln!(log, "\nFailed: {e}");
+        }
+    }
+
+    let warnings = WARNINGS.lock().map(|w| w.clone()).unwrap_or_default();
+    if !warnings.is_empty() {
+        let _ = writeln!(log, "\nWarnings:");
+        for warning in warnings {
+            let _ = writeln!(log, "  {warning}");
+        }
+    }
+
+    let path = dir.join(format!("run-{}.log", started.format("%Y%m%d-%H%M%S")));
+    fs::write(&path, log)?;
+    Ok(path)
+}
@@ -0,0 +1,244 @@
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::{Datelike, Local, Timelike};
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::t;
+use crate::lock::StoreLock;
+use crate::{output, queue, redact};
+
+const FILE_NAME: &str = "schedules.json";
+
+/// A generation queued whenever its cron expression matches.
+#[derive(Serialize, Deserialize)]
+struct Schedule {
+    name: String,
+    cron: String,
+    /// Arguments for an imgmc generation, as given on the command line.
+    args: Vec<String>,
+}
+
+/// A standard five-field cron expression: minute, hour, day of month, month
+/// and day of week, each `*`, a number, a range `a-b`, a step `*/n` or `a-b/n`,
+/// or a comma-separated list of these.
+pub struct Cron {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    weekdays: Vec<bool>,
+    /// Whether day of month and day of week were both restricted, in which
+    /// case cron runs on days matching either.
+    either_day: bool,
+}
+
+impl FromStr for Cron {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!("Expected five cron fields, got {s:?}"));
+        };
+        let mut weekdays = field(weekday, 0, 7)?;
+        // Both 0 and 7 are Sunday
+        weekdays[0] |= weekdays[7];
+        Ok(Cron {
+            minutes: field(minute, 0, 59)?,
+            hours: field(hour, 0, 23)?,
+            days: field(day, 1, 31)?,
+            months: field(month, 1, 12)?,
+            weekdays,
+            either_day: day != "*" && weekday != "*",
+        })
+    }
+}
+
+/// Which values from 0 to `max` a cron field allows.
+fn field(s: &str, min: u32, max: u32) -> Result<Vec<bool>, String> {
+    let mut allowed = vec![false; max as usize + 1];
+    for part in s.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .map_err(|_| format!("Invalid cron step {step}"))?;
+                (range, step.max(1))
+            }
+            None => (part, 1),
+        };
+        let number = |n: &str| match n.parse() {
+            Ok(n) if (min..=max).contains(&n) => Ok(n),
+            _ => Err(format!("Cron value {n} is not between {min} and {max}")),
+        };
+        let (first, last) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((first, last)) => (number(first)?, number(last)?),
+            // A single value with a step runs from there to the end
+            None if step > 1 => (number(range)?, max),
+            None => (number(range)?, number(range)?),
+        };
+        if first > last {
+            return Err(format!("The cron range {range} is reversed"));
+        }
+        for value in (first..=last).step_by(step as usize) {
+            allowed[value as usize] = true;
+        }
+    }
+    Ok(allowed)
+}
+
+impl Cron {
+    pub fn matches(&self, time: &chrono::DateTime<Local>) -> bool {
+        let day = self.days[time.day() as usize];
+        let weekday = self.weekdays[time.weekday().num_days_from_sunday() as usize];
+        let day = if self.either_day {
+            day || weekday
+        } else {
+            day && weekday
+        };
+        self.minutes[time.minute() as usize]
+            && self.hours[time.hour() as usize]
+            && self.months[time.month() as usize]
+            && day
+    }
+}
+
+fn file() -> std::io::Result<PathBuf> {
+    xdg::BaseDirectories::with_prefix("imgmc").place_data_file(FILE_NAME)
+}
+
+fn load() -> Result<Vec<Schedule>, Box<dyn std::error::Error>> {
+    match fs::read(file()?) {
+        Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn store(schedules: &[Schedule]) -> Result<(), Box<dyn std::error::Error>> {
+    output::write_atomic(&file()?, &serde_json::to_vec_pretty(schedules)?)?;
+    Ok(())
+}
+
+pub fn add(name: &str, cron: &str, args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    cron.parse::<Cron>()?;
+    // Another imgmc adding or removing at the same time would undo this
+    let _lock = StoreLock::acquire(&file()?)?;
+    let mut schedules = load()?;
+    if schedules.iter().any(|s| s.name == name) {
+        return Err(format!("A schedule named {name} already exists").into());
+    }
+    schedules.push(Schedule {
+        name: name.to_string(),
+        cron: cron.to_string(),
+        args: args.to_vec(),
+    });
+    store(&schedules)?;
+    println!("{}", t!("schedule-added", name = name));
+    Ok(())
+}
+
+pub fn remove(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let _lock = StoreLock::acquire(&file()?)?;
+    let mut schedules = load()?;
+    let before = schedules.len();
+    schedules.retain(|s| s.name != name);
+    if schedules.len() == before {
+        return Err(format!("No schedule named {name}").into());
+    }
+    store(&schedules)
+}
+
+pub fn list() -> Result<(), Box<dyn std::error::Error>> {
+    for schedule in load()? {
+        println!(
+            "{}  {}  {}",
+            schedule.name,
+            schedule.cron,
+            redact::redact(&schedule.args.join(" "))
+        );
+    }
+    Ok(())
+}
+
+/// Queues every schedule when it is due, checking once a minute until the
+/// process is killed. The schedules are read again every time, so changes
+/// take effect without a restart.
+pub fn run() -> Result<(), Box<dyn std::error::Error>> {
+    loop {
+        let now = Local::now();
+        // Sleep to just past the start of the next minute
+        let wait = 60 - u64::from(now.second());
+        std::thread::sleep(Duration::from_secs(wait));
+
+        let now = Local::now();
+        for schedule in load()? {
+            match schedule.cron.parse::<Cron>() {
+                Ok(cron) if cron.matches(&now) => {
+                    if let Err(e) = queue::submit(&schedule.args) {
+                        eprintln!("Could not queue schedule {}: {e}", schedule.name);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("Schedule {}: {e}", schedule.name),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowed(values: &[bool]) -> Vec<usize> {
+        (0..values.len()).filter(|&i| values[i]).collect()
+    }
+
+    #[test]
+    fn field_accepts_values_ranges_steps_and_lists() {
+        assert_eq!(allowed(&field("*", 0, 3).unwrap()), [0, 1, 2, 3]);
+        assert_eq!(allowed(&field("5", 0, 59).unwrap()), [5]);
+        assert_eq!(allowed(&field("1-3,7", 0, 9).unwrap()), [1, 2, 3, 7]);
+        assert_eq!(allowed(&field("*/15", 0, 59).unwrap()), [0, 15, 30, 45]);
+        assert_eq!(allowed(&field("10-20/5", 0, 59).unwrap()), [10, 15, 20]);
+        assert_eq!(allowed(&field("50/5", 0, 59).unwrap()), [50, 55]);
+    }
+
+    #[test]
+    fn field_rejects_out_of_bounds_and_reversed() {
+        for s in [
+            "",
+            "60",
+            "-1",
+            "0",
+            "5-1",
+            "1-",
+            "a",
+            "*/x",
+            "1,,2",
+            "99999999999",
+        ] {
+            assert!(field(s, 1, 59).is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn cron_needs_five_fields() {
+        assert!("".parse::<Cron>().is_err());
+        assert!("* * * *".parse::<Cron>().is_err());
+        assert!("* * * * * *".parse::<Cron>().is_err());
+        assert!("*/5 9-17 * * 1-5".parse::<Cron>().is_ok());
+    }
+
+    #[test]
+    fn cron_sunday_is_0_and_7() {
+        let cron: Cron = "0 0 * * 7".parse().unwrap();
+        assert_eq!(allowed(&cron.weekdays), [0, 7]);
+        assert!(!cron.either_day);
+        assert!("0 0 1 * 1".parse::<Cron>().unwrap().either_day);
+    }
+}
@@ -0,0 +1,136 @@
+use clap::ValueEnum;
+
+use crate::azure::{Client, ImageData};
+use crate::i18n::t;
+use crate::output;
+use crate::provider::{self, Provider};
+use crate::request::ImageRequest;
+use crate::run_log;
+use crate::spinner::Spinner;
+
+/// Ways to score an image from 0 to 100.
+#[derive(ValueEnum, Clone, Copy)]
+pub enum Judge {
+    /// Sharpness and detail measured locally, free but blind to content.
+    Local,
+    /// The chat deployment set as `vision_deployment`, judging the image as a
+    /// whole.
+    Vision,
+}
+
+/// Rejects images that score below a minimum.
+pub struct QualityGate {
+    pub min_score: u8,
+    /// How often a weak image may be regenerated.
+    pub attempts: u8,
+    /// Keep images that never reach the minimum instead of dropping them.
+    pub keep_weak: bool,
+    /// Scores the images instead of the local heuristics.
+    pub vision: Option<Client>,
+}
+
+impl QualityGate {
+    /// Scores every image in `data` and regenerates those below the minimum.
+    /// Images still below it after all attempts are dropped, or kept with a
+    /// warning if `keep_weak` is set.
+    pub fn ensure(
+        &self,
+        client: &dyn Provider,
+        req: &ImageRequest,
+        data: &mut Vec<ImageData>,
+        progress: &Spinner,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut weak = Vec::new();
+        for (i, item) in data.iter_mut().enumerate() {
+            for attempt in 1.. {
+                let score = self.score(&output::decode(req, item)?)?;
+                if score >= self.min_score {
+                    break;
+                }
+                if attempt > self.attempts {
+                    let min = self.min_score;
+                    if self.keep_weak {
+                        run_log::warn(t!(
+                            "low-score-kept",
+                            index = i + 1,
+                            score = score,
+                            min = min
+                        ));
+                    } else {
+                        run_log::warn(t!(
+                            "low-score-dropped",
+                            index = i + 1,
+                            score = score,
+                            min = min
+                        ));
+                        weak.push(i);
+                    }
+                    break;
+                }
+                progress.set_message(t!(
+                    "regenerating-for-score",
+                    index = i + 1,
+                    score = score,
+                    attempt = attempt,
+                    attempts = self.attempts
+                ));
+                *item = provider::regenerate_one(client, req)?;
+            }
+        }
+        for i in weak.into_iter().rev() {
+            data.remove(i);
+        }
+        Ok(())
+    }
+
+    fn score(&self, png: &[u8]) -> Result<u8, Box<dyn std::error::Error>> {
+        match &self.vision {
+            Some(client) => client.judge(png),
+            None => local_score(png),
+        }
+    }
+}
+
+/// Averages sharpness, from the variance of the Laplacian, and detail, from
+/// the entropy of the brightness histogram. Blurry images have little edge
+/// contrast, empty or washed-out ones few distinct tones.
+fn local_score(png: &[u8]) -> Result<u8, Box<dyn std::error::Error>> {
+    let img = image::load_from_memory(png)?.into_luma8();
+    let (width, height) = img.dimensions();
+    if width < 3 || height < 3 {
+        return Ok(0);
+    }
+
+    let px = |x: u32, y: u32| f64::from(img.get_pixel(x, y).0[0]);
+    let (mut sum, mut sum_sq, mut n) = (0.0, 0.0, 0.0);
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let lap = 4.0 * px(x, y) - px(x - 1, y) - px(x + 1, y) - px(x, y - 1) - px(x, y + 1);
+            sum += lap;
+            sum_sq += lap * lap;
+            n += 1.0;
+        }
+    }
+    let variance = sum_sq / n - (sum / n).powi(2);
+    // A variance of 1000 is crisp; the log keeps mild softness from
+    // counting as much as heavy blur
+    let sharpness = ((1.0 + variance).log10() / 3.0).min(1.0);
+
+    let mut histogram = [0u64; 256];
+    for pixel in img.pixels() {
+        histogram[usize::from(pixel.0[0])] += 1;
+    }
+    let total = f64::from(width) * f64::from(height);
+    let entropy: f64 = histogram
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / total;
+            -p * p.log2()
+        })
+        .sum();
+    // 8 bits is every tone equally often, photos get about 7
+    let detail = (entropy / 7.0).min(1.0);
+
+    Ok(((sharpness + detail) * 50.0).round() as u8)
+}
@@ -0,0 +1,108 @@
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use rhai::{AST, Dynamic, Engine, Map, Scope};
+
+use crate::config::ConfigError;
+use crate::request::{ImageQuality, ImageRequest};
+
+/// File in the config directory holding the script hooks.
+const FILE_NAME: &str = "hooks.rhai";
+
+/// Rhai functions from `hooks.rhai` that are called around every generation.
+///
+/// `before_request(req)` gets the request as a map with `prompt`, `quality`,
+/// `resolution` and `count` and returns it, changed or not; throwing refuses
+/// the request. `after_save(file, prompt, provider)` is called for every saved
+/// image. Both are optional.
+pub struct Hooks {
+    engine: Engine,
+    ast: AST,
+}
+
+impl Hooks {
+    /// Loads `hooks.rhai` from the config directory if there is one.
+    pub fn load() -> Result<Option<Hooks>, Box<dyn std::error::Error>> {
+        match xdg::BaseDirectories::with_prefix("imgmc").find_config_file(FILE_NAME) {
+            Some(path) => Hooks::load_from(&path).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn load_from(path: &Path) -> Result<Hooks, Box<dyn std::error::Error>> {
+        let engine = Engine::new();
+        let ast = engine
+            .compile_file(PathBuf::from(path))
+            .map_err(|e| ConfigError(format!("{}: {e}", path.display())))?;
+        Ok(Hooks { engine, ast })
+    }
+
+    fn defines(&self, name: &str) -> bool {
+        self.ast.iter_functions().any(|f| f.name == name)
+    }
+
+    pub fn before_request(&self, req: &mut ImageRequest) -> Result<(), String> {
+        if !self.defines("before_request") {
+            return Ok(());
+        }
+
+        let mut map = Map::new();
+        map.insert("prompt".into(), req.prompt.clone().into());
+        map.insert("quality".into(), req.quality.to_string().into());
+        map.insert("resolution".into(), req.resolution.to_string().into());
+        map.insert("count".into(), i64::from(req.count).into());
+
+        let result: Dynamic = self
+            .engine
+            .call_fn(&mut Scope::new(), &self.ast, "before_request", (map,))
+            .map_err(|e| format!("before_request in {FILE_NAME}: {e}"))?;
+        let map = result
+            .try_cast::<Map>()
+            .ok_or_else(|| format!("before_request in {FILE_NAME} must return the request"))?;
+
+        let string = |key: &str| {
+            map.get(key)
+                .map(|value| value.clone().into_string())
+                .transpose()
+                .map_err(|found| format!("{key} from {FILE_NAME} must be a string, not {found}"))
+        };
+        if let Some(prompt) = string("prompt")? {
+            req.prompt = prompt;
+        }
+        if let Some(quality) = string("quality")? {
+            req.quality = ImageQuality::from_str(&quality, true)?;
+        }
+        if let Some(resolution) = string("resolution")? {
+            req.resolution = resolution.parse()?;
+        }
+        if let Some(count) = map.get("count") {
+            let count = count
+                .as_int()
+                .ok()
+                .and_then(|count| u8::try_from(count).ok())
+                .ok_or_else(|| format!("count from {FILE_NAME} must be a number from 1 to 255"))?;
+            req.count = count;
+        }
+        Ok(())
+    }
+
+    pub fn after_save(
+        &self,
+        file: &Path,
+        prompt: &str,
+        provider: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.defines("after_save") {
+            return Ok(());
+        }
+        let args = (
+            file.display().to_string(),
+            prompt.to_string(),
+            provider.to_string(),
+        );
+        self.engine
+            .call_fn::<Dynamic>(&mut Scope::new(), &self.ast, "after_save", args)
+            .map_err(|e| format!("after_save in {FILE_NAME}: {e}"))?;
+        Ok(())
+    }
+}
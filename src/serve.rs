@@ -0,0 +1,350 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::azure::{Client, ImageData};
+use crate::exit;
+use crate::metrics::Metrics;
+use crate::output::{self, Saver};
+use crate::palette::Palette;
+use crate::png;
+use crate::redact;
+use crate::request::{Background, Fit, ImageQuality, ImageRequest, Moderation};
+use crate::size::Size;
+use crate::spinner::Spinner;
+
+const MAX_BODY_LEN: usize = 1024 * 1024;
+
+/// Connections handled at once; more are turned away with 503 rather than
+/// each getting a thread.
+const MAX_CONNECTIONS: usize = 32;
+
+/// How long a client may take to send its request or read the response.
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Unknown fields are refused, so a request with a `reference`, which is not
+/// taken over HTTP, fails instead of generating something else.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GenerateBody {
+    prompt: String,
+    #[serde(default)]
+    truncate: bool,
+    #[serde(default)]
+    quality: ImageQuality,
+    #[serde(default = "default_resolution")]
+    resolution: Size,
+    fit: Option<Fit>,
+    #[serde(default)]
+    background: Background,
+    moderation: Option<Moderation>,
+    #[serde(default = "default_count")]
+    count: u8,
+    #[serde(default)]
+    tileable: bool,
+    palette: Option<Palette>,
+    #[serde(default)]
+    quantize: bool,
+    #[serde(default)]
+    vars: std::collections::HashMap<String, String>,
+    /// Save to disk and return the paths instead of the image bytes.
+    #[serde(default)]
+    save: bool,
+}
+
+fn default_resolution() -> Size {
+    Size {
+        width: 1024,
+        height: 1024,
+    }
+}
+
+fn default_count() -> u8 {
+    1
+}
+
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn json(status: u16, value: serde_json::Value) -> Response {
+        Response {
+            status,
+            content_type: "application/json",
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    pub fn error(status: u16, msg: impl std::fmt::Display) -> Response {
+        let msg = redact::redact(&msg.to_string());
+        Response::json(status, serde_json::json!({ "error": msg }))
+    }
+
+    pub fn write_to(&self, stream: &mut TcpStream) -> std::io::Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            404 => "Not Found",
+            413 => "Payload Too Large",
+            429 => "Too Many Requests",
+            502 => "Bad Gateway",
+            503 => "Service Unavailable",
+            507 => "Insufficient Storage",
+            _ => "Internal Server Error",
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.status,
+            reason,
+            self.content_type,
+            self.body.len()
+        )?;
+        stream.write_all(&self.body)?;
+        stream.flush()
+    }
+}
+
+struct State {
+    client: Client,
+    saver: Saver,
+    metrics: Metrics,
+    /// Connections being handled right now.
+    active: AtomicUsize,
+}
+
+/// Serves `POST /generate` and `GET /metrics` on `addr` until the process is
+/// killed.
+pub fn run(
+    addr: SocketAddr,
+    client: Client,
+    saver: Saver,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind(addr)?;
+    let state = Arc::new(State {
+        client,
+        saver,
+        metrics: Metrics::default(),
+        active: AtomicUsize::new(0),
+    });
+    eprintln!("Listening on http://{addr}");
+
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("Connection failed: {e}");
+                continue;
+            }
+        };
+        // A client that stalls must not hold its thread forever
+        let timeouts = stream
+            .set_read_timeout(Some(IO_TIMEOUT))
+            .and_then(|()| stream.set_write_timeout(Some(IO_TIMEOUT)));
+        if let Err(e) = timeouts {
+            eprintln!("Connection failed: {e}");
+            continue;
+        }
+        if state.active.fetch_add(1, Ordering::Relaxed) >= MAX_CONNECTIONS {
+            state.active.fetch_sub(1, Ordering::Relaxed);
+            let _ = Response::error(503, "Too many connections").write_to(&mut stream);
+            continue;
+        }
+        let state = Arc::clone(&state);
+        thread::spawn(move || {
+            if let Err(e) = handle(stream, &state) {
+                eprintln!("Request failed: {}", redact::redact(&e.to_string()));
+            }
+            state.active.fetch_sub(1, Ordering::Relaxed);
+        });
+    }
+
+    Ok(())
+}
+
+/// The parts of an HTTP request that the servers here look at.
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// `None` when the body is longer than allowed and was left unread.
+    pub body: Option<Vec<u8>>,
+}
+
+/// Reads one request from `stream`, with a body of up to `max_body_len` bytes.
+pub fn read_request(
+    stream: &TcpStream,
+    max_body_len: usize,
+) -> Result<Request, Box<dyn std::error::Error>> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0usize;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse()?;
+        }
+    }
+
+    let body = if content_length > max_body_len {
+        None
+    } else {
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+        Some(body)
+    };
+    Ok(Request { method, path, body })
+}
+
+fn handle(mut stream: TcpStream, state: &State) -> Result<(), Box<dyn std::error::Error>> {
+    let request = read_request(&stream, MAX_BODY_LEN)?;
+    let response = match request.body {
+        None => Response::error(413, "Request body too large"),
+        Some(body) => match (request.method.as_str(), request.path.as_str()) {
+            ("POST", "/generate") => {
+                let started = Instant::now();
+                let response = generate(state, &body);
+                state.metrics.request(response.status, started.elapsed());
+                response
+            }
+            ("GET", "/metrics") => Response {
+                status: 200,
+                content_type: "text/plain; version=0.0.4",
+                body: state.metrics.render().into_bytes(),
+            },
+            _ => Response::error(404, "Not found"),
+        },
+    };
+
+    response.write_to(&mut stream)?;
+    Ok(())
+}
+
+fn generate(state: &State, body: &[u8]) -> Response {
+    let body: GenerateBody = match serde_json::from_slice(body) {
+        Ok(body) => body,
+        Err(e) => {
+            state.metrics.failure("invalid_request");
+            return Response::error(400, e);
+        }
+    };
+
+    let save = body.save;
+    let mut req = ImageRequest {
+        prompt: String::new(),
+        truncate_prompt: body.truncate,
+        quality: body.quality,
+        resolution: body.resolution,
+        fit: body.fit,
+        background: body.background,
+        moderation: body.moderation,
+        count: body.count,
+        // Taking a path from the request would let any caller have a local
+        // file read and uploaded to the provider
+        reference: None,
+        tileable: body.tileable,
+        palette: body.palette,
+        quantize: body.quantize,
+        vars: body.vars.into_iter().collect(),
+        seed: None,
+    };
+    req.set_prompt(&body.prompt);
+
+    if let Err(e) = state.client.validate(&mut req) {
+        state.metrics.failure("invalid_request");
+        return Response::error(400, e);
+    }
+
+    if save && let Err(e) = state.saver.preflight(&req) {
+        state.metrics.failure("insufficient_storage");
+        return Response::error(507, e);
+    }
+
+    // Through the saver, so its --jitter, --verify-text and --min-score apply
+    let data = match state.saver.fetch(&state.client, &req, &Spinner::hidden()) {
+        Ok(data) => data,
+        Err(e) => {
+            state.metrics.failure(exit::class(e.as_ref()));
+            return Response::error(502, e);
+        }
+    };
+    state.metrics.spend(state.client.estimated_cost(&req));
+
+    // Flagged filter categories for each image, in response order
+    let filter: Vec<Vec<String>> = data.iter().map(ImageData::filter_annotations).collect();
+    let provenance: Vec<Vec<String>> = data.iter().map(ImageData::provenance).collect();
+
+    if save {
+        return match state.saver.save(&req, &data) {
+            Ok(paths) => {
+                let paths: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+                Response::json(
+                    200,
+                    serde_json::json!({
+                        "paths": paths,
+                        "content_filter": filter,
+                        "provenance": provenance,
+                    }),
+                )
+            }
+            Err(e) => {
+                state.metrics.failure(exit::class(e.as_ref()));
+                Response::error(500, e)
+            }
+        };
+    }
+
+    state.saver.record(&req, Ok(&[]));
+    match data.as_slice() {
+        [image] => match output::decode(&req, image) {
+            Ok(bytes) => Response {
+                status: 200,
+                // DALL·E can answer with JPEG
+                content_type: if bytes.starts_with(&png::SIGNATURE) {
+                    "image/png"
+                } else {
+                    "image/jpeg"
+                },
+                body: bytes,
+            },
+            Err(e) => {
+                state.metrics.failure(exit::class(e.as_ref()));
+                Response::error(500, e)
+            }
+        },
+        images => {
+            let images: Vec<&str> = images.iter().map(|i| i.b64_json.as_str()).collect();
+            Response::json(
+                200,
+                serde_json::json!({
+                    "images": images,
+                    "content_filter": filter,
+                    "provenance": provenance,
+                }),
+            )
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use image::{DynamicImage, Rgba, RgbaImage, imageops, imageops::FilterType};
+
+use crate::process;
+use crate::size::Size;
+
+/// Height every image is scaled to on the sheet.
+const ROW_HEIGHT: u32 = 512;
+const GAP: u32 = 16;
+/// Pixels per font dot; glyphs are 3x5 dots.
+const SCALE: u32 = 5;
+const LABEL_HEIGHT: u32 = 5 * SCALE + 2 * GAP;
+/// Width of a glyph plus the space after it.
+const ADVANCE: u32 = 4 * SCALE;
+const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+/// Puts the images side by side on one sheet, each with its label above it.
+pub fn render(columns: &[(String, Vec<u8>)]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut images = Vec::with_capacity(columns.len());
+    for (label, bytes) in columns {
+        let img = image::load_from_memory(bytes)?;
+        let width = (u64::from(img.width()) * u64::from(ROW_HEIGHT) / u64::from(img.height()))
+            .max(1) as u32;
+        images.push((
+            label,
+            img.resize_exact(width, ROW_HEIGHT, FilterType::Lanczos3),
+        ));
+    }
+
+    let width = images.iter().map(|(_, img)| img.width() + GAP).sum::<u32>() + GAP;
+    let height = LABEL_HEIGHT + ROW_HEIGHT + GAP;
+    let mut sheet = RgbaImage::from_pixel(width, height, WHITE);
+
+    let mut x = GAP;
+    for (label, img) in &images {
+        draw_text(&mut sheet, label, x, GAP, img.width(), BLACK);
+        imageops::overlay(&mut sheet, &img.to_rgba8(), x.into(), LABEL_HEIGHT.into());
+        x += img.width() + GAP;
+    }
+
+    process::encode_png(&DynamicImage::ImageRgba8(sheet))
+}
+
+/// Adds a strip with `label` above the image, for telling apart images in
+/// a batch under review.
+pub fn caption(bytes: &[u8], label: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let img = image::load_from_memory(bytes)?;
+    let height = LABEL_HEIGHT + img.height();
+    let mut captioned = RgbaImage::from_pixel(img.width(), height, WHITE);
+    let max_width = img.width().saturating_sub(2 * GAP);
+    draw_text(&mut captioned, label, GAP, GAP, max_width, BLACK);
+    imageops::overlay(&mut captioned, &img.to_rgba8(), 0, LABEL_HEIGHT.into());
+    process::encode_png(&DynamicImage::ImageRgba8(captioned))
+}
+
+/// An image of `size` filled with `color`, with `text` written across it
+/// line by line as far as it fits.
+pub fn placeholder(
+    size: Size,
+    color: [u8; 3],
+    text: &str,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let [r, g, b] = color;
+    let mut img = RgbaImage::from_pixel(size.width, size.height, Rgba([r, g, b, 255]));
+    // Dark text on light colors and light text on dark ones
+    let luma = (299 * u32::from(r) + 587 * u32::from(g) + 114 * u32::from(b)) / 1000;
+    let ink = if luma > 127 { BLACK } else { WHITE };
+
+    let max_width = size.width.saturating_sub(2 * GAP);
+    let fits = (max_width / ADVANCE) as usize;
+    let mut lines: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if line.chars().count() + 1 + word.chars().count() <= fits => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_string()),
+        }
+    }
+
+    let line_height = 5 * SCALE + GAP;
+    for (i, line) in lines.iter().enumerate() {
+        let y = GAP + i as u32 * line_height;
+        if y + 5 * SCALE > size.height {
+            break;
+        }
+        draw_text(&mut img, line, GAP, y, max_width, ink);
+    }
+    process::encode_png(&DynamicImage::ImageRgba8(img))
+}
+
+/// Draws `text` in `ink`, cut off where it would exceed `max_width`.
+fn draw_text(img: &mut RgbaImage, text: &str, x: u32, y: u32, max_width: u32, ink: Rgba<u8>) {
+    let fits = (max_width / ADVANCE) as usize;
+    for (i, c) in text.chars().take(fits).enumerate() {
+        let left = x + i as u32 * ADVANCE;
+        for (row, bits) in glyph(c).into_iter().enumerate() {
+            for col in 0..3 {
+                if bits & (0b100 >> col) == 0 {
+                    continue;
+                }
+                for dy in 0..SCALE {
+                    for dx in 0..SCALE {
+                        let px = left + col * SCALE + dx;
+                        let py = y + row as u32 * SCALE + dy;
+                        img.put_pixel(px, py, ink);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A 3x5 bitmap of the character, one row per entry with the leftmost dot in
+/// the highest bit. Lowercase is drawn as uppercase, unknown characters as `?`.
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '$' => [0b011, 0b110, 0b010, 0b011, 0b110],
+        ' ' => [0; 5],
+        _ => [0b111, 0b001, 0b010, 0b000, 0b010],
+    }
+}
@@ -0,0 +1,26 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Writes an ASCII-armored detached GPG signature next to `file` as
+/// `<file>.asc`, made with `key` or else gpg's default key.
+pub fn sign(file: &Path, key: Option<&str>) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let mut signature = file.as_os_str().to_owned();
+    signature.push(".asc");
+    let signature = PathBuf::from(signature);
+
+    let mut cmd = Command::new("gpg");
+    cmd.args(["--batch", "--yes", "--armor", "--detach-sign"]);
+    if let Some(key) = key {
+        cmd.arg("--local-user").arg(key);
+    }
+    let status = cmd
+        .arg("--output")
+        .arg(&signature)
+        .arg(file)
+        .status()
+        .map_err(|e| format!("Could not run gpg: {e}"))?;
+    if !status.success() {
+        return Err(format!("gpg failed with {status}").into());
+    }
+    Ok(signature)
+}
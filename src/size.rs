@@ -0,0 +1,101 @@
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+fn parse_pair(s: &str, sep: char, what: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("Invalid {what} {s}, expected two numbers separated by {sep}");
+    let (a, b) = s.split_once(sep).ok_or_else(invalid)?;
+    let a: u32 = a.trim().parse().map_err(|_| invalid())?;
+    let b: u32 = b.trim().parse().map_err(|_| invalid())?;
+    if a == 0 || b == 0 {
+        return Err(invalid());
+    }
+    Ok((a, b))
+}
+
+/// Pixel dimensions written as `WIDTHxHEIGHT`.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct Size {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Size {
+    pub const fn new(width: u32, height: u32) -> Size {
+        Size { width, height }
+    }
+}
+
+impl FromStr for Size {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, height) = parse_pair(s, 'x', "size")?;
+        Ok(Size { width, height })
+    }
+}
+
+impl TryFrom<String> for Size {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl std::fmt::Display for Size {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+/// An aspect ratio written as `W:H`.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(try_from = "String")]
+pub struct AspectRatio {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for AspectRatio {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, height) = parse_pair(s, ':', "aspect ratio")?;
+        Ok(AspectRatio { width, height })
+    }
+}
+
+impl TryFrom<String> for AspectRatio {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Exact image sizes expected by social media sites.
+#[derive(ValueEnum, Clone, Copy)]
+pub enum Preset {
+    /// 1200x630 link preview (Open Graph)
+    OgImage,
+    /// 1500x500 Twitter/X profile header
+    TwitterHeader,
+    /// 1080x1080 Instagram feed post
+    InstagramPost,
+    /// 1280x720 YouTube thumbnail
+    YoutubeThumb,
+}
+
+impl Preset {
+    pub const fn size(self) -> Size {
+        match self {
+            Preset::OgImage => Size::new(1200, 630),
+            Preset::TwitterHeader => Size::new(1500, 500),
+            Preset::InstagramPost => Size::new(1080, 1080),
+            Preset::YoutubeThumb => Size::new(1280, 720),
+        }
+    }
+}
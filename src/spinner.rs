@@ -1,42 +1,207 @@
 use std::{
-    io::{self, Write},
+    io::{self, IsTerminal, Write},
     sync::{
-        Arc,
+        Arc, Mutex,
         atomic::{AtomicBool, Ordering},
     },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
+use crate::i18n::t;
+
+/// How often the plain fallback reports that it is still waiting.
+const PLAIN_INTERVAL: Duration = Duration::from_secs(15);
+/// Width of a determinate bar in characters, without the brackets.
+const BAR_WIDTH: usize = 20;
+
+static ANIMATION_DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Forces the plain line-based fallback, e.g. for `--no-spinner`.
+pub fn disable_animation() {
+    ANIMATION_DISABLED.store(true, Ordering::Relaxed);
+}
+
+/// Only animate on a real terminal that has not opted out via `NO_COLOR`.
+fn animate() -> bool {
+    !ANIMATION_DISABLED.load(Ordering::Relaxed)
+        && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+        && io::stderr().is_terminal()
+}
+
+/// What the drawing thread shows, shared with the [`Spinner`] handle.
+struct State {
+    message: String,
+    /// Steps done and total steps for a determinate bar.
+    progress: Option<(usize, usize)>,
+    /// Set while someone else prints, see [`Spinner::suspend`].
+    paused: bool,
+    /// Whether the plain fallback still has to print the current state.
+    changed: bool,
+}
+
+impl State {
+    fn line(&self, frame: &str) -> String {
+        match self.progress {
+            Some((done, total)) => {
+                let filled = (done * BAR_WIDTH).checked_div(total).unwrap_or(BAR_WIDTH);
+                format!(
+                    "[{}{}] {done}/{total} {}",
+                    "#".repeat(filled),
+                    "-".repeat(BAR_WIDTH - filled),
+                    self.message
+                )
+            }
+            None => format!("{frame} {}", self.message),
+        }
+    }
+
+    fn plain_line(&self) -> String {
+        match self.progress {
+            Some((done, total)) => format!("{done}/{total} {}", self.message),
+            None => self.message.clone(),
+        }
+    }
+}
+
 pub struct Spinner {
+    state: Arc<Mutex<State>>,
+    animate: bool,
     stop: Arc<AtomicBool>,
     handle: Option<thread::JoinHandle<()>>,
 }
 
 impl Spinner {
     pub fn start(msg: impl Into<String>) -> Spinner {
-        let msg = msg.into();
+        Spinner::spawn(msg.into(), None)
+    }
+
+    /// A determinate progress bar over `total` steps, advanced with [`Spinner::inc`].
+    pub fn bar(msg: impl Into<String>, total: usize) -> Spinner {
+        Spinner::spawn(msg.into(), Some((0, total)))
+    }
+
+    /// Shows nothing, for callers whose progress no one watches, such as
+    /// requests to the HTTP server.
+    pub fn hidden() -> Spinner {
+        Spinner {
+            state: Arc::new(Mutex::new(State {
+                message: String::new(),
+                progress: None,
+                paused: false,
+                changed: false,
+            })),
+            animate: false,
+            stop: Arc::new(AtomicBool::new(true)),
+            handle: None,
+        }
+    }
+
+    fn spawn(message: String, progress: Option<(usize, usize)>) -> Spinner {
+        let state = Arc::new(Mutex::new(State {
+            message,
+            progress,
+            paused: false,
+            changed: true,
+        }));
         let stop = Arc::new(AtomicBool::new(false));
-        let stop2 = stop.clone();
-        let handle = thread::spawn(move || {
-            let frames = ["-", "\\", "|", "/"];
-            let mut i = 0usize;
-            let mut out = io::stderr(); // write to stderr
-            while !stop2.load(Ordering::Relaxed) {
-                let _ = write!(out, "\r{} {}", frames[i % frames.len()], msg);
-                let _ = out.flush();
-                i = (i + 1) % frames.len();
-                thread::sleep(Duration::from_millis(80));
+        let animate = animate();
+        let handle = thread::spawn({
+            let state = state.clone();
+            let stop = stop.clone();
+            move || {
+                if animate {
+                    spin(&state, &stop);
+                } else {
+                    report(&state, &stop);
+                }
             }
-            // Clear the line
-            let _ = write!(out, "\r\x1b[2K");
-            let _ = out.flush();
         });
         Spinner {
+            state,
+            animate,
             stop,
             handle: Some(handle),
         }
     }
+
+    pub fn set_message(&self, msg: impl Into<String>) {
+        if let Ok(mut state) = self.state.lock() {
+            state.message = msg.into();
+            state.changed = true;
+        }
+    }
+
+    /// Marks one more step of a bar as done.
+    pub fn inc(&self) {
+        if let Ok(mut state) = self.state.lock()
+            && let Some((done, total)) = &mut state.progress
+        {
+            *done = (*done + 1).min(*total);
+            state.changed = true;
+        }
+    }
+
+    /// Hides the spinner while `f` runs, so whatever it prints is not garbled.
+    pub fn suspend<T>(&self, f: impl FnOnce() -> T) -> T {
+        if let Ok(mut state) = self.state.lock() {
+            state.paused = true;
+            if self.animate {
+                clear_line(&mut io::stderr());
+            }
+        }
+        let result = f();
+        if let Ok(mut state) = self.state.lock() {
+            state.paused = false;
+        }
+        result
+    }
+}
+
+fn clear_line(out: &mut impl Write) {
+    let _ = write!(out, "\r\x1b[2K");
+    let _ = out.flush();
+}
+
+fn spin(state: &Mutex<State>, stop: &AtomicBool) {
+    let frames = ["-", "\\", "|", "/"];
+    let mut i = 0usize;
+    let mut out = io::stderr(); // write to stderr
+    while !stop.load(Ordering::Relaxed) {
+        // Drawing under the lock keeps suspend() from racing a half-drawn frame
+        if let Ok(state) = state.lock()
+            && !state.paused
+        {
+            let _ = write!(out, "\r\x1b[2K{}", state.line(frames[i]));
+            let _ = out.flush();
+        }
+        i = (i + 1) % frames.len();
+        thread::sleep(Duration::from_millis(80));
+    }
+    clear_line(&mut out);
+}
+
+/// Plain fallback for logs: every new message or step once, and a line every
+/// interval while nothing changes.
+fn report(state: &Mutex<State>, stop: &AtomicBool) {
+    let started = Instant::now();
+    let mut next = PLAIN_INTERVAL;
+    let mut out = io::stderr();
+    while !stop.load(Ordering::Relaxed) {
+        if let Ok(mut state) = state.lock()
+            && !state.paused
+            && state.changed
+        {
+            let _ = writeln!(out, "{}", state.plain_line());
+            state.changed = false;
+        }
+        thread::sleep(Duration::from_millis(80));
+        let elapsed = started.elapsed();
+        if elapsed >= next {
+            let _ = writeln!(out, "{}", t!("still-waiting", seconds = elapsed.as_secs()));
+            next += PLAIN_INTERVAL;
+        }
+    }
 }
 
 impl Drop for Spinner {
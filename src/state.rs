@@ -0,0 +1,146 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::i18n::t;
+use crate::redact;
+
+/// What makes up imgmc's state, by where it lives: `hooks.rhai` next to the
+/// config, the REPL history and the schedules in the data directory. Queued
+/// jobs and the audit log stay with the machine that ran them.
+const FILES: [(Place, &str); 4] = [
+    (Place::Config, "config.toml"),
+    (Place::Config, "hooks.rhai"),
+    (Place::Data, "history.txt"),
+    (Place::Data, "schedules.json"),
+];
+
+#[derive(Clone, Copy)]
+enum Place {
+    Config,
+    Data,
+}
+
+impl Place {
+    /// Directory in the archive.
+    fn name(self) -> &'static str {
+        match self {
+            Place::Config => "config",
+            Place::Data => "data",
+        }
+    }
+
+    fn find(self, file: &str) -> Option<PathBuf> {
+        let dirs = xdg::BaseDirectories::with_prefix("imgmc");
+        match self {
+            Place::Config => dirs.find_config_file(file),
+            Place::Data => dirs.find_data_file(file),
+        }
+    }
+
+    fn place(self, file: &str) -> std::io::Result<PathBuf> {
+        let dirs = xdg::BaseDirectories::with_prefix("imgmc");
+        match self {
+            Place::Config => dirs.place_config_file(file),
+            Place::Data => dirs.place_data_file(file),
+        }
+    }
+}
+
+/// Bundles the state into `archive`, compressed as its extension says, e.g.
+/// `state.tar.zst`. Secrets the config was loaded with are replaced in the
+/// copy of `config.toml`, so the archive can be handed around.
+pub fn export(archive: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let staging = staging_dir()?;
+    for (place, file) in FILES {
+        let Some(source) = place.find(file) else {
+            continue;
+        };
+        fs::create_dir_all(staging.join(place.name()))?;
+        let target = staging.join(place.name()).join(file);
+        if file == "config.toml" {
+            fs::write(&target, redact::redact(&fs::read_to_string(&source)?))?;
+        } else {
+            fs::copy(&source, &target)?;
+        }
+    }
+
+    // tar picks the compression from the extension with -a
+    let result = Command::new("tar")
+        .arg("-a")
+        .arg("-cf")
+        .arg(std::path::absolute(archive)?)
+        .arg("-C")
+        .arg(&staging)
+        .arg(".")
+        .status();
+    let _ = fs::remove_dir_all(&staging);
+    match result {
+        Ok(status) if status.success() => {}
+        Ok(status) => return Err(format!("tar failed with {status}").into()),
+        Err(e) => return Err(format!("Could not run tar: {e}").into()),
+    }
+    eprintln!("{}", t!("state-exported", path = archive.display()));
+    Ok(())
+}
+
+/// Restores the state from an archive written by [`export`]. Existing files
+/// are only replaced with `force`; files in the archive imgmc does not know
+/// are left out.
+pub fn import(archive: &Path, force: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let staging = staging_dir()?;
+    let result = Command::new("tar")
+        .arg("-xf")
+        .arg(std::path::absolute(archive)?)
+        .arg("-C")
+        .arg(&staging)
+        .status();
+    let restored = match result {
+        Ok(status) if status.success() => restore(&staging, force),
+        Ok(status) => Err(format!("tar failed with {status}").into()),
+        Err(e) => Err(format!("Could not run tar: {e}").into()),
+    };
+    let _ = fs::remove_dir_all(&staging);
+    let config = restored?;
+
+    eprintln!("{}", t!("state-imported", path = archive.display()));
+    if let Some(config) = config
+        && fs::read_to_string(&config)?.contains(redact::REDACTED)
+    {
+        eprintln!("{}", t!("state-secrets-missing", path = config.display()));
+    }
+    Ok(())
+}
+
+/// Copies the known files from an unpacked archive into place, returning
+/// where the config went if there was one.
+fn restore(staging: &Path, force: bool) -> Result<Option<PathBuf>, Box<dyn std::error::Error>> {
+    let mut files = Vec::new();
+    for (place, file) in FILES {
+        let source = staging.join(place.name()).join(file);
+        if !source.is_file() {
+            continue;
+        }
+        let target = place.place(file)?;
+        if target.exists() && !force {
+            return Err(t!("state-exists", path = target.display()).into());
+        }
+        files.push((source, target));
+    }
+    // Checked for all files first, so a refused import changes nothing
+    let mut config = None;
+    for (source, target) in files {
+        fs::copy(&source, &target)?;
+        if target.ends_with("config.toml") {
+            config = Some(target);
+        }
+    }
+    Ok(config)
+}
+
+fn staging_dir() -> std::io::Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("imgmc-state-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
@@ -0,0 +1,160 @@
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+
+use crate::azure::ImageData;
+use crate::provider;
+use crate::serve::{self, Response};
+use crate::sheet;
+use crate::size::{AspectRatio, Size};
+
+/// Uploads for edits carry a whole image.
+const MAX_BODY_LEN: usize = 64 * 1024 * 1024;
+
+/// Answers like the Azure OpenAI endpoints imgmc talks to, with placeholder
+/// images, so the whole CLI can be tested end to end without an account. It
+/// also imitates the OpenAI images API and Stability's stable-image API.
+///
+/// A prompt starting with `stub:<status>`, e.g. `stub:429`, gets that error
+/// status instead, with an error body like the imitated API's.
+pub fn run(addr: SocketAddr) -> Result<(), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind(addr)?;
+    // With port 0 the address is only known now, and tests read it from here
+    eprintln!("Listening on http://{}", listener.local_addr()?);
+
+    for stream in listener.incoming().flatten() {
+        thread::spawn(move || {
+            if let Err(e) = handle(stream) {
+                eprintln!("Request failed: {e}");
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle(mut stream: TcpStream) -> Result<(), Box<dyn std::error::Error>> {
+    let request = serve::read_request(&stream, MAX_BODY_LEN)?;
+    let path = request.path.split('?').next().unwrap_or_default();
+    let body = request.body.unwrap_or_default();
+
+    // Azure and OpenAI share the paths below `/images`
+    let response = if path.ends_with("/images/generations") {
+        match serde_json::from_slice::<serde_json::Value>(&body) {
+            Ok(body) => images(&body),
+            Err(e) => Response::error(400, e),
+        }
+    } else if path.ends_with("/images/edits") {
+        // Multipart bodies are not worth parsing for a placeholder
+        let body = serde_json::json!({ "prompt": multipart_field(&body, "prompt") });
+        images(&body)
+    } else if path.ends_with("/chat/completions") {
+        let content = "A placeholder image from the stub server";
+        Response::json(
+            200,
+            serde_json::json!({ "choices": [{ "message": { "content": content } }] }),
+        )
+    } else if path.ends_with("/openai/deployments") {
+        Response::json(
+            200,
+            serde_json::json!({ "data": [{ "id": "gpt-image-1", "model": "gpt-image-1" }] }),
+        )
+    } else if path == "/v1/models" {
+        Response::json(
+            200,
+            serde_json::json!({
+                "object": "list",
+                "data": [{ "id": "gpt-image-1", "object": "model", "owned_by": "system" }]
+            }),
+        )
+    } else if path.starts_with("/v2beta/stable-image/generate/") {
+        stability(&body)
+    } else {
+        Response::error(404, "Not found")
+    };
+    response.write_to(&mut stream)?;
+    Ok(())
+}
+
+/// A generation response with `n` placeholder images of the requested size.
+fn images(body: &serde_json::Value) -> Response {
+    let prompt = body["prompt"].as_str().unwrap_or_default();
+    if let Some(status) = stubbed_status(prompt) {
+        let code = if status == 400 {
+            "content_policy_violation"
+        } else {
+            "stub"
+        };
+        return Response::json(
+            status,
+            serde_json::json!({ "error": { "code": code, "message": "Stubbed error" } }),
+        );
+    }
+
+    let size = body["size"]
+        .as_str()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(Size::new(1024, 1024));
+    let n = body["n"].as_u64().unwrap_or(1);
+    let data: Result<Vec<String>, _> = (0..n)
+        .map(|_| sheet::placeholder(size, [200, 200, 200], prompt))
+        .map(|png| png.map(|png| ImageData::from_png(&png).b64_json))
+        .collect();
+    match data {
+        Ok(data) => {
+            let data: Vec<_> = data
+                .into_iter()
+                .map(|b64_json| serde_json::json!({ "b64_json": b64_json }))
+                .collect();
+            Response::json(200, serde_json::json!({ "created": 0, "data": data }))
+        }
+        Err(e) => Response::error(500, e),
+    }
+}
+
+/// A Stability response with one placeholder image in the requested aspect
+/// ratio, as Stability answers with `Accept: application/json`.
+fn stability(body: &[u8]) -> Response {
+    let prompt = multipart_field(body, "prompt");
+    if let Some(status) = stubbed_status(&prompt) {
+        return Response::json(
+            status,
+            serde_json::json!({ "id": "stub", "name": "stub", "errors": ["Stubbed error"] }),
+        );
+    }
+
+    let ratio = multipart_field(body, "aspect_ratio")
+        .parse()
+        .unwrap_or(AspectRatio {
+            width: 1,
+            height: 1,
+        });
+    match sheet::placeholder(provider::megapixel_size(ratio), [200, 200, 200], &prompt) {
+        Ok(png) => Response::json(
+            200,
+            serde_json::json!({
+                "image": ImageData::from_png(&png).b64_json,
+                "finish_reason": "SUCCESS",
+                "seed": 0
+            }),
+        ),
+        Err(e) => Response::error(500, e),
+    }
+}
+
+/// The error status a `stub:<status>` prompt asks for.
+fn stubbed_status(prompt: &str) -> Option<u16> {
+    prompt
+        .strip_prefix("stub:")
+        .and_then(|rest| rest.get(..3))
+        .and_then(|status| status.parse().ok())
+}
+
+/// The value of a text field in a multipart/form-data body.
+fn multipart_field(body: &[u8], name: &str) -> String {
+    let body = String::from_utf8_lossy(body);
+    let marker = format!("name=\"{name}\"");
+    body.split_once(&marker)
+        .and_then(|(_, rest)| rest.split_once("\r\n\r\n"))
+        .and_then(|(_, rest)| rest.split("\r\n").next())
+        .unwrap_or_default()
+        .to_string()
+}
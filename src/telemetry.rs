@@ -0,0 +1,67 @@
+/// Flushes pending spans when dropped at the end of `main`.
+pub struct Guard {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+/// Exports the spans recorded for API calls, decoding and saving over OTLP
+/// when `OTEL_EXPORTER_OTLP_ENDPOINT` or `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`
+/// is set. Otherwise spans go nowhere and cost next to nothing.
+#[cfg(feature = "otel")]
+pub fn init() -> Guard {
+    use opentelemetry::trace::TracerProvider as _;
+    use tracing_subscriber::layer::SubscriberExt as _;
+    use tracing_subscriber::util::SubscriberInitExt as _;
+
+    let configured = [
+        "OTEL_EXPORTER_OTLP_ENDPOINT",
+        "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+    ]
+    .iter()
+    .any(|var| std::env::var_os(var).is_some());
+    if !configured {
+        return Guard { provider: None };
+    }
+
+    // The endpoint and headers come from the standard OTEL_* variables
+    let exporter = match opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .build()
+    {
+        Ok(exporter) => exporter,
+        Err(e) => {
+            eprintln!("Could not set up trace export: {e}");
+            return Guard { provider: None };
+        }
+    };
+    let resource = opentelemetry_sdk::Resource::builder()
+        .with_service_name("imgmc")
+        .build();
+    let provider = opentelemetry_sdk::trace::SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource)
+        .build();
+
+    let layer = tracing_opentelemetry::layer().with_tracer(provider.tracer("imgmc"));
+    tracing_subscriber::registry().with(layer).init();
+    Guard {
+        provider: Some(provider),
+    }
+}
+
+/// Without the `otel` feature spans are never exported.
+#[cfg(not(feature = "otel"))]
+pub fn init() -> Guard {
+    Guard {}
+}
+
+#[cfg(feature = "otel")]
+impl Drop for Guard {
+    fn drop(&mut self) {
+        if let Some(provider) = &self.provider
+            && let Err(e) = provider.shutdown()
+        {
+            eprintln!("Could not export traces: {e}");
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::fs;
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+const RELEASES_URL: &str = "https://api.github.com/repos/hkrutzer/imgmc/releases/latest";
+const MAX_DOWNLOAD: u64 = 200 * 1024 * 1024;
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// Release binaries are published as `imgmc-<arch>-<os>`, next to a
+/// `SHA256SUMS` file listing their checksums.
+fn asset_name() -> String {
+    let suffix = if cfg!(windows) { ".exe" } else { "" };
+    format!(
+        "imgmc-{}-{}{suffix}",
+        std::env::consts::ARCH,
+        std::env::consts::OS
+    )
+}
+
+fn parse_version(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches('v')
+        .split('.')
+        .map(|part| part.parse().unwrap_or(0))
+        .collect()
+}
+
+fn download(url: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    Ok(ureq::get(url)
+        .call()?
+        .body_mut()
+        .with_config()
+        .limit(MAX_DOWNLOAD)
+        .read_to_vec()?)
+}
+
+/// Replaces the running executable with the latest GitHub release.
+pub fn run(check_only: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let current = env!("CARGO_PKG_VERSION");
+    let release: Release = ureq::get(RELEASES_URL)
+        .header("Accept", "application/vnd.github+json")
+        .call()?
+        .body_mut()
+        .read_json()?;
+
+    if parse_version(&release.tag_name) <= parse_version(current) {
+        println!("imgmc {current} is up to date");
+        return Ok(());
+    }
+    if check_only {
+        println!(
+            "imgmc {} is available (installed: {current})",
+            release.tag_name
+        );
+        return Ok(());
+    }
+
+    let name = asset_name();
+    let find = |wanted: &str| {
+        release
+            .assets
+            .iter()
+            .find(|asset| asset.name == wanted)
+            .ok_or_else(|| format!("Release {} has no {wanted}", release.tag_name))
+    };
+    let binary = find(&name)?;
+    let sums = find("SHA256SUMS")?;
+
+    let sums = String::from_utf8(download(&sums.browser_download_url)?)?;
+    let expected = sums
+        .lines()
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .find(|(_, file)| file.trim().trim_start_matches('*') == name)
+        .map(|(hash, _)| hash.to_lowercase())
+        .ok_or_else(|| format!("SHA256SUMS has no entry for {name}"))?;
+
+    eprintln!("Downloading {} {name}", release.tag_name);
+    let bytes = download(&binary.browser_download_url)?;
+    let actual: String = Sha256::digest(&bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    if actual != expected {
+        return Err(
+            format!("Checksum mismatch for {name}: expected {expected}, got {actual}").into(),
+        );
+    }
+
+    let exe = std::env::current_exe()?;
+    let staged = exe.with_extension("new");
+    fs::write(&staged, &bytes)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))?;
+    }
+    // Windows cannot overwrite a running executable, but it can rename it
+    #[cfg(windows)]
+    fs::rename(&exe, exe.with_extension("old"))?;
+    fs::rename(&staged, &exe)?;
+
+    println!("Updated imgmc {current} to {}", release.tag_name);
+    Ok(())
+}
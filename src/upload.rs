@@ -0,0 +1,178 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+/// Object storage location that saved images are copied to.
+///
+/// Uploads go through the provider's own CLI (`aws`, `gcloud`, `az`, `scp`) so
+/// that whatever credentials the user already has configured are picked up.
+#[derive(Clone)]
+pub enum Destination {
+    S3 {
+        bucket: String,
+        prefix: String,
+    },
+    Gcs {
+        bucket: String,
+        prefix: String,
+    },
+    AzureBlob {
+        account: String,
+        container: String,
+        prefix: String,
+    },
+    Sftp {
+        /// `user@host`, optionally followed by `:port`.
+        authority: String,
+        /// Absolute remote directory, empty for the login directory.
+        path: String,
+        identity: Option<PathBuf>,
+    },
+}
+
+impl FromStr for Destination {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = s.split_once("://").ok_or_else(|| {
+            format!("Upload destination must look like s3://bucket/prefix, got {s}")
+        })?;
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(format!("Upload destination is missing a bucket: {s}"));
+        }
+        let prefix = prefix.trim_matches('/').to_string();
+
+        match scheme {
+            "s3" => Ok(Destination::S3 {
+                bucket: bucket.to_string(),
+                prefix,
+            }),
+            "gs" => Ok(Destination::Gcs {
+                bucket: bucket.to_string(),
+                prefix,
+            }),
+            "az" => {
+                let (container, prefix) = prefix.split_once('/').unwrap_or((prefix.as_str(), ""));
+                if container.is_empty() {
+                    return Err(format!("Expected az://account/container/prefix, got {s}"));
+                }
+                Ok(Destination::AzureBlob {
+                    account: bucket.to_string(),
+                    container: container.to_string(),
+                    prefix: prefix.to_string(),
+                })
+            }
+            "sftp" | "scp" => Ok(Destination::Sftp {
+                authority: bucket.to_string(),
+                path: prefix,
+                identity: None,
+            }),
+            _ => Err(format!(
+                "Unsupported upload scheme {scheme}://, expected s3://, gs://, az:// or sftp://"
+            )),
+        }
+    }
+}
+
+fn object_key(prefix: &str, file: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let name = file
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| format!("Cannot upload {}: invalid file name", file.display()))?;
+    if prefix.is_empty() {
+        Ok(name.to_string())
+    } else {
+        Ok(format!("{prefix}/{name}"))
+    }
+}
+
+fn run(program: &str, cmd: &mut Command) -> Result<(), Box<dyn std::error::Error>> {
+    let status = cmd
+        .stdout(Stdio::null())
+        .status()
+        .map_err(|e| format!("Could not run {program}: {e}"))?;
+    if !status.success() {
+        return Err(format!("{program} exited with {status}").into());
+    }
+    Ok(())
+}
+
+impl Destination {
+    /// Uploads `file` and returns the URL it can be reached at.
+    pub fn upload(&self, file: &Path) -> Result<String, Box<dyn std::error::Error>> {
+        match self {
+            Destination::S3 { bucket, prefix } => {
+                let key = object_key(prefix, file)?;
+                run(
+                    "aws",
+                    Command::new("aws")
+                        .args(["s3", "cp", "--only-show-errors"])
+                        .arg(file)
+                        .arg(format!("s3://{bucket}/{key}")),
+                )?;
+                Ok(format!("https://{bucket}.s3.amazonaws.com/{key}"))
+            }
+            Destination::Gcs { bucket, prefix } => {
+                let key = object_key(prefix, file)?;
+                run(
+                    "gcloud",
+                    Command::new("gcloud")
+                        .args(["storage", "cp", "--quiet"])
+                        .arg(file)
+                        .arg(format!("gs://{bucket}/{key}")),
+                )?;
+                Ok(format!("https://storage.googleapis.com/{bucket}/{key}"))
+            }
+            Destination::AzureBlob {
+                account,
+                container,
+                prefix,
+            } => {
+                let key = object_key(prefix, file)?;
+                run(
+                    "az",
+                    Command::new("az")
+                        .args(["storage", "blob", "upload", "--only-show-errors"])
+                        .args(["--auth-mode", "login", "--overwrite"])
+                        .args(["--account-name", account.as_str()])
+                        .args(["--container-name", container.as_str()])
+                        .args(["--name", key.as_str()])
+                        .arg("--file")
+                        .arg(file),
+                )?;
+                Ok(format!(
+                    "https://{account}.blob.core.windows.net/{container}/{key}"
+                ))
+            }
+            Destination::Sftp {
+                authority,
+                path,
+                identity,
+            } => {
+                let key = object_key(path, file)?;
+                let (host, port) = match authority.rsplit_once(':') {
+                    Some((host, port)) => (host, Some(port)),
+                    None => (authority.as_str(), None),
+                };
+                let remote = if path.is_empty() {
+                    format!("{host}:{key}")
+                } else {
+                    format!("{host}:/{key}")
+                };
+
+                let mut cmd = Command::new("scp");
+                // Never prompt for a password in the middle of a run
+                cmd.arg("-B").arg("-q");
+                if let Some(port) = port {
+                    cmd.args(["-P", port]);
+                }
+                if let Some(identity) = identity {
+                    cmd.arg("-i").arg(identity);
+                }
+                run("scp", cmd.arg(file).arg(remote))?;
+                Ok(format!("sftp://{authority}/{key}"))
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use serde_json::Value;
+
+/// The folder Obsidian puts new attachments in, from the vault's
+/// `.obsidian/app.json`. Vaults without one, or set to store attachments next
+/// to the current note, get the vault root since there is no note here.
+pub fn attachment_dir(vault: &Path) -> Result<PathBuf, String> {
+    if !vault.is_dir() {
+        return Err(format!("Vault not found at: {}", vault.display()));
+    }
+
+    let setting = fs::read_to_string(vault.join(".obsidian").join("app.json"))
+        .ok()
+        .and_then(|json| serde_json::from_str::<Value>(&json).ok())
+        .and_then(|app| app["attachmentFolderPath"].as_str().map(str::to_string))
+        .unwrap_or_default();
+
+    let dir = match setting.trim_start_matches('/') {
+        folder if folder.is_empty() || folder.starts_with('.') => vault.to_path_buf(),
+        folder => vault.join(folder),
+    };
+    fs::create_dir_all(&dir).map_err(|e| format!("Cannot create {}: {e}", dir.display()))?;
+    Ok(dir)
+}
+
+/// Puts `text` on the system clipboard with whichever clipboard tool exists.
+pub fn copy_to_clipboard(text: &str) -> std::io::Result<()> {
+    let tools: &[(&str, &[&str])] = if cfg!(target_os = "macos") {
+        &[("pbcopy", &[])]
+    } else if cfg!(windows) {
+        &[("clip", &[])]
+    } else {
+        &[
+            ("wl-copy", &[]),
+            ("xclip", &["-selection", "clipboard"]),
+            ("xsel", &["-ib"]),
+        ]
+    };
+
+    let mut last_error = std::io::Error::other("No clipboard tool available");
+    for (program, args) in tools {
+        let child = Command::new(program)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) => {
+                last_error = e;
+                continue;
+            }
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+        let status = child.wait()?;
+        if status.success() {
+            return Ok(());
+        }
+        last_error = std::io::Error::other(format!("{program} failed with {status}"));
+    }
+    Err(last_error)
+}
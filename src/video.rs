@@ -0,0 +1,61 @@
+use std::fs;
+use std::process::{Command, Stdio};
+
+use clap::ValueEnum;
+
+use crate::size::Size;
+
+pub struct VideoRequest {
+    pub prompt: String,
+    pub seconds: u8,
+    pub resolution: Size,
+}
+
+/// Containers videos can be saved in. Providers deliver MP4.
+#[derive(ValueEnum, Clone, Copy, PartialEq)]
+pub enum Container {
+    Mp4,
+    /// VP9 re-encoded with ffmpeg, which has to be installed.
+    Webm,
+}
+
+impl Container {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Container::Mp4 => "mp4",
+            Container::Webm => "webm",
+        }
+    }
+
+    /// Turns an MP4 from a provider into this container.
+    pub fn convert(self, mp4: Vec<u8>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        match self {
+            Container::Mp4 => Ok(mp4),
+            Container::Webm => webm(&mp4),
+        }
+    }
+}
+
+fn webm(mp4: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let base = std::env::temp_dir().join(format!("imgmc-video-{}", std::process::id()));
+    let input = base.with_extension("mp4");
+    let output = base.with_extension("webm");
+    fs::write(&input, mp4)?;
+
+    // Constant quality instead of a bitrate, as clip lengths vary a lot
+    let result = Command::new("ffmpeg")
+        .args(["-loglevel", "error", "-y", "-i"])
+        .arg(&input)
+        .args(["-c:v", "libvpx-vp9", "-crf", "32", "-b:v", "0"])
+        .arg(&output)
+        .stdout(Stdio::null())
+        .status();
+    let _ = fs::remove_file(&input);
+    let outcome: Result<Vec<u8>, Box<dyn std::error::Error>> = match result {
+        Ok(status) if status.success() => fs::read(&output).map_err(Into::into),
+        Ok(status) => Err(format!("ffmpeg failed with {status}").into()),
+        Err(e) => Err(format!("Could not run ffmpeg: {e}").into()),
+    };
+    let _ = fs::remove_file(&output);
+    outcome
+}
@@ -0,0 +1,105 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::size::Size;
+
+/// The resolution of the primary screen, as reported by the platform's tools.
+pub fn screen_size() -> Option<Size> {
+    if cfg!(target_os = "macos") {
+        // "Resolution: 2560 x 1600 Retina"
+        let out = output("system_profiler", &["SPDisplaysDataType"])?;
+        let line = out
+            .lines()
+            .find_map(|l| l.trim().strip_prefix("Resolution:"))?;
+        parse_dimensions(line)
+    } else if cfg!(windows) {
+        let script = "$v = Get-CimInstance Win32_VideoController | Select-Object -First 1; \
+                      \"$($v.CurrentHorizontalResolution) x $($v.CurrentVerticalResolution)\"";
+        parse_dimensions(&output("powershell", &["-NoProfile", "-Command", script])?)
+    } else {
+        // "Screen 0: minimum 320 x 200, current 2560 x 1440, maximum ..."
+        let out = output("xrandr", &["--current"])?;
+        let current = out.split("current").nth(1)?;
+        parse_dimensions(current.split(',').next()?)
+    }
+}
+
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    let out = Command::new(program)
+        .args(args)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    out.status
+        .success()
+        .then(|| String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+/// Reads the first `W x H` pair from `text`.
+fn parse_dimensions(text: &str) -> Option<Size> {
+    let mut numbers = text
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|s| !s.is_empty())
+        .map(str::parse::<u32>);
+    let width = numbers.next()?.ok()?;
+    let height = numbers.next()?.ok()?;
+    (width > 0 && height > 0).then(|| Size::new(width, height))
+}
+
+/// Where wallpapers are kept unless the config says otherwise.
+pub fn default_dir() -> std::io::Result<PathBuf> {
+    xdg::BaseDirectories::with_prefix("imgmc").create_data_directory("wallpapers")
+}
+
+/// Makes `image` the desktop background on GNOME, KDE, macOS or Windows.
+pub fn set(image: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let image = image.canonicalize()?;
+    let path = image.to_string_lossy();
+
+    let commands: Vec<(&str, Vec<String>)> = if cfg!(target_os = "macos") {
+        let script = format!(
+            "tell application \"System Events\" to tell every desktop to set picture to \"{}\"",
+            path.replace('\\', "\\\\").replace('"', "\\\"")
+        );
+        vec![("osascript", vec!["-e".into(), script])]
+    } else if cfg!(windows) {
+        let script = format!(
+            "Add-Type -TypeDefinition 'using System.Runtime.InteropServices; \
+             public class W {{ [DllImport(\"user32.dll\", CharSet = CharSet.Unicode)] \
+             public static extern int SystemParametersInfo(int a, int b, string c, int d); }}'; \
+             [void][W]::SystemParametersInfo(20, 0, '{}', 3)",
+            path.replace('\'', "''")
+        );
+        vec![(
+            "powershell",
+            vec!["-NoProfile".into(), "-Command".into(), script],
+        )]
+    } else {
+        let desktop = std::env::var("XDG_CURRENT_DESKTOP").unwrap_or_default();
+        if desktop.to_uppercase().contains("KDE") {
+            vec![("plasma-apply-wallpaperimage", vec![path.to_string()])]
+        } else {
+            // GNOME keeps a separate picture for its dark style
+            let uri = format!("file://{path}");
+            ["picture-uri", "picture-uri-dark"]
+                .into_iter()
+                .map(|key| {
+                    let args = ["set", "org.gnome.desktop.background", key, &uri];
+                    ("gsettings", args.map(String::from).to_vec())
+                })
+                .collect()
+        }
+    };
+
+    for (program, args) in commands {
+        let status = Command::new(program)
+            .args(&args)
+            .stdout(Stdio::null())
+            .status()
+            .map_err(|e| format!("Could not run {program}: {e}"))?;
+        if !status.success() {
+            return Err(format!("{program} failed with {status}").into());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,82 @@
+use std::path::Path;
+
+use wasmtime::{Config, Engine, Instance, Module, Store, StoreLimits, StoreLimitsBuilder, Trap};
+
+use crate::png;
+
+/// Most memory a plugin may grow to, enough for a few copies of a large image.
+const MAX_MEMORY: usize = 512 * 1024 * 1024;
+
+/// Instructions a plugin may run per image, roughly a thousand per pixel of a
+/// 4K image, so a plugin stuck in a loop fails instead of hanging imgmc.
+const FUEL: u64 = 10_000_000_000;
+
+/// A post-processing step compiled to WebAssembly.
+///
+/// The module gets no imports, so it cannot touch files, the network or the
+/// environment; it only sees the bytes handed to it. Memory and the number of
+/// instructions run are capped. It must export:
+///
+/// - `memory`
+/// - `alloc(len: i32) -> i32`, returning a buffer of `len` bytes
+/// - `transform(image: i32, image_len: i32, params: i32, params_len: i32) -> i64`,
+///   taking a PNG and its parameters as JSON and returning the pointer to the
+///   resulting PNG in the upper and its length in the lower 32 bits, or 0 on
+///   failure
+pub struct Plugin {
+    engine: Engine,
+    module: Module,
+}
+
+impl Plugin {
+    pub fn load(path: &Path) -> Result<Plugin, Box<dyn std::error::Error>> {
+        let engine = Engine::new(Config::new().consume_fuel(true))?;
+        let module = Module::from_file(&engine, path)
+            .map_err(|e| format!("Could not load {}: {e}", path.display()))?;
+        Ok(Plugin { engine, module })
+    }
+
+    /// Runs the plugin on one image in a fresh instance, so no state carries
+    /// over from earlier images.
+    pub fn transform(
+        &self,
+        image: &[u8],
+        params: &serde_json::Value,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let limits = StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build();
+        let mut store: Store<StoreLimits> = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(FUEL)?;
+        let instance = Instance::new(&mut store, &self.module, &[])?;
+
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or("The WASM plugin exports no memory")?;
+        let alloc = instance.get_typed_func::<u32, u32>(&mut store, "alloc")?;
+        let transform =
+            instance.get_typed_func::<(u32, u32, u32, u32), u64>(&mut store, "transform")?;
+
+        let mut pass = |bytes: &[u8]| -> Result<(u32, u32), Box<dyn std::error::Error>> {
+            let len = u32::try_from(bytes.len()).map_err(|_| "Image too large for WASM")?;
+            let ptr = alloc.call(&mut store, len)?;
+            memory.write(&mut store, ptr as usize, bytes)?;
+            Ok((ptr, len))
+        };
+        let (image_ptr, image_len) = pass(image)?;
+        let (params_ptr, params_len) = pass(params.to_string().as_bytes())?;
+
+        let result = transform
+            .call(&mut store, (image_ptr, image_len, params_ptr, params_len))
+            .map_err(|e| match e.downcast_ref::<Trap>() {
+                Some(Trap::OutOfFuel) => "The WASM plugin ran too long and was stopped".into(),
+                _ => e.to_string(),
+            })?;
+        if result == 0 {
+            return Err("The WASM plugin could not transform the image".into());
+        }
+        let mut out = vec![0; (result & 0xffff_ffff) as usize];
+        memory.read(&store, (result >> 32) as usize, &mut out)?;
+        png::verify(out.as_slice()).map_err(|e| format!("Invalid image from WASM plugin: {e}"))?;
+        Ok(out)
+    }
+}
@@ -0,0 +1,82 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use std::{fs, thread};
+
+use crate::azure::Client;
+use crate::i18n::t;
+use crate::output::Saver;
+use crate::redact;
+use crate::request::ImageRequest;
+use crate::spinner;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Generates from the contents of `path` and again every time they change.
+///
+/// Writes are debounced so an editor saving in several steps only triggers one
+/// generation, and saves that leave the prompt unchanged are ignored.
+pub fn run(
+    path: &Path,
+    client: &Client,
+    mut req: ImageRequest,
+    mut saver: Saver,
+) -> Result<(), Box<dyn std::error::Error>> {
+    saver.dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    let mut last_modified = None;
+    let mut last_prompt = String::new();
+    eprintln!("{}", t!("watching", path = path.display()));
+
+    loop {
+        let current = modified(path);
+        if current.is_some() && current != last_modified {
+            // Wait for the file to settle before reading it
+            thread::sleep(DEBOUNCE);
+            if modified(path) != current {
+                continue;
+            }
+            last_modified = current;
+
+            // Editors that save by renaming can briefly leave the file missing
+            let Ok(contents) = fs::read_to_string(path) else {
+                continue;
+            };
+            let prompt = contents.trim();
+            if prompt.is_empty() || prompt == last_prompt {
+                continue;
+            }
+            last_prompt = prompt.to_string();
+
+            req.set_prompt(&last_prompt);
+            match client.validate(&mut req) {
+                Ok(()) => regenerate(client, &req, &saver),
+                Err(e) => eprintln!("{e}"),
+            }
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Generates and saves one request, reporting failures instead of returning them.
+pub fn regenerate(client: &Client, req: &ImageRequest, saver: &Saver) {
+    let sp = spinner::Spinner::start(t!("calling-api"));
+    let saved = saver.generate(client, req, &sp);
+    drop(sp);
+
+    match saved {
+        Ok(files) => {
+            for filename in files {
+                println!("{}", t!("image-saved", path = filename.display()));
+            }
+        }
+        Err(e) => {
+            let e = redact::redact(&e.to_string());
+            eprintln!("{}", t!("generation-failed-with", error = e));
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use std::path::Path;
+
+use serde::Deserialize;
+use ureq::unversioned::multipart::Form;
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Slack,
+    Discord,
+}
+
+/// A chat channel results are posted to, configured under `[webhooks.<name>]`.
+#[derive(Deserialize, Clone)]
+pub struct Webhook {
+    pub url: String,
+    pub kind: Kind,
+}
+
+impl Webhook {
+    /// Posts the prompt together with the image.
+    ///
+    /// Discord receives the file as an attachment. Slack incoming webhooks
+    /// cannot take files, so they only get a link when the image was uploaded.
+    pub fn post(
+        &self,
+        file: &Path,
+        prompt: &str,
+        uploaded_url: Option<&str>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match self.kind {
+            Kind::Discord => {
+                let payload = serde_json::json!({ "content": prompt }).to_string();
+                let form = Form::new()
+                    .text("payload_json", &payload)
+                    .file("files[0]", file)?;
+                ureq::post(&self.url).send(form)?;
+            }
+            Kind::Slack => {
+                let text = match uploaded_url {
+                    Some(url) => format!("{prompt}\n{url}"),
+                    None => format!("{prompt}\n(saved as {})", file.display()),
+                };
+                ureq::post(&self.url).send_json(serde_json::json!({ "text": text }))?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,313 @@
+//! End-to-end tests running the imgmc binary against its stub server.
+//! Run with `cargo test --features stub-server`.
+#![cfg(feature = "stub-server")]
+
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::process::{Child, ChildStderr, Command, Output, Stdio};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64_STD;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const BOUNDARY: &str = "imgmc-test-boundary";
+
+/// A running `imgmc stub`, killed when dropped.
+struct Stub {
+    child: Child,
+    url: String,
+    /// Kept open so later messages from the stub don't hit a closed pipe.
+    _stderr: BufReader<ChildStderr>,
+}
+
+impl Stub {
+    fn start() -> Stub {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_imgmc"))
+            .args(["stub", "--http", "127.0.0.1:0"])
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("stub server starts");
+        let mut line = String::new();
+        let mut stderr = BufReader::new(child.stderr.take().expect("stderr is piped"));
+        stderr
+            .read_line(&mut line)
+            .expect("stub prints its address");
+        let url = line
+            .trim()
+            .strip_prefix("Listening on ")
+            .expect("stub prints its address")
+            .to_string();
+        Stub {
+            child,
+            url,
+            _stderr: stderr,
+        }
+    }
+}
+
+impl Stub {
+    /// Sends a request straight to the stub, for the APIs imgmc has no client
+    /// for, and returns the status and JSON body of the response.
+    fn send(&self, method: &str, path: &str, body: &Body) -> (u16, serde_json::Value) {
+        let addr = self.url.strip_prefix("http://").expect("stub serves HTTP");
+        let (content_type, body) = match body {
+            Body::Empty => ("text/plain".to_string(), Vec::new()),
+            Body::Json(value) => (
+                "application/json".to_string(),
+                value.to_string().into_bytes(),
+            ),
+            Body::Form(fields) => {
+                let mut form = String::new();
+                for (name, value) in fields {
+                    form += &format!("--{BOUNDARY}\r\n");
+                    form += &format!("Content-Disposition: form-data; name=\"{name}\"\r\n\r\n");
+                    form += &format!("{value}\r\n");
+                }
+                form += &format!("--{BOUNDARY}--\r\n");
+                (
+                    format!("multipart/form-data; boundary={BOUNDARY}"),
+                    form.into_bytes(),
+                )
+            }
+        };
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "{method} {path} HTTP/1.1\r\nHost: {addr}\r\nAuthorization: Bearer test-key\r\n\
+             Content-Type: {content_type}\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        )
+        .unwrap();
+        stream.write_all(&body).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        let (head, body) = response.split_once("\r\n\r\n").expect("complete response");
+        let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+}
+
+enum Body<'a> {
+    Empty,
+    Json(serde_json::Value),
+    Form(&'a [(&'a str, &'a str)]),
+}
+
+/// The width and height of a base64 PNG, read from its header.
+fn png_size(b64: &serde_json::Value) -> (u32, u32) {
+    let png = BASE64_STD.decode(b64.as_str().unwrap()).unwrap();
+    assert!(png.starts_with(PNG_SIGNATURE));
+    let number = |at: usize| u32::from_be_bytes(png[at..at + 4].try_into().unwrap());
+    (number(16), number(20))
+}
+
+impl Drop for Stub {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// A scratch directory with a config pointing at the stub, used as working
+/// and XDG directory so tests never touch the real ones.
+struct Env {
+    dir: PathBuf,
+    _stub: Stub,
+}
+
+impl Env {
+    fn new(name: &str) -> Env {
+        let stub = Stub::start();
+        let dir = std::env::temp_dir().join(format!("imgmc-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("config/imgmc")).unwrap();
+        fs::create_dir_all(dir.join("out")).unwrap();
+        let config = format!(
+            "[azure]\napi_base = \"{}\"\napi_key = \"test-key\"\ndeployment = \"gpt-image-1\"\n",
+            stub.url
+        );
+        fs::write(dir.join("config/imgmc/config.toml"), config).unwrap();
+        Env { dir, _stub: stub }
+    }
+
+    fn imgmc(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_imgmc"))
+            .args(args)
+            .current_dir(self.dir.join("out"))
+            .env("XDG_CONFIG_HOME", self.dir.join("config"))
+            .env("XDG_DATA_HOME", self.dir.join("data"))
+            .env("LANG", "C")
+            .stdin(Stdio::null())
+            .output()
+            .unwrap()
+    }
+
+    fn saved_pngs(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = fs::read_dir(self.dir.join("out"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|e| e == "png"))
+            .filter(|path| !path.ends_with("latest.png"))
+            .collect();
+        files.sort();
+        files
+    }
+}
+
+impl Drop for Env {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn generates_and_saves_images() {
+    let env = Env::new("generate");
+    let output = env.imgmc(&["-p", "azure", "--count", "2", "a red fox"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let files = env.saved_pngs();
+    assert_eq!(files.len(), 2);
+    for file in files {
+        assert!(fs::read(file).unwrap().starts_with(PNG_SIGNATURE));
+    }
+}
+
+#[test]
+fn latest_link_follows_the_saved_format() {
+    let env = Env::new("latest");
+    let out = env.dir.join("out");
+    let png = env.imgmc(&["-p", "azure", "--count", "1", "a red fox"]);
+    assert!(
+        png.status.success(),
+        "{}",
+        String::from_utf8_lossy(&png.stderr)
+    );
+    assert!(out.join("latest.png").exists());
+
+    let avif = env.imgmc(&[
+        "-p",
+        "azure",
+        "--count",
+        "1",
+        "--format",
+        "avif",
+        "a red fox",
+    ]);
+    assert!(
+        avif.status.success(),
+        "{}",
+        String::from_utf8_lossy(&avif.stderr)
+    );
+    assert!(out.join("latest.avif").exists());
+    assert!(!out.join("latest.png").exists());
+
+    let last = env.imgmc(&["last", "--path"]);
+    assert!(
+        String::from_utf8_lossy(&last.stdout)
+            .trim_end()
+            .ends_with(".avif")
+    );
+}
+
+#[test]
+fn api_errors_map_to_exit_codes() {
+    let env = Env::new("errors");
+    let rate_limited = env.imgmc(&["-p", "azure", "stub:429 a red fox"]);
+    assert_eq!(rate_limited.status.code(), Some(4));
+    let refused = env.imgmc(&["-p", "azure", "stub:400 a red fox"]);
+    assert_eq!(refused.status.code(), Some(5));
+    assert!(env.saved_pngs().is_empty());
+}
+
+#[test]
+fn lists_deployments() {
+    let env = Env::new("models");
+    let output = env.imgmc(&["models"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("gpt-image-1"));
+}
+
+#[test]
+fn replays_recorded_session() {
+    let env = Env::new("replay");
+    let cassette = env.dir.join("session.json");
+    let cassette = cassette.to_str().unwrap();
+    let recorded = env.imgmc(&["-p", "azure", "--record", cassette, "a red fox"]);
+    assert!(
+        recorded.status.success(),
+        "{}",
+        String::from_utf8_lossy(&recorded.stderr)
+    );
+
+    fs::remove_file(env.dir.join("config/imgmc/config.toml")).unwrap();
+    let replayed = env.imgmc(&["-p", "azure", "--replay", cassette, "a red fox"]);
+    assert!(
+        replayed.status.success(),
+        "{}",
+        String::from_utf8_lossy(&replayed.stderr)
+    );
+    assert_eq!(env.saved_pngs().len(), 2);
+}
+
+#[test]
+fn imitates_openai_images_api() {
+    let stub = Stub::start();
+    let request = serde_json::json!({
+        "model": "gpt-image-1",
+        "prompt": "a red fox",
+        "n": 2,
+        "size": "1536x1024"
+    });
+    let (status, body) = stub.send("POST", "/v1/images/generations", &Body::Json(request));
+    assert_eq!(status, 200);
+    let data = body["data"].as_array().unwrap();
+    assert_eq!(data.len(), 2);
+    for image in data {
+        assert_eq!(png_size(&image["b64_json"]), (1536, 1024));
+    }
+
+    let (status, body) = stub.send("GET", "/v1/models", &Body::Empty);
+    assert_eq!(status, 200);
+    assert_eq!(body["data"][0]["id"], "gpt-image-1");
+}
+
+#[test]
+fn imitates_openai_errors() {
+    let stub = Stub::start();
+    let request = serde_json::json!({ "model": "gpt-image-1", "prompt": "stub:429 a red fox" });
+    let (status, body) = stub.send("POST", "/v1/images/generations", &Body::Json(request));
+    assert_eq!(status, 429);
+    assert_eq!(body["error"]["code"], "stub");
+}
+
+#[test]
+fn imitates_stability_api() {
+    let stub = Stub::start();
+    let fields = [
+        ("prompt", "a red fox"),
+        ("aspect_ratio", "16:9"),
+        ("output_format", "png"),
+    ];
+    let path = "/v2beta/stable-image/generate/core";
+    let (status, body) = stub.send("POST", path, &Body::Form(&fields));
+    assert_eq!(status, 200);
+    assert_eq!(body["finish_reason"], "SUCCESS");
+    assert_eq!(png_size(&body["image"]), (1344, 768));
+
+    let fields = [("prompt", "stub:400 a red fox")];
+    let (status, body) = stub.send("POST", path, &Body::Form(&fields));
+    assert_eq!(status, 400);
+    assert_eq!(body["errors"][0], "Stubbed error");
+}